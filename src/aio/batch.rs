@@ -0,0 +1,216 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures::stream::{self, StreamExt};
+
+use super::{Gateway, Provider};
+use crate::common::batch::{self, BatchReport, MappingOperation, OperationOutcome};
+use crate::common::manager::MappingSpec;
+use crate::errors::Error;
+
+impl<P: Provider> Gateway<P> {
+    /// Run a batch of add and remove operations, with at most `max_concurrency` of them at
+    /// once (see [`DEFAULT_BATCH_CONCURRENCY`](crate::DEFAULT_BATCH_CONCURRENCY)).
+    ///
+    /// Operations on the same protocol and external port run in turn, in batch order, so that
+    /// a mapping can be removed and added again in one batch.
+    ///
+    /// If an add fails, the operations not started yet are skipped and the mappings added by
+    /// the batch are removed again. Removed mappings are not restored. A failed remove does
+    /// not stop the batch.
+    pub async fn run_batch(&self, operations: &[MappingOperation], max_concurrency: usize) -> BatchReport {
+        let max_concurrency = max_concurrency.max(1);
+        let add_failed = AtomicBool::new(false);
+        let chained: Vec<Vec<(usize, OperationOutcome)>> = stream::iter(batch::chains(operations))
+            .map(|chain| {
+                let add_failed = &add_failed;
+                async move {
+                    let mut outcomes = Vec::new();
+                    for index in chain {
+                        let operation = &operations[index];
+                        if add_failed.load(Ordering::SeqCst) {
+                            outcomes.push((index, OperationOutcome::Skipped));
+                            continue;
+                        }
+                        let outcome = match self.run_operation(operation).await {
+                            Ok(()) => OperationOutcome::Applied,
+                            Err(e) => OperationOutcome::Failed(e),
+                        };
+                        if batch::is_failed_add(operation, &outcome) {
+                            add_failed.store(true, Ordering::SeqCst);
+                        }
+                        outcomes.push((index, outcome));
+                    }
+                    outcomes
+                }
+            })
+            .buffer_unordered(max_concurrency)
+            .collect()
+            .await;
+        let mut outcomes: Vec<_> = operations.iter().map(|_| OperationOutcome::Skipped).collect();
+        for (index, outcome) in chained.into_iter().flatten() {
+            outcomes[index] = outcome;
+        }
+        let mut report = BatchReport { outcomes };
+
+        if report.add_failed(operations) {
+            let rollbacks: Vec<_> = stream::iter(report.applied_adds(operations))
+                .map(|(index, mapping)| async move {
+                    let outcome = match self.remove_port(mapping.protocol, mapping.external_port).await {
+                        Ok(()) => OperationOutcome::RolledBack,
+                        Err(e) => OperationOutcome::RollbackFailed(e.into()),
+                    };
+                    (index, outcome)
+                })
+                .buffer_unordered(max_concurrency)
+                .collect()
+                .await;
+            for (index, outcome) in rollbacks {
+                report.outcomes[index] = outcome;
+            }
+        }
+        report
+    }
+
+    async fn run_operation(&self, operation: &MappingOperation) -> Result<(), Error> {
+        match operation {
+            MappingOperation::Add(MappingSpec {
+                protocol,
+                external_port,
+                local_addr,
+                lease,
+                description,
+            }) => {
+                self.add_port_with_lease(*protocol, *external_port, *local_addr, *lease, description)
+                    .await?;
+                Ok(())
+            }
+            MappingOperation::Remove {
+                protocol,
+                external_port,
+            } => Ok(self.remove_port(*protocol, *external_port).await?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::aio::tokio::Tokio;
+    use crate::common::gena;
+    use crate::PortMappingProtocol;
+
+    const SERVICE_TYPE: &str = "urn:schemas-upnp-org:service:WANIPConnection:1";
+
+    // Answer control requests with success, logging their actions once answered. Deletes
+    // are answered late, so that a request sent meanwhile is logged first.
+    async fn stand_in(actions: Arc<Mutex<Vec<String>>>) -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(answer(stream, actions.clone()));
+            }
+        });
+        addr
+    }
+
+    async fn answer(mut stream: TcpStream, actions: Arc<Mutex<Vec<String>>>) {
+        let mut request = Vec::new();
+        let end = loop {
+            if let Some(end) = gena::head_end(&request) {
+                break end;
+            }
+            let mut buf = [0; 1024];
+            let read = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..read]);
+        };
+        let head = gena::parse_request_head(&request[..end]).unwrap();
+        while request.len() < end + head.content_length().unwrap() {
+            let mut buf = [0; 1024];
+            let read = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..read]);
+        }
+        let action = head.header("SOAPAction").unwrap().trim_matches('"');
+        let action = action.rsplit('#').next().unwrap().to_string();
+        if action == "DeletePortMapping" {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        let body = format!(
+            r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body><u:{action}Response xmlns:u="{SERVICE_TYPE}"></u:{action}Response></s:Body>
+</s:Envelope>"#
+        );
+        actions.lock().unwrap().push(action);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+
+    fn gateway(addr: SocketAddr) -> Gateway<Tokio> {
+        let schema = |arguments: &[&str]| arguments.iter().map(|argument| argument.to_string()).collect();
+        Gateway {
+            addr,
+            root_url: "/root.xml".to_string(),
+            control_url: "/ctl".to_string(),
+            control_schema_url: "/scpd.xml".to_string(),
+            control_schema: HashMap::from([
+                (
+                    "AddPortMapping".to_string(),
+                    schema(&[
+                        "NewRemoteHost",
+                        "NewExternalPort",
+                        "NewProtocol",
+                        "NewInternalPort",
+                        "NewInternalClient",
+                        "NewEnabled",
+                        "NewPortMappingDescription",
+                        "NewLeaseDuration",
+                    ]),
+                ),
+                (
+                    "DeletePortMapping".to_string(),
+                    schema(&["NewRemoteHost", "NewExternalPort", "NewProtocol"]),
+                ),
+            ]),
+            service_type: SERVICE_TYPE.to_string(),
+            services: Vec::new(),
+            bind_addr: None,
+            provider: Tokio,
+        }
+    }
+
+    #[tokio::test]
+    async fn operations_on_the_same_port_run_in_order() {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let gateway = gateway(stand_in(actions.clone()).await);
+        let operations = [
+            MappingOperation::Remove {
+                protocol: PortMappingProtocol::TCP,
+                external_port: 80,
+            },
+            MappingOperation::Add(MappingSpec {
+                protocol: PortMappingProtocol::TCP,
+                external_port: 80,
+                local_addr: "127.0.0.1:8080".parse().unwrap(),
+                lease: None,
+                description: "test".to_string(),
+            }),
+        ];
+
+        let report = gateway.run_batch(&operations, 4).await;
+        assert!(report.is_success(), "{:?}", report.outcomes);
+        assert_eq!(*actions.lock().unwrap(), ["DeletePortMapping", "AddPortMapping"]);
+    }
+}
@@ -0,0 +1,127 @@
+use std::net::{IpAddr, SocketAddr};
+
+use super::{Gateway, Provider};
+use crate::common::firewall::{self, FirewallStatus, PinholeId, PinholeProtocol, WAN_IPV6_FIREWALL_CONTROL_SERVICE};
+use crate::common::parsing::RequestReponse;
+use crate::errors::{PinholeError, RequestError};
+
+impl<P: Provider> Gateway<P> {
+    async fn perform_firewall_request(&self, action: &str, body: &str) -> Result<RequestReponse, RequestError> {
+        let service = self.service(WAN_IPV6_FIREWALL_CONTROL_SERVICE, action)?;
+        self.perform_service_request(
+            &service.control_url,
+            &service.service_type,
+            action,
+            body,
+            &format!("{action}Response"),
+        )
+        .await
+    }
+
+    /// Get the state of the gateway's IPv6 firewall.
+    pub async fn get_firewall_status(&self) -> Result<FirewallStatus, PinholeError> {
+        let result = self
+            .perform_firewall_request(
+                firewall::GET_FIREWALL_STATUS_ACTION,
+                &firewall::format_get_firewall_status_message(),
+            )
+            .await;
+        firewall::parse_get_firewall_status_response(result)
+    }
+
+    /// Get the timeout in seconds of outbound pinholes matching the given traffic.
+    ///
+    /// A `remote_host` of `None` and a `remote_port` of 0 are wildcards.
+    pub async fn get_outbound_pinhole_timeout(
+        &self,
+        remote_host: Option<IpAddr>,
+        remote_port: u16,
+        internal_addr: SocketAddr,
+        protocol: PinholeProtocol,
+    ) -> Result<u32, PinholeError> {
+        let result = self
+            .perform_firewall_request(
+                firewall::GET_OUTBOUND_PINHOLE_TIMEOUT_ACTION,
+                &firewall::format_get_outbound_pinhole_timeout_message(
+                    remote_host,
+                    remote_port,
+                    internal_addr,
+                    protocol,
+                ),
+            )
+            .await;
+        firewall::parse_get_outbound_pinhole_timeout_response(result)
+    }
+
+    /// Add an inbound pinhole to the IPv6 firewall.
+    ///
+    /// The internal_addr is the IPv6 address and port where the traffic is sent to.
+    /// A `remote_host` of `None` and a `remote_port` of 0 are wildcards.
+    /// The lease_time parameter is in seconds, between 1 and 86400.
+    ///
+    /// # Returns
+    ///
+    /// The id of the pinhole on success. Otherwise an error.
+    pub async fn add_pinhole(
+        &self,
+        remote_host: Option<IpAddr>,
+        remote_port: u16,
+        internal_addr: SocketAddr,
+        protocol: PinholeProtocol,
+        lease_time: u32,
+    ) -> Result<PinholeId, PinholeError> {
+        let result = self
+            .perform_firewall_request(
+                firewall::ADD_PINHOLE_ACTION,
+                &firewall::format_add_pinhole_message(remote_host, remote_port, internal_addr, protocol, lease_time),
+            )
+            .await;
+        firewall::parse_add_pinhole_response(result)
+    }
+
+    /// Extend the lease of a pinhole.
+    ///
+    /// The lease_time parameter is in seconds, between 1 and 86400.
+    pub async fn update_pinhole(&self, id: PinholeId, lease_time: u32) -> Result<(), PinholeError> {
+        self.perform_firewall_request(
+            firewall::UPDATE_PINHOLE_ACTION,
+            &firewall::format_update_pinhole_message(id, lease_time),
+        )
+        .await
+        .map_err(firewall::convert_pinhole_error)?;
+        Ok(())
+    }
+
+    /// Remove a pinhole.
+    pub async fn delete_pinhole(&self, id: PinholeId) -> Result<(), PinholeError> {
+        self.perform_firewall_request(
+            firewall::DELETE_PINHOLE_ACTION,
+            &firewall::format_pinhole_id_message(firewall::DELETE_PINHOLE_ACTION, id),
+        )
+        .await
+        .map_err(firewall::convert_pinhole_error)?;
+        Ok(())
+    }
+
+    /// Get the number of packets that went through a pinhole.
+    pub async fn get_pinhole_packets(&self, id: PinholeId) -> Result<u32, PinholeError> {
+        let result = self
+            .perform_firewall_request(
+                firewall::GET_PINHOLE_PACKETS_ACTION,
+                &firewall::format_pinhole_id_message(firewall::GET_PINHOLE_PACKETS_ACTION, id),
+            )
+            .await;
+        firewall::parse_get_pinhole_packets_response(result)
+    }
+
+    /// Check whether traffic has been received through a pinhole.
+    pub async fn check_pinhole_working(&self, id: PinholeId) -> Result<bool, PinholeError> {
+        let result = self
+            .perform_firewall_request(
+                firewall::CHECK_PINHOLE_WORKING_ACTION,
+                &firewall::format_pinhole_id_message(firewall::CHECK_PINHOLE_WORKING_ACTION, id),
+            )
+            .await;
+        firewall::parse_check_pinhole_working_response(result)
+    }
+}
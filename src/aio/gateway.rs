@@ -2,11 +2,19 @@ use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use log::debug;
 
 use super::Provider;
-use crate::errors::{self, AddAnyPortError, AddPortError, GetExternalIpError, RemovePortError, RequestError};
+use crate::errors::{
+    self, AddAnyPortError, AddPortError, AddPortRangeError, DefaultConnectionServiceError, GetExternalIpError,
+    RemovePortError, RequestError,
+};
 
-use crate::common::{self, messages, parsing, parsing::RequestReponse};
+use crate::common::{
+    self, lease, messages, parsing, parsing::OutArguments, parsing::RequestReponse, parsing::ServiceDescription, range,
+};
 use crate::PortMappingProtocol;
 
 /// This structure represents a gateway found by the search functions.
@@ -25,18 +33,44 @@ pub struct Gateway<P> {
     /// Service type of the gateway's WAN connection service (e.g.
     /// `urn:schemas-upnp-org:service:WANIPConnection:1`)
     pub service_type: String,
+    /// All services advertised in the device description
+    pub services: Vec<ServiceDescription>,
+    /// Local address control connections are bound to, as some gateways only accept
+    /// mappings requested from the internal client. Set from the search's bind address, or
+    /// else the address routing to the gateway; `None` lets the OS pick the address.
+    pub bind_addr: Option<IpAddr>,
     /// Executor provider
     pub provider: P,
 }
 
 impl<P: Provider> Gateway<P> {
     async fn perform_request(&self, action: &str, body: &str, ok: &str) -> Result<RequestReponse, RequestError> {
-        let url = format!("{self}");
-        let header = messages::soap_action(&self.service_type, action);
-        let text = P::send_async(&url, &header, body).await?;
+        self.perform_service_request(&self.control_url, &self.service_type, action, body, ok)
+            .await
+    }
+
+    pub(crate) async fn perform_service_request(
+        &self,
+        control_url: &str,
+        service_type: &str,
+        action: &str,
+        body: &str,
+        ok: &str,
+    ) -> Result<RequestReponse, RequestError> {
+        let url = format!("http://{}{}", self.addr, control_url);
+        let header = messages::soap_action(service_type, action);
+        let text = P::send_async_from(&url, &header, body, self.bind_addr).await?;
         parsing::parse_response(text, ok)
     }
 
+    /// Find the advertised service of the given type, or fail with `UnsupportedAction(action)`.
+    pub(crate) fn service(&self, service_type: &str, action: &str) -> Result<&ServiceDescription, RequestError> {
+        self.services
+            .iter()
+            .find(|service| service.service_type == service_type)
+            .ok_or_else(|| RequestError::UnsupportedAction(action.to_string()))
+    }
+
     /// Get the external IP address of the gateway in a tokio compatible way
     pub async fn get_external_ip(&self) -> Result<IpAddr, GetExternalIpError> {
         let result = self
@@ -53,7 +87,9 @@ impl<P: Provider> Gateway<P> {
     /// function that calls `get_external_ip` followed by `add_any_port`
     ///
     /// The local_addr is the address where the traffic is sent to.
-    /// The lease_duration parameter is in seconds. A value of 0 is infinite.
+    /// The lease_duration parameter is in seconds. A value of 0 is infinite, except on IGD v2
+    /// (`WANIPConnection:2`) gateways, which allow no infinite lease: there 0 is sent as one
+    /// week (604800 seconds), the longest lease they accept, and longer leases are capped to it.
     ///
     /// # Returns
     ///
@@ -76,7 +112,9 @@ impl<P: Provider> Gateway<P> {
     /// Add a port mapping.with any external port.
     ///
     /// The local_addr is the address where the traffic is sent to.
-    /// The lease_duration parameter is in seconds. A value of 0 is infinite.
+    /// The lease_duration parameter is in seconds. A value of 0 is infinite, except on IGD v2
+    /// (`WANIPConnection:2`) gateways, which allow no infinite lease: there 0 is sent as one
+    /// week (604800 seconds), the longest lease they accept, and longer leases are capped to it.
     ///
     /// # Returns
     ///
@@ -91,8 +129,8 @@ impl<P: Provider> Gateway<P> {
         // This function first attempts to call AddAnyPortMapping on the IGD with a random port
         // number. If that fails due to the method being unknown it attempts to call AddPortMapping
         // instead with a random port number. If that fails due to ConflictInMappingEntry it retrys
-        // with another port up to a maximum of 20 times. If it fails due to SamePortValuesRequired
-        // it retrys once with the same port values.
+        // with another port up to a maximum of 20 times, then fails with NoPortsAvailable. If it
+        // fails due to SamePortValuesRequired it retrys once with the same port values.
 
         if local_addr.port() == 0 {
             return Err(AddAnyPortError::InternalPortZeroInvalid);
@@ -141,7 +179,7 @@ impl<P: Provider> Gateway<P> {
                 .await
             {
                 Ok(port) => return Ok(port),
-                Err(AddAnyPortError::NoPortsAvailable) => continue,
+                Err(e) if parsing::is_random_port_conflict(&e) => continue,
                 e => return e,
             }
         }
@@ -219,7 +257,9 @@ impl<P: Provider> Gateway<P> {
     /// Add a port mapping.
     ///
     /// The local_addr is the address where the traffic is sent to.
-    /// The lease_duration parameter is in seconds. A value of 0 is infinite.
+    /// The lease_duration parameter is in seconds. A value of 0 is infinite, except on IGD v2
+    /// (`WANIPConnection:2`) gateways, which allow no infinite lease: there 0 is sent as one
+    /// week (604800 seconds), the longest lease they accept, and longer leases are capped to it.
     pub async fn add_port(
         &self,
         protocol: PortMappingProtocol,
@@ -244,6 +284,185 @@ impl<P: Provider> Gateway<P> {
         Ok(())
     }
 
+    /// Add a port mapping with a lease of the given duration, `None` being infinite.
+    ///
+    /// The lease is adjusted to the version of the WAN connection service: IGD v2 gateways
+    /// cap leases at one week and do not grant infinite leases.
+    ///
+    /// # Returns
+    ///
+    /// The lease granted by the gateway on success, `None` being infinite. Otherwise an error.
+    pub async fn add_port_with_lease(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<Option<Duration>, AddPortError> {
+        let lease_duration =
+            lease::lease_duration(&self.service_type, lease).map_err(AddPortError::InvalidLeaseDuration)?;
+        self.add_port(protocol, external_port, local_addr, lease_duration, description)
+            .await?;
+        Ok(lease::granted_lease(&self.service_type, lease_duration))
+    }
+
+    /// Add a port mapping with any external port and a lease of the given duration, `None`
+    /// being infinite. The lease is adjusted as for [`add_port_with_lease`](Self::add_port_with_lease).
+    ///
+    /// # Returns
+    ///
+    /// The external port that was mapped and the lease granted by the gateway on success.
+    /// Otherwise an error.
+    pub async fn add_any_port_with_lease(
+        &self,
+        protocol: PortMappingProtocol,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<(u16, Option<Duration>), AddAnyPortError> {
+        let lease_duration =
+            lease::lease_duration(&self.service_type, lease).map_err(AddAnyPortError::InvalidLeaseDuration)?;
+        let external_port = self
+            .add_any_port(protocol, local_addr, lease_duration, description)
+            .await?;
+        Ok((external_port, lease::granted_lease(&self.service_type, lease_duration)))
+    }
+
+    /// Add a port mapping, or refresh it if the external port is already mapped to `local_addr`.
+    ///
+    /// Gateways refuse to add a mapping of an external port that is in use, even by the same
+    /// client, for example after the process restarted. The existing mapping is then looked
+    /// up: if it points at `local_addr`, it is removed and added again with the new lease
+    /// and description. Should adding it again fail, the old mapping is restored with its
+    /// remaining lease, so that the port is not left to another host. Otherwise the error
+    /// is `AddPortError::PortInUseBy`, describing it.
+    pub async fn add_or_refresh_port(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease_duration: u32,
+        description: &str,
+    ) -> Result<(), AddPortError> {
+        match self
+            .add_port(protocol, external_port, local_addr, lease_duration, description)
+            .await
+        {
+            Err(AddPortError::PortInUse) => {}
+            result => return result,
+        }
+        let entry = match self.get_specific_port_mapping_entry(protocol, external_port).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("could not look up the {protocol} mapping of external port {external_port}: {e}");
+                return Err(AddPortError::PortInUse);
+            }
+        };
+        if entry.internal_addr() != Some(local_addr) {
+            return Err(AddPortError::PortInUseBy(entry));
+        }
+        self.remove_port(protocol, external_port)
+            .await
+            .map_err(parsing::convert_refresh_remove_error)?;
+        let result = self
+            .add_port(protocol, external_port, local_addr, lease_duration, description)
+            .await;
+        if result.is_err() {
+            let restored = self
+                .add_port(
+                    protocol,
+                    external_port,
+                    local_addr,
+                    entry.lease_duration,
+                    &entry.port_mapping_description,
+                )
+                .await;
+            if let Err(e) = restored {
+                debug!("could not restore the {protocol} mapping of external port {external_port}: {e}");
+            }
+        }
+        result
+    }
+
+    /// The address of this host on the interface that routes to the gateway, with the given
+    /// port. This is the address the gateway expects as internal client.
+    pub fn local_addr_for_port(&self, local_port: u16) -> Result<SocketAddr, RequestError> {
+        Ok(SocketAddr::new(common::local_ip_for(self.addr)?, local_port))
+    }
+
+    /// Add a port mapping to a local port of this host, on the interface that routes to the
+    /// gateway. See [`add_port`](Self::add_port).
+    ///
+    /// # Returns
+    ///
+    /// The local address that was mapped on success. Otherwise an error.
+    pub async fn add_local_port(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_port: u16,
+        lease_duration: u32,
+        description: &str,
+    ) -> Result<SocketAddr, AddPortError> {
+        let local_addr = self
+            .local_addr_for_port(local_port)
+            .map_err(AddPortError::RequestError)?;
+        self.add_port(protocol, external_port, local_addr, lease_duration, description)
+            .await?;
+        Ok(local_addr)
+    }
+
+    /// Add a port mapping with any external port to a local port of this host, on the
+    /// interface that routes to the gateway. See [`add_any_port`](Self::add_any_port).
+    ///
+    /// # Returns
+    ///
+    /// The external port that was mapped on success. Otherwise an error.
+    pub async fn add_any_local_port(
+        &self,
+        protocol: PortMappingProtocol,
+        local_port: u16,
+        lease_duration: u32,
+        description: &str,
+    ) -> Result<u16, AddAnyPortError> {
+        let local_addr = self.local_addr_for_port(local_port)?;
+        self.add_any_port(protocol, local_addr, lease_duration, description)
+            .await
+    }
+
+    /// Add port mappings for a range of `count` consecutive ports, starting at external port
+    /// `external_start` and local address `local_addr_start`.
+    ///
+    /// Either every port of the range is mapped, or none is: when a port can not be mapped,
+    /// the ports mapped before it are removed again.
+    /// The lease is adjusted as for [`add_port_with_lease`](Self::add_port_with_lease).
+    pub async fn add_port_range(
+        &self,
+        protocol: PortMappingProtocol,
+        external_start: u16,
+        local_addr_start: SocketAddr,
+        count: u16,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<(), AddPortRangeError> {
+        let ports = range::port_range(external_start, local_addr_start, count)?;
+        for (index, &(external_port, local_addr)) in ports.iter().enumerate() {
+            if let Err(error) = self
+                .add_port_with_lease(protocol, external_port, local_addr, lease, description)
+                .await
+            {
+                for &(added_port, _) in &ports[..index] {
+                    if let Err(e) = self.remove_port(protocol, added_port).await {
+                        debug!("could not roll back the {protocol} mapping of external port {added_port}: {e}");
+                    }
+                }
+                return Err(range::range_error(external_port, error));
+            }
+        }
+        Ok(())
+    }
+
     /// Remove a port mapping.
     pub async fn remove_port(&self, protocol: PortMappingProtocol, external_port: u16) -> Result<(), RemovePortError> {
         let res = self
@@ -281,6 +500,119 @@ impl<P: Provider> Gateway<P> {
             .await;
         parsing::parse_get_generic_port_mapping_entry(result)
     }
+
+    /// Get the port mapping of an external port.
+    pub async fn get_specific_port_mapping_entry(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+    ) -> Result<parsing::PortMappingEntry, errors::GetSpecificPortMappingEntryError> {
+        let result = self
+            .perform_request(
+                messages::GET_SPECIFIC_PORT_MAPPING_ENTRY_ACTION,
+                &messages::format_get_specific_port_mapping_entry_message(&self.service_type, protocol, external_port),
+                "GetSpecificPortMappingEntryResponse",
+            )
+            .await;
+        parsing::parse_get_specific_port_mapping_entry(result, protocol, external_port)
+    }
+
+    /// Invoke any action of an advertised service.
+    ///
+    /// The service can be given by its service type or its service id. The in-arguments are
+    /// sent in the order of the control schema when invoking an action of the WAN connection
+    /// service, and in the given order otherwise.
+    ///
+    /// # Returns
+    ///
+    /// The out-arguments of the action on success. Otherwise an error.
+    pub async fn invoke(
+        &self,
+        service: &str,
+        action: &str,
+        args: &[(&str, &str)],
+    ) -> Result<OutArguments, RequestError> {
+        let args = args
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect::<Vec<_>>();
+        let (control_url, service_type) = if service == self.service_type {
+            (self.control_url.as_str(), self.service_type.as_str())
+        } else {
+            let description = self
+                .services
+                .iter()
+                .find(|description| description.service_type == service || description.service_id == service)
+                .ok_or_else(|| RequestError::UnsupportedAction(format!("{service}#{action}")))?;
+            (description.control_url.as_str(), description.service_type.as_str())
+        };
+        let schema = if control_url == self.control_url {
+            self.control_schema.get(action).map(Vec::as_slice)
+        } else {
+            None
+        };
+        let body = messages::format_action_message(service_type, action, &messages::order_arguments(schema, &args));
+        parsing::parse_out_arguments(
+            self.perform_service_request(control_url, service_type, action, &body, &format!("{action}Response"))
+                .await,
+        )
+    }
+
+    /// Get the default connection service of the root device's Layer3Forwarding service.
+    ///
+    /// The value has the form `uuid:<device-uuid>:WANConnectionDevice:1,<service-id>`.
+    pub async fn get_default_connection_service(&self) -> Result<String, DefaultConnectionServiceError> {
+        let service = self.service(
+            parsing::LAYER3_FORWARDING_SERVICE,
+            messages::GET_DEFAULT_CONNECTION_SERVICE_ACTION,
+        )?;
+        let result = self
+            .perform_service_request(
+                &service.control_url,
+                &service.service_type,
+                messages::GET_DEFAULT_CONNECTION_SERVICE_ACTION,
+                &messages::format_get_default_connection_service_message(&service.service_type),
+                "GetDefaultConnectionServiceResponse",
+            )
+            .await;
+        parsing::parse_get_default_connection_service_response(result)
+    }
+
+    /// Set the default connection service of the root device's Layer3Forwarding service.
+    ///
+    /// The value has the form `uuid:<device-uuid>:WANConnectionDevice:1,<service-id>`.
+    pub async fn set_default_connection_service(
+        &self,
+        default_connection_service: &str,
+    ) -> Result<(), DefaultConnectionServiceError> {
+        let service = self.service(
+            parsing::LAYER3_FORWARDING_SERVICE,
+            messages::SET_DEFAULT_CONNECTION_SERVICE_ACTION,
+        )?;
+        self.perform_service_request(
+            &service.control_url,
+            &service.service_type,
+            messages::SET_DEFAULT_CONNECTION_SERVICE_ACTION,
+            &messages::format_set_default_connection_service_message(&service.service_type, default_connection_service),
+            "SetDefaultConnectionServiceResponse",
+        )
+        .await
+        .map_err(parsing::convert_default_connection_service_error)?;
+        Ok(())
+    }
+
+    /// Find the WAN connection service the gateway advertises as its default connection service.
+    ///
+    /// Returns `None` if the referenced service is not in `services`.
+    pub async fn find_default_connection_service(
+        &self,
+    ) -> Result<Option<&ServiceDescription>, DefaultConnectionServiceError> {
+        let default_connection_service = self.get_default_connection_service().await?;
+        Ok(parsing::find_default_connection_service(
+            &self.services,
+            &default_connection_service,
+        ))
+    }
 }
 
 impl<P> fmt::Display for Gateway<P> {
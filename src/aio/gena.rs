@@ -0,0 +1,383 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::Stream;
+use http_body_util::Empty;
+use hyper::{Method, Request, Response};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use log::{debug, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use super::{Gateway, Provider};
+use crate::common;
+use crate::common::gena::{self, EventNotification, RequestHead};
+use crate::common::options::{DEFAULT_REQUEST_TIMEOUT, MAX_RESPONSE_BYTES};
+use crate::common::parsing::ServiceDescription;
+use crate::errors::{RequestError, SubscriptionError};
+
+/// How long to wait for a gateway to send a `NOTIFY` request once it connected.
+const NOTIFY_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before retrying a renewal that failed.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Receives the events gateways send to subscriptions, on a tokio task.
+///
+/// The listener must be reachable by the gateway, so it should be bound to the unspecified
+/// address or to the address of the interface the gateway is reachable on. It is also a
+/// [`Stream`] of the received events.
+pub struct EventListener {
+    local_addr: SocketAddr,
+    events: mpsc::UnboundedReceiver<EventNotification>,
+    task: JoinHandle<()>,
+}
+
+impl EventListener {
+    /// Listen for events on the given address. Use port 0 to let the OS pick a port.
+    pub async fn bind(addr: SocketAddr) -> io::Result<EventListener> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let (sender, events) = mpsc::unbounded_channel();
+        let task = tokio::spawn(accept_notifications(listener, sender));
+        Ok(EventListener {
+            local_addr,
+            events,
+            task,
+        })
+    }
+
+    /// The address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Wait for the next event.
+    pub async fn recv(&mut self) -> Option<EventNotification> {
+        self.events.recv().await
+    }
+
+    /// The address the gateway at `gateway_addr` should send events to.
+    fn callback_addr(&self, gateway_addr: SocketAddr) -> io::Result<SocketAddr> {
+        if self.local_addr.ip().is_unspecified() {
+            Ok(SocketAddr::new(
+                common::local_ip_for(gateway_addr)?,
+                self.local_addr.port(),
+            ))
+        } else {
+            Ok(self.local_addr)
+        }
+    }
+}
+
+impl Stream for EventListener {
+    type Item = EventNotification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<EventNotification>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+impl Drop for EventListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept_notifications(listener: TcpListener, sender: mpsc::UnboundedSender<EventNotification>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                debug!("error while accepting an event connection: {e}");
+                continue;
+            }
+        };
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            if let Some(notification) = handle_notification(stream).await {
+                let _ = sender.send(notification);
+            }
+        });
+    }
+}
+
+async fn handle_notification(mut stream: TcpStream) -> Option<EventNotification> {
+    let result = match timeout(NOTIFY_READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(request) => request.and_then(|(head, body)| gena::parse_notification(&head, &body)),
+        Err(_) => Err(400),
+    };
+    let reply = match &result {
+        Ok(_) => gena::NOTIFY_OK.to_string(),
+        Err(status) => {
+            debug!("rejecting event request with status {status}");
+            gena::notify_error(*status)
+        }
+    };
+    if let Err(e) = stream.write_all(reply.as_bytes()).await {
+        debug!("could not reply to an event request: {e}");
+    }
+    result.ok()
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<(RequestHead, Vec<u8>), u16> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let end = loop {
+        if let Some(end) = gena::head_end(&buf) {
+            break end;
+        }
+        if buf.len() > gena::MAX_HEAD_BYTES {
+            return Err(413);
+        }
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return Err(400),
+            Ok(read) => buf.extend_from_slice(&chunk[..read]),
+        }
+    };
+    let head = gena::parse_request_head(&buf[..end]).ok_or(400u16)?;
+    let length = head.content_length().ok_or(400u16)?;
+    if length > MAX_RESPONSE_BYTES {
+        return Err(413);
+    }
+    let mut body = buf.split_off(end);
+    if body.len() < length {
+        let mut rest = vec![0u8; length - body.len()];
+        stream.read_exact(&mut rest).await.map_err(|_| 400u16)?;
+        body.extend_from_slice(&rest);
+    }
+    body.truncate(length);
+    Ok((head, body))
+}
+
+/// A subscription to the events of a service, which is renewed on a tokio task until it is
+/// dropped or unsubscribed.
+pub struct Subscription {
+    url: String,
+    bind_addr: Option<IpAddr>,
+    sid: Arc<Mutex<String>>,
+    control: Option<oneshot::Sender<bool>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Subscription {
+    /// The subscription id, which matches `EventNotification::sid` of the events it receives.
+    ///
+    /// It changes if the gateway forgot the subscription and it had to be made again.
+    pub fn sid(&self) -> String {
+        self.sid.lock().unwrap().clone()
+    }
+
+    /// Cancel the subscription.
+    ///
+    /// Dropping the subscription also cancels it from the renewal task, but ignores any error.
+    pub async fn unsubscribe(mut self) -> Result<(), SubscriptionError> {
+        if let Some(control) = self.control.take() {
+            let _ = control.send(false);
+        }
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+        send_unsubscribe(&self.url, self.bind_addr, &self.sid()).await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(control) = self.control.take() {
+            let _ = control.send(true);
+        }
+    }
+}
+
+impl<P: Provider> Gateway<P> {
+    /// Subscribe to the events of the WAN connection service, such as changes of
+    /// `ExternalIPAddress`, `ConnectionStatus` and `PortMappingNumberOfEntries`.
+    ///
+    /// The events are delivered to the listener. The gateway may grant a shorter duration than
+    /// the requested timeout; the subscription is renewed before it expires either way.
+    /// Requests are sent from [`Gateway::bind_addr`], like control requests.
+    pub async fn subscribe(
+        &self,
+        listener: &EventListener,
+        timeout: Duration,
+    ) -> Result<Subscription, SubscriptionError> {
+        let service = self
+            .services
+            .iter()
+            .find(|service| service.control_url == self.control_url)
+            .ok_or(SubscriptionError::EventsNotSupported)?;
+        self.subscribe_service(service, listener, timeout).await
+    }
+
+    /// Subscribe to the events of any advertised service.
+    pub async fn subscribe_service(
+        &self,
+        service: &ServiceDescription,
+        listener: &EventListener,
+        timeout: Duration,
+    ) -> Result<Subscription, SubscriptionError> {
+        if service.event_sub_url.is_empty() {
+            return Err(SubscriptionError::EventsNotSupported);
+        }
+        let url = gena::event_url(&self.addr, &service.event_sub_url);
+        let callback = gena::callback(&listener.callback_addr(self.addr)?);
+        let bind_addr = self.bind_addr;
+        let (sid, granted) = send_subscribe(&url, bind_addr, &callback, timeout).await?;
+        debug!("subscribed to {url} as {sid} for {granted:?}");
+
+        let sid = Arc::new(Mutex::new(sid));
+        let (control, control_receiver) = oneshot::channel();
+        let task = tokio::spawn(renew_subscription(
+            url.clone(),
+            bind_addr,
+            callback,
+            sid.clone(),
+            timeout,
+            granted,
+            control_receiver,
+        ));
+        Ok(Subscription {
+            url,
+            bind_addr,
+            sid,
+            control: Some(control),
+            task: Some(task),
+        })
+    }
+}
+
+async fn renew_subscription(
+    url: String,
+    bind_addr: Option<IpAddr>,
+    callback: String,
+    sid: Arc<Mutex<String>>,
+    timeout_duration: Duration,
+    granted: Duration,
+    mut control: oneshot::Receiver<bool>,
+) {
+    let mut interval = gena::renew_interval(granted);
+    loop {
+        match timeout(interval, &mut control).await {
+            Err(_) => {}
+            Ok(Ok(unsubscribe)) => {
+                if unsubscribe {
+                    let current = sid.lock().unwrap().clone();
+                    let _ = send_unsubscribe(&url, bind_addr, &current).await;
+                }
+                return;
+            }
+            Ok(Err(_)) => {
+                let current = sid.lock().unwrap().clone();
+                let _ = send_unsubscribe(&url, bind_addr, &current).await;
+                return;
+            }
+        }
+
+        let current = sid.lock().unwrap().clone();
+        let renewed = match send_renew(&url, bind_addr, &current, timeout_duration).await {
+            Err(SubscriptionError::PreconditionFailed) => {
+                debug!("subscription {current} expired, subscribing again to {url}");
+                send_subscribe(&url, bind_addr, &callback, timeout_duration).await
+            }
+            result => result,
+        };
+        interval = match renewed {
+            Ok((new_sid, granted)) => {
+                *sid.lock().unwrap() = new_sid;
+                gena::renew_interval(granted)
+            }
+            Err(e) => {
+                warn!("could not renew subscription to {url}: {e}");
+                RETRY_INTERVAL
+            }
+        };
+    }
+}
+
+async fn send(
+    request: Request<Empty<Bytes>>,
+    bind_addr: Option<IpAddr>,
+) -> Result<Response<hyper::body::Incoming>, SubscriptionError> {
+    let mut connector = HttpConnector::new();
+    connector.set_local_address(bind_addr);
+    let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(connector);
+    let response = timeout(DEFAULT_REQUEST_TIMEOUT, client.request(request))
+        .await
+        .map_err(RequestError::from)?
+        .map_err(RequestError::from)?;
+    Ok(response)
+}
+
+fn method(name: &str) -> Method {
+    Method::from_bytes(name.as_bytes()).expect("valid HTTP method")
+}
+
+fn header<'a, T>(response: &'a Response<T>, name: &str) -> Option<&'a str> {
+    response.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+async fn send_subscribe(
+    url: &str,
+    bind_addr: Option<IpAddr>,
+    callback: &str,
+    timeout: Duration,
+) -> Result<(String, Duration), SubscriptionError> {
+    let request = Request::builder()
+        .method(method("SUBSCRIBE"))
+        .uri(url)
+        .header("CALLBACK", callback)
+        .header("NT", "upnp:event")
+        .header("TIMEOUT", gena::format_timeout(timeout))
+        .body(Empty::new())
+        .map_err(RequestError::from)?;
+    let response = send(request, bind_addr).await?;
+    gena::parse_subscribe_response(
+        response.status().as_u16(),
+        header(&response, "SID"),
+        header(&response, "TIMEOUT"),
+        timeout,
+    )
+}
+
+async fn send_renew(
+    url: &str,
+    bind_addr: Option<IpAddr>,
+    sid: &str,
+    timeout: Duration,
+) -> Result<(String, Duration), SubscriptionError> {
+    let request = Request::builder()
+        .method(method("SUBSCRIBE"))
+        .uri(url)
+        .header("SID", sid)
+        .header("TIMEOUT", gena::format_timeout(timeout))
+        .body(Empty::new())
+        .map_err(RequestError::from)?;
+    let response = send(request, bind_addr).await?;
+    gena::parse_subscribe_response(
+        response.status().as_u16(),
+        header(&response, "SID").or(Some(sid)),
+        header(&response, "TIMEOUT"),
+        timeout,
+    )
+}
+
+async fn send_unsubscribe(url: &str, bind_addr: Option<IpAddr>, sid: &str) -> Result<(), SubscriptionError> {
+    let request = Request::builder()
+        .method(method("UNSUBSCRIBE"))
+        .uri(url)
+        .header("SID", sid)
+        .body(Empty::new())
+        .map_err(RequestError::from)?;
+    let response = send(request, bind_addr).await?;
+    gena::parse_unsubscribe_response(response.status().as_u16())
+}
@@ -0,0 +1,77 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use super::{Gateway, Provider};
+use crate::common::lan_host::{self, AddressRange, LAN_HOST_CONFIG_MANAGEMENT_SERVICE};
+use crate::common::parsing::RequestReponse;
+use crate::errors::RequestError;
+
+impl<P: Provider> Gateway<P> {
+    async fn perform_lan_host_config_request(&self, action: &str) -> Result<RequestReponse, RequestError> {
+        let service = self.service(LAN_HOST_CONFIG_MANAGEMENT_SERVICE, action)?;
+        self.perform_service_request(
+            &service.control_url,
+            &service.service_type,
+            action,
+            &lan_host::format_lan_host_config_message(&service.service_type, action),
+            &format!("{action}Response"),
+        )
+        .await
+    }
+
+    /// Get whether the DHCP server of the LAN can be configured.
+    pub async fn get_dhcp_server_configurable(&self) -> Result<bool, RequestError> {
+        self.perform_lan_host_config_request(lan_host::GET_DHCP_SERVER_CONFIGURABLE_ACTION)
+            .await?
+            .bool_argument("NewDHCPServerConfigurable")
+    }
+
+    /// Get the subnet mask of the LAN.
+    pub async fn get_subnet_mask(&self) -> Result<Ipv4Addr, RequestError> {
+        lan_host::parse_get_subnet_mask_response(
+            self.perform_lan_host_config_request(lan_host::GET_SUBNET_MASK_ACTION)
+                .await,
+        )
+    }
+
+    /// Get the routers the DHCP server hands out to LAN hosts.
+    pub async fn get_ip_routers_list(&self) -> Result<Vec<IpAddr>, RequestError> {
+        lan_host::parse_address_list_response(
+            self.perform_lan_host_config_request(lan_host::GET_IP_ROUTERS_LIST_ACTION)
+                .await,
+            "NewIPRouters",
+        )
+    }
+
+    /// Get the domain name the DHCP server hands out to LAN hosts.
+    pub async fn get_domain_name(&self) -> Result<String, RequestError> {
+        self.perform_lan_host_config_request(lan_host::GET_DOMAIN_NAME_ACTION)
+            .await?
+            .argument("NewDomainName")
+    }
+
+    /// Get the range of addresses the DHCP server hands out to LAN hosts.
+    pub async fn get_address_range(&self) -> Result<AddressRange, RequestError> {
+        lan_host::parse_get_address_range_response(
+            self.perform_lan_host_config_request(lan_host::GET_ADDRESS_RANGE_ACTION)
+                .await,
+        )
+    }
+
+    /// Get the DNS servers the DHCP server hands out to LAN hosts.
+    pub async fn get_dns_servers(&self) -> Result<Vec<IpAddr>, RequestError> {
+        lan_host::parse_address_list_response(
+            self.perform_lan_host_config_request(lan_host::GET_DNS_SERVERS_ACTION)
+                .await,
+            "NewDNSServers",
+        )
+    }
+
+    /// Get the addresses within the address range the DHCP server does not hand out.
+    pub async fn get_reserved_addresses(&self) -> Result<Vec<IpAddr>, RequestError> {
+        lan_host::parse_address_list_response(
+            self.perform_lan_host_config_request(lan_host::GET_RESERVED_ADDRESSES_ACTION)
+                .await,
+            "NewReservedAddresses",
+        )
+    }
+}
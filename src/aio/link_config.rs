@@ -0,0 +1,146 @@
+use super::{Gateway, Provider};
+use crate::common::link_config::{self, CableLinkConfigInfo, DslLinkInfo, LinkStatus};
+use crate::common::parsing::RequestReponse;
+use crate::errors::RequestError;
+
+impl<P: Provider> Gateway<P> {
+    async fn perform_link_config_request(
+        &self,
+        service_type: &str,
+        action: &str,
+    ) -> Result<RequestReponse, RequestError> {
+        let service = link_config::find_link_config_service(&self.services, service_type, &self.control_url)
+            .ok_or_else(|| RequestError::UnsupportedAction(action.to_string()))?;
+        self.perform_service_request(
+            &service.control_url,
+            &service.service_type,
+            action,
+            &link_config::format_link_config_message(&service.service_type, action),
+            &format!("{action}Response"),
+        )
+        .await
+    }
+
+    /// Get the type and state of the DSL link.
+    pub async fn get_dsl_link_info(&self) -> Result<DslLinkInfo, RequestError> {
+        link_config::parse_get_dsl_link_info_response(
+            self.perform_link_config_request(
+                link_config::WAN_DSL_LINK_CONFIG_SERVICE,
+                link_config::GET_DSL_LINK_INFO_ACTION,
+            )
+            .await,
+        )
+    }
+
+    /// Get whether the DSL link is configured automatically.
+    pub async fn get_dsl_auto_config(&self) -> Result<bool, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_DSL_LINK_CONFIG_SERVICE,
+            link_config::GET_AUTO_CONFIG_ACTION,
+        )
+        .await?
+        .bool_argument("NewAutoConfig")
+    }
+
+    /// Get the modulation type of the DSL link (e.g. `ADSL_G.dmt`).
+    pub async fn get_dsl_modulation_type(&self) -> Result<String, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_DSL_LINK_CONFIG_SERVICE,
+            link_config::GET_MODULATION_TYPE_ACTION,
+        )
+        .await?
+        .argument("NewModulationType")
+    }
+
+    /// Get the destination address of the DSL link (e.g. `PVC: 1/32`).
+    pub async fn get_dsl_destination_address(&self) -> Result<String, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_DSL_LINK_CONFIG_SERVICE,
+            link_config::GET_DESTINATION_ADDRESS_ACTION,
+        )
+        .await?
+        .argument("NewDestinationAddress")
+    }
+
+    /// Get the ATM encapsulation of the DSL link (e.g. `LLC` or `VCMUX`).
+    pub async fn get_atm_encapsulation(&self) -> Result<String, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_DSL_LINK_CONFIG_SERVICE,
+            link_config::GET_ATM_ENCAPSULATION_ACTION,
+        )
+        .await?
+        .argument("NewATMEncapsulation")
+    }
+
+    /// Get the state of the Ethernet WAN link.
+    pub async fn get_ethernet_link_status(&self) -> Result<LinkStatus, RequestError> {
+        link_config::parse_get_ethernet_link_status_response(
+            self.perform_link_config_request(
+                link_config::WAN_ETHERNET_LINK_CONFIG_SERVICE,
+                link_config::GET_ETHERNET_LINK_STATUS_ACTION,
+            )
+            .await,
+        )
+    }
+
+    /// Get the configuration state and link type of the cable modem.
+    pub async fn get_cable_link_config_info(&self) -> Result<CableLinkConfigInfo, RequestError> {
+        link_config::parse_get_cable_link_config_info_response(
+            self.perform_link_config_request(
+                link_config::WAN_CABLE_LINK_CONFIG_SERVICE,
+                link_config::GET_CABLE_LINK_CONFIG_INFO_ACTION,
+            )
+            .await,
+        )
+    }
+
+    /// Get the downstream frequency of the cable modem in Hz.
+    pub async fn get_downstream_frequency(&self) -> Result<u32, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_CABLE_LINK_CONFIG_SERVICE,
+            link_config::GET_DOWNSTREAM_FREQUENCY_ACTION,
+        )
+        .await?
+        .parse_argument("NewDownstreamFrequency")
+    }
+
+    /// Get the downstream modulation of the cable modem (e.g. `64QAM`).
+    pub async fn get_downstream_modulation(&self) -> Result<String, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_CABLE_LINK_CONFIG_SERVICE,
+            link_config::GET_DOWNSTREAM_MODULATION_ACTION,
+        )
+        .await?
+        .argument("NewDownstreamModulation")
+    }
+
+    /// Get the upstream frequency of the cable modem in Hz.
+    pub async fn get_upstream_frequency(&self) -> Result<u32, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_CABLE_LINK_CONFIG_SERVICE,
+            link_config::GET_UPSTREAM_FREQUENCY_ACTION,
+        )
+        .await?
+        .parse_argument("NewUpstreamFrequency")
+    }
+
+    /// Get the upstream modulation of the cable modem (e.g. `QPSK`).
+    pub async fn get_upstream_modulation(&self) -> Result<String, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_CABLE_LINK_CONFIG_SERVICE,
+            link_config::GET_UPSTREAM_MODULATION_ACTION,
+        )
+        .await?
+        .argument("NewUpstreamModulation")
+    }
+
+    /// Get the upstream power level of the cable modem in dBmV.
+    pub async fn get_upstream_power_level(&self) -> Result<u32, RequestError> {
+        self.perform_link_config_request(
+            link_config::WAN_CABLE_LINK_CONFIG_SERVICE,
+            link_config::GET_UPSTREAM_POWER_LEVEL_ACTION,
+        )
+        .await?
+        .parse_argument("NewUpstreamPowerLevel")
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use log::debug;
+use tokio::sync::{mpsc, Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use super::{Gateway, Provider};
+use crate::common::manager::{self, MappingFailure, MappingSpec, Schedule};
+use crate::errors::{AddPortError, RemovePortError};
+use crate::PortMappingProtocol;
+
+/// Keeps a set of port mappings alive on a tokio task, adding each one again before its
+/// lease expires, which also re-creates mappings the gateway lost.
+///
+/// Dropping the manager stops the renewals; the mappings then expire with their lease.
+pub struct MappingManager<P> {
+    gateway: Gateway<P>,
+    schedule: Arc<Mutex<Schedule>>,
+    wake: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static> MappingManager<P> {
+    /// Manage mappings on the gateway, calling `on_failure` whenever a renewal fails.
+    pub fn new<F>(gateway: Gateway<P>, on_failure: F) -> MappingManager<P>
+    where
+        F: Fn(MappingFailure) + Send + 'static,
+    {
+        let schedule = Arc::new(Mutex::new(Schedule::default()));
+        let wake = Arc::new(Notify::new());
+        let task = tokio::spawn(renew_mappings(
+            gateway.clone(),
+            schedule.clone(),
+            wake.clone(),
+            on_failure,
+        ));
+        MappingManager {
+            gateway,
+            schedule,
+            wake,
+            task,
+        }
+    }
+
+    /// Manage mappings on the gateway, sending the failed renewals to the returned channel.
+    pub fn with_channel(gateway: Gateway<P>) -> (MappingManager<P>, mpsc::UnboundedReceiver<MappingFailure>) {
+        let (sender, failures) = mpsc::unbounded_channel();
+        let manager = MappingManager::new(gateway, move |failure| {
+            let _ = sender.send(failure);
+        });
+        (manager, failures)
+    }
+}
+
+impl<P: Provider> MappingManager<P> {
+    /// Add the mapping to the gateway and keep it alive.
+    pub async fn add(&self, mapping: MappingSpec) -> Result<(), AddPortError> {
+        let granted = self
+            .gateway
+            .add_port_with_lease(
+                mapping.protocol,
+                mapping.external_port,
+                mapping.local_addr,
+                mapping.lease,
+                &mapping.description,
+            )
+            .await?;
+        self.schedule.lock().await.insert(mapping, granted, Instant::now());
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    /// Stop managing a mapping and remove it from the gateway.
+    pub async fn remove(&self, protocol: PortMappingProtocol, external_port: u16) -> Result<(), RemovePortError> {
+        self.schedule.lock().await.remove(protocol, external_port);
+        self.gateway.remove_port(protocol, external_port).await
+    }
+
+    /// The managed mappings.
+    pub async fn mappings(&self) -> Vec<MappingSpec> {
+        self.schedule.lock().await.mappings()
+    }
+}
+
+impl<P> Drop for MappingManager<P> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn renew_mappings<P, F>(gateway: Gateway<P>, schedule: Arc<Mutex<Schedule>>, wake: Arc<Notify>, on_failure: F)
+where
+    P: Provider,
+    F: Fn(MappingFailure),
+{
+    loop {
+        let next_due = schedule.lock().await.next_due();
+        let wait = match next_due {
+            Some(due) => due.saturating_duration_since(Instant::now()),
+            None => manager::IDLE_INTERVAL,
+        };
+        if timeout(wait, wake.notified()).await.is_ok() {
+            continue;
+        }
+
+        // The schedule is only locked between requests, so adding, removing and listing
+        // mappings do not wait for the renewals.
+        let due = schedule.lock().await.due(Instant::now());
+        for (mapping, generation) in due {
+            let result = gateway
+                .add_port_with_lease(
+                    mapping.protocol,
+                    mapping.external_port,
+                    mapping.local_addr,
+                    mapping.lease,
+                    &mapping.description,
+                )
+                .await;
+            let current = schedule
+                .lock()
+                .await
+                .renewed(&mapping, generation, result.as_ref().copied(), Instant::now());
+            match result {
+                Ok(_) if !current => undo_stale_renewal(&gateway, &schedule, &mapping).await,
+                Ok(_) => {}
+                Err(error) if current => on_failure(MappingFailure { mapping, error }),
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+// The mapping was removed or added again while it was being renewed, so the renewal may
+// have overwritten that change on the gateway. Remove the mapping again if it is no longer
+// managed, and renew the current version right away otherwise.
+async fn undo_stale_renewal<P: Provider>(gateway: &Gateway<P>, schedule: &Mutex<Schedule>, mapping: &MappingSpec) {
+    let managed = schedule.lock().await.contains(mapping.protocol, mapping.external_port);
+    if !managed {
+        if let Err(error) = gateway.remove_port(mapping.protocol, mapping.external_port).await {
+            debug!("could not remove the renewal of a removed mapping: {}", error);
+        }
+    }
+    // Also covers a mapping added again during the removal above.
+    schedule
+        .lock()
+        .await
+        .renew_now(mapping.protocol, mapping.external_port, Instant::now());
+}
@@ -0,0 +1,241 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+use super::{Gateway, Provider};
+use crate::common::MapOptions;
+use crate::errors::{AddAnyPortError, AddPortError, RemovePortError};
+use crate::PortMappingProtocol;
+
+/// A port mapping that is removed from the gateway when dropped.
+///
+/// Dropping it spawns the removal on the current tokio runtime, if any, without waiting
+/// for it. Use [`release`](Self::release) to remove it and get the error, if any, or
+/// [`leak`](Self::leak) to keep it on the gateway.
+#[derive(Debug)]
+pub struct PortMapping<P: Provider + Clone + Send + Sync + 'static> {
+    gateway: Gateway<P>,
+    protocol: PortMappingProtocol,
+    external_port: u16,
+    lease: Option<Duration>,
+    expiry: Option<Expiry>,
+    active: bool,
+}
+
+// Removal of a permanent mapping once the requested lease has passed.
+#[derive(Debug)]
+struct Expiry {
+    task: JoinHandle<()>,
+    expired: Arc<AtomicBool>,
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static> PortMapping<P> {
+    fn new(
+        gateway: &Gateway<P>,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        granted: Option<Duration>,
+        requested: Option<Duration>,
+    ) -> PortMapping<P> {
+        let expiry = requested.filter(|_| granted.is_none()).map(|lease| {
+            let expired = Arc::new(AtomicBool::new(false));
+            let task = tokio::spawn(remove_after(
+                gateway.clone(),
+                protocol,
+                external_port,
+                lease,
+                expired.clone(),
+            ));
+            Expiry { task, expired }
+        });
+        PortMapping {
+            gateway: gateway.clone(),
+            protocol,
+            external_port,
+            lease: granted,
+            expiry,
+            active: true,
+        }
+    }
+
+    /// The gateway the port is mapped on.
+    pub fn gateway(&self) -> &Gateway<P> {
+        &self.gateway
+    }
+
+    /// The protocol of the mapping.
+    pub fn protocol(&self) -> PortMappingProtocol {
+        self.protocol
+    }
+
+    /// The external port of the mapping.
+    pub fn external_port(&self) -> u16 {
+        self.external_port
+    }
+
+    /// The lease of the mapping on the gateway, `None` being infinite.
+    pub fn lease(&self) -> Option<Duration> {
+        self.lease
+    }
+
+    /// Whether the mapping has an infinite lease on the gateway.
+    ///
+    /// When the gateway only supports permanent leases and the lease was emulated, the
+    /// mapping is still removed once the requested lease has passed.
+    pub fn is_permanent(&self) -> bool {
+        self.lease.is_none()
+    }
+
+    /// Remove the mapping from the gateway now.
+    pub async fn release(mut self) -> Result<(), RemovePortError> {
+        self.active = false;
+        if let Some(expiry) = &self.expiry {
+            expiry.task.abort();
+        }
+        self.gateway.remove_port(self.protocol, self.external_port).await
+    }
+
+    /// Keep the mapping on the gateway. It then lives until its lease expires or it is
+    /// removed with `remove_port`.
+    pub fn leak(mut self) {
+        self.active = false;
+        // Dropping the handle detaches the task, which still removes an emulated lease.
+        self.expiry = None;
+    }
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static> Drop for PortMapping<P> {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        if let Some(expiry) = &self.expiry {
+            expiry.task.abort();
+            if expiry.expired.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+        let (protocol, external_port) = (self.protocol, self.external_port);
+        let Ok(runtime) = Handle::try_current() else {
+            debug!("no tokio runtime to remove the {protocol} mapping of external port {external_port}");
+            return;
+        };
+        let gateway = self.gateway.clone();
+        runtime.spawn(async move {
+            if let Err(e) = gateway.remove_port(protocol, external_port).await {
+                debug!("could not remove the {protocol} mapping of external port {external_port}: {e}");
+            }
+        });
+    }
+}
+
+async fn remove_after<P: Provider>(
+    gateway: Gateway<P>,
+    protocol: PortMappingProtocol,
+    external_port: u16,
+    lease: Duration,
+    expired: Arc<AtomicBool>,
+) {
+    tokio::time::sleep(lease).await;
+    expired.store(true, Ordering::SeqCst);
+    match gateway.remove_port(protocol, external_port).await {
+        Ok(()) => debug!("removed the {protocol} mapping of external port {external_port} as its lease expired"),
+        Err(e) => debug!("could not remove the {protocol} mapping of external port {external_port}: {e}"),
+    }
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static> Gateway<P> {
+    /// Add a port mapping that is removed when the returned guard is dropped.
+    ///
+    /// The arguments are the same as for [`add_port_with_lease`](Self::add_port_with_lease).
+    pub async fn map_port(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<PortMapping<P>, AddPortError> {
+        self.map_port_with_options(
+            protocol,
+            external_port,
+            local_addr,
+            lease,
+            description,
+            MapOptions::default(),
+        )
+        .await
+    }
+
+    /// Add a port mapping that is removed when the returned guard is dropped, with the given
+    /// options.
+    pub async fn map_port_with_options(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+        options: MapOptions,
+    ) -> Result<PortMapping<P>, AddPortError> {
+        let granted = match self
+            .add_port_with_lease(protocol, external_port, local_addr, lease, description)
+            .await
+        {
+            Ok(granted) => granted,
+            Err(AddPortError::OnlyPermanentLeasesSupported) if options.permanent_lease_fallback => {
+                debug!("gateway only supports permanent leases, mapping external port {external_port} permanently");
+                self.add_port(protocol, external_port, local_addr, 0, description)
+                    .await?;
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(PortMapping::new(self, protocol, external_port, granted, lease))
+    }
+
+    /// Add a port mapping with any external port that is removed when the returned guard is
+    /// dropped.
+    ///
+    /// The arguments are the same as for
+    /// [`add_any_port_with_lease`](Self::add_any_port_with_lease).
+    pub async fn map_any_port(
+        &self,
+        protocol: PortMappingProtocol,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<PortMapping<P>, AddAnyPortError> {
+        self.map_any_port_with_options(protocol, local_addr, lease, description, MapOptions::default())
+            .await
+    }
+
+    /// Add a port mapping with any external port that is removed when the returned guard is
+    /// dropped, with the given options.
+    pub async fn map_any_port_with_options(
+        &self,
+        protocol: PortMappingProtocol,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+        options: MapOptions,
+    ) -> Result<PortMapping<P>, AddAnyPortError> {
+        let (external_port, granted) = match self
+            .add_any_port_with_lease(protocol, local_addr, lease, description)
+            .await
+        {
+            Ok(mapped) => mapped,
+            Err(AddAnyPortError::OnlyPermanentLeasesSupported) if options.permanent_lease_fallback => {
+                debug!("gateway only supports permanent leases, mapping a port permanently");
+                (self.add_any_port(protocol, local_addr, 0, description).await?, None)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(PortMapping::new(self, protocol, external_port, granted, lease))
+    }
+}
@@ -1,14 +1,39 @@
 //! This module implements the same features as the main crate, but using async io.
 
+mod batch;
+mod cascade;
+mod firewall;
 mod gateway;
+mod gena;
+mod lan_host;
+mod link_config;
+mod manager;
+mod mapping;
+mod natpmp;
+mod pcp;
+mod port_mapper;
+mod ppp;
+mod reconcile;
+mod stun;
+mod topology;
+mod watcher;
 
 #[cfg(feature = "aio_tokio")]
 pub mod tokio;
 
 use crate::RequestError;
 use std::future::Future;
+use std::net::IpAddr;
 
 pub use self::gateway::Gateway;
+pub use self::gena::{EventListener, Subscription};
+pub use self::manager::MappingManager;
+pub use self::mapping::PortMapping;
+pub use self::natpmp::NatPmpClient;
+pub use self::pcp::PcpClient;
+pub use self::port_mapper::PortMapper;
+pub use self::stun::StunClient;
+pub use self::watcher::ExternalIpWatcher;
 
 pub(crate) const MAX_RESPONSE_SIZE: usize = 1500;
 pub(crate) const HEADER_NAME: &str = "SOAPAction";
@@ -17,4 +42,17 @@ pub(crate) const HEADER_NAME: &str = "SOAPAction";
 pub trait Provider {
     /// Send an async request over the executor.
     fn send_async(url: &str, action: &str, body: &str) -> impl Future<Output = Result<String, RequestError>> + Send;
+
+    /// Send an async request over a connection bound to `bind_addr`, if any.
+    ///
+    /// The default implementation ignores `bind_addr`.
+    fn send_async_from(
+        url: &str,
+        action: &str,
+        body: &str,
+        bind_addr: Option<IpAddr>,
+    ) -> impl Future<Output = Result<String, RequestError>> + Send {
+        let _ = bind_addr;
+        Self::send_async(url, action, body)
+    }
 }
@@ -0,0 +1,152 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tokio::time::timeout_at;
+
+use crate::common::natpmp::{self, EpochTracker, NatPmpExternalAddress, NatPmpMapping};
+use crate::errors::NatPmpError;
+use crate::PortMappingProtocol;
+
+/// A client of a NAT-PMP (RFC 6886) gateway.
+pub struct NatPmpClient {
+    socket: UdpSocket,
+    gateway: SocketAddr,
+    max_attempts: u32,
+    // Held for the whole exchange, so concurrent requests do not read each other's responses.
+    epoch: Mutex<EpochTracker>,
+}
+
+impl NatPmpClient {
+    /// Create a client of the gateway at `gateway`, which usually is the default router on
+    /// port [`NATPMP_PORT`](crate::NATPMP_PORT).
+    pub async fn new(gateway: SocketAddr) -> io::Result<NatPmpClient> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.connect(gateway).await?;
+        Ok(NatPmpClient {
+            socket,
+            gateway,
+            max_attempts: natpmp::MAX_ATTEMPTS,
+            epoch: Mutex::new(EpochTracker::default()),
+        })
+    }
+
+    /// The address of the gateway.
+    pub fn gateway(&self) -> SocketAddr {
+        self.gateway
+    }
+
+    /// Set how many times a request is sent before failing with `NoResponse`. The delay
+    /// between attempts starts at 250ms and doubles each time, so the default of 9 attempts
+    /// waits about 2 minutes.
+    pub fn set_max_attempts(&mut self, attempts: u32) {
+        self.max_attempts = attempts.max(1);
+    }
+
+    /// Get the external address of the gateway.
+    pub async fn external_address(&self) -> Result<NatPmpExternalAddress, NatPmpError> {
+        let request = natpmp::format_external_address_request();
+        let mut epoch = self.epoch.lock().await;
+        let (address, response_epoch) = self.send(&request, natpmp::parse_external_address_response).await?;
+        Ok(NatPmpExternalAddress {
+            address,
+            epoch: response_epoch,
+            gateway_restarted: epoch.observe(response_epoch, Instant::now()),
+        })
+    }
+
+    /// Map `internal_port` for the given lifetime, or renew the existing mapping of that port.
+    ///
+    /// The gateway may grant a different external port than the suggested one (0 lets the
+    /// gateway choose) and a shorter lifetime than requested.
+    pub async fn add_mapping(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        suggested_external_port: u16,
+        lifetime: Duration,
+    ) -> Result<NatPmpMapping, NatPmpError> {
+        let lifetime = u32::try_from(lifetime.as_secs()).unwrap_or(u32::MAX).max(1);
+        let request = natpmp::format_mapping_request(protocol, internal_port, suggested_external_port, lifetime);
+        self.request_mapping(&request, protocol, internal_port).await
+    }
+
+    /// Remove the mapping of `internal_port`.
+    pub async fn remove_mapping(&self, protocol: PortMappingProtocol, internal_port: u16) -> Result<(), NatPmpError> {
+        let request = natpmp::format_mapping_request(protocol, internal_port, 0, 0);
+        self.request_mapping(&request, protocol, internal_port)
+            .await
+            .map(|_| ())
+    }
+
+    /// Remove all mappings of this host for the given protocol.
+    pub async fn remove_all_mappings(&self, protocol: PortMappingProtocol) -> Result<(), NatPmpError> {
+        self.remove_mapping(protocol, 0).await
+    }
+
+    async fn request_mapping(
+        &self,
+        request: &[u8],
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+    ) -> Result<NatPmpMapping, NatPmpError> {
+        let mut epoch = self.epoch.lock().await;
+        let mut mapping = self
+            .send(request, |packet| {
+                natpmp::parse_mapping_response(packet, protocol, internal_port)
+            })
+            .await?;
+        mapping.gateway_restarted = epoch.observe(mapping.epoch, Instant::now());
+        Ok(mapping)
+    }
+
+    // Send the request following the retransmission schedule until a response is parsed.
+    async fn send<T>(
+        &self,
+        request: &[u8],
+        parse: impl Fn(&[u8]) -> Result<Option<T>, NatPmpError>,
+    ) -> Result<T, NatPmpError> {
+        let mut timeout = natpmp::INITIAL_RETRANSMISSION_TIMEOUT;
+        let mut buf = [0u8; natpmp::MAX_RESPONSE_SIZE];
+        for _ in 0..self.max_attempts {
+            self.socket.send(request).await?;
+            let deadline = tokio::time::Instant::now() + timeout;
+            while let Ok(read) = timeout_at(deadline, self.socket.recv(&mut buf)).await {
+                match parse(&buf[..read?])? {
+                    Some(response) => return Ok(response),
+                    None => debug!("ignoring unexpected NAT-PMP packet from {}", self.gateway),
+                }
+            }
+            timeout *= 2;
+        }
+        Err(NatPmpError::NoResponse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn retransmits_until_the_gateway_responds() {
+        let gateway = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let client = NatPmpClient::new(gateway.local_addr().unwrap()).await.unwrap();
+        let stand_in = tokio::spawn(async move {
+            let mut buf = [0u8; 12];
+            // Drop the first request, as if it was lost.
+            gateway.recv_from(&mut buf).await.unwrap();
+            let (_, from) = gateway.recv_from(&mut buf).await.unwrap();
+            gateway
+                .send_to(&[0, 128, 0, 0, 0, 0, 0, 1, 198, 51, 100, 1], from)
+                .await
+                .unwrap();
+        });
+        let external = client.external_address().await.unwrap();
+        assert_eq!(external.address, Ipv4Addr::new(198, 51, 100, 1));
+        assert_eq!(external.epoch, 1);
+        stand_in.await.unwrap();
+    }
+}
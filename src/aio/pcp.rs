@@ -0,0 +1,232 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tokio::time::timeout_at;
+
+use crate::common::pcp::{self, EpochTracker, Nonce, PcpAnnounce, PcpMapOptions, PcpMapping, PcpMappingKind};
+use crate::errors::PcpError;
+use crate::PortMappingProtocol;
+
+/// A client of a PCP (RFC 6887) server.
+pub struct PcpClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    client_ip: IpAddr,
+    max_attempts: u32,
+    // Held for the whole exchange, so concurrent requests do not read each other's responses.
+    epoch: Mutex<EpochTracker>,
+}
+
+impl PcpClient {
+    /// Create a client of the server at `server`, which usually is the default router on
+    /// port [`PCP_PORT`](crate::PCP_PORT).
+    pub async fn new(server: SocketAddr) -> io::Result<PcpClient> {
+        let unspecified = match server {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let socket = UdpSocket::bind((unspecified, 0)).await?;
+        socket.connect(server).await?;
+        let client_ip = socket.local_addr()?.ip();
+        Ok(PcpClient {
+            socket,
+            server,
+            client_ip,
+            max_attempts: pcp::MAX_ATTEMPTS,
+            epoch: Mutex::new(EpochTracker::default()),
+        })
+    }
+
+    /// The address of the server.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Set how many times a request is sent before failing with `NoResponse`. The delay
+    /// between attempts starts at about 3 seconds and doubles each time.
+    pub fn set_max_attempts(&mut self, attempts: u32) {
+        self.max_attempts = attempts.max(1);
+    }
+
+    /// Check that the server is reachable and get its epoch.
+    pub async fn announce(&self) -> Result<PcpAnnounce, PcpError> {
+        let request = pcp::format_announce_request(self.client_ip);
+        let mut epoch = self.epoch.lock().await;
+        let response_epoch = self.send(&request, pcp::parse_announce_response).await?;
+        Ok(PcpAnnounce {
+            epoch: response_epoch,
+            gateway_restarted: epoch.observe(response_epoch, Instant::now()),
+        })
+    }
+
+    /// Map `internal_port` on the server for inbound traffic, with the MAP opcode.
+    ///
+    /// The server may grant another external address or port than the suggested one, unless
+    /// `prefer_failure` is set, and a shorter lifetime than requested.
+    pub async fn map(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        lifetime: Duration,
+        options: PcpMapOptions,
+    ) -> Result<PcpMapping, PcpError> {
+        let nonce = pcp::new_nonce();
+        let request = pcp::format_map_request(
+            self.client_ip,
+            &nonce,
+            protocol,
+            internal_port,
+            lifetime_secs(lifetime),
+            &options,
+        );
+        self.request_mapping(&request, PcpMappingKind::Map, &nonce, protocol, internal_port)
+            .await
+    }
+
+    /// Create or learn the mapping used by the connection from `internal_port` to
+    /// `remote_peer`, with the PEER opcode.
+    pub async fn peer(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        remote_peer: SocketAddr,
+        lifetime: Duration,
+        suggested_external_addr: Option<SocketAddr>,
+    ) -> Result<PcpMapping, PcpError> {
+        let nonce = pcp::new_nonce();
+        let request = pcp::format_peer_request(
+            self.client_ip,
+            &nonce,
+            protocol,
+            internal_port,
+            remote_peer,
+            lifetime_secs(lifetime),
+            suggested_external_addr,
+        );
+        let kind = PcpMappingKind::Peer { remote_peer };
+        self.request_mapping(&request, kind, &nonce, protocol, internal_port)
+            .await
+    }
+
+    /// Extend the lifetime of a mapping, which should be done after
+    /// [`PcpMapping::renew_after`]. It also re-creates the mapping if the server lost it.
+    pub async fn renew(&self, mapping: &PcpMapping, lifetime: Duration) -> Result<PcpMapping, PcpError> {
+        self.refresh(mapping, lifetime_secs(lifetime)).await
+    }
+
+    /// Delete a mapping.
+    pub async fn delete(&self, mapping: &PcpMapping) -> Result<(), PcpError> {
+        self.refresh(mapping, 0).await.map(|_| ())
+    }
+
+    async fn refresh(&self, mapping: &PcpMapping, lifetime: u32) -> Result<PcpMapping, PcpError> {
+        // The current external address is suggested, so a server that lost the mapping
+        // re-creates the same one if it can.
+        let request = match mapping.kind {
+            PcpMappingKind::Map => pcp::format_map_request(
+                self.client_ip,
+                &mapping.nonce,
+                mapping.protocol,
+                mapping.internal_port,
+                lifetime,
+                &PcpMapOptions {
+                    suggested_external_addr: Some(mapping.external_addr),
+                    prefer_failure: false,
+                },
+            ),
+            PcpMappingKind::Peer { remote_peer } => pcp::format_peer_request(
+                self.client_ip,
+                &mapping.nonce,
+                mapping.protocol,
+                mapping.internal_port,
+                remote_peer,
+                lifetime,
+                Some(mapping.external_addr),
+            ),
+        };
+        self.request_mapping(
+            &request,
+            mapping.kind,
+            &mapping.nonce,
+            mapping.protocol,
+            mapping.internal_port,
+        )
+        .await
+    }
+
+    async fn request_mapping(
+        &self,
+        request: &[u8],
+        kind: PcpMappingKind,
+        nonce: &Nonce,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+    ) -> Result<PcpMapping, PcpError> {
+        let mut epoch = self.epoch.lock().await;
+        let mut mapping = self
+            .send(request, |packet| {
+                pcp::parse_mapping_response(packet, kind, nonce, protocol, internal_port)
+            })
+            .await?;
+        mapping.gateway_restarted = epoch.observe(mapping.epoch, Instant::now());
+        Ok(mapping)
+    }
+
+    // Send the request following the retransmission schedule until a response is parsed.
+    async fn send<T>(
+        &self,
+        request: &[u8],
+        parse: impl Fn(&[u8]) -> Result<Option<T>, PcpError>,
+    ) -> Result<T, PcpError> {
+        let mut timeout = None;
+        let mut buf = [0u8; pcp::MAX_MESSAGE_SIZE];
+        for _ in 0..self.max_attempts {
+            self.socket.send(request).await?;
+            let attempt_timeout = pcp::retransmission_timeout(timeout);
+            timeout = Some(attempt_timeout);
+            let deadline = tokio::time::Instant::now() + attempt_timeout;
+            while let Ok(read) = timeout_at(deadline, self.socket.recv(&mut buf)).await {
+                match parse(&buf[..read?])? {
+                    Some(response) => return Ok(response),
+                    None => debug!("ignoring unexpected PCP packet from {}", self.server),
+                }
+            }
+        }
+        Err(PcpError::NoResponse)
+    }
+}
+
+fn lifetime_secs(lifetime: Duration) -> u32 {
+    u32::try_from(lifetime.as_secs()).unwrap_or(u32::MAX).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn announce_tracks_the_epoch() {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let client = PcpClient::new(server.local_addr().unwrap()).await.unwrap();
+        let stand_in = tokio::spawn(async move {
+            let mut buf = [0u8; 1100];
+            for epoch in [500u32, 3] {
+                let (read, from) = server.recv_from(&mut buf).await.unwrap();
+                assert_eq!(read, 24);
+                let mut response = vec![2, 0x80, 0, 0, 0, 0, 0, 0];
+                response.extend_from_slice(&epoch.to_be_bytes());
+                response.resize(24, 0);
+                server.send_to(&response, from).await.unwrap();
+            }
+        });
+        let announce = client.announce().await.unwrap();
+        assert_eq!(announce.epoch, 500);
+        assert!(!announce.gateway_restarted);
+        assert!(client.announce().await.unwrap().gateway_restarted);
+        stand_in.await.unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use log::debug;
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tokio::time::timeout_at;
+
+use crate::common::stun::{self, ExternalIpCheck};
+use crate::errors::StunError;
+
+/// A client of a STUN (RFC 5389) server, which tells the address requests come from as seen
+/// from the internet.
+pub struct StunClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    max_attempts: u32,
+    // Held for the whole exchange, so concurrent requests do not read each other's responses.
+    exchange: Mutex<()>,
+}
+
+impl StunClient {
+    /// Create a client of the STUN server at `server`, which usually listens on port
+    /// [`STUN_PORT`](crate::STUN_PORT).
+    pub async fn new(server: SocketAddr) -> io::Result<StunClient> {
+        let unspecified = match server {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let socket = UdpSocket::bind((unspecified, 0)).await?;
+        socket.connect(server).await?;
+        Ok(StunClient {
+            socket,
+            server,
+            max_attempts: stun::MAX_ATTEMPTS,
+            exchange: Mutex::new(()),
+        })
+    }
+
+    /// The address of the server.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Set how many times a request is sent before failing with `NoResponse`. The delay
+    /// between attempts starts at 500ms and doubles each time, and the last request is waited
+    /// for 8 seconds, so the default of 7 attempts waits 39.5 seconds.
+    pub fn set_max_attempts(&mut self, attempts: u32) {
+        self.max_attempts = attempts.max(1);
+    }
+
+    /// Get the address and port the server received a binding request from.
+    pub async fn reflexive_address(&self) -> Result<SocketAddr, StunError> {
+        let transaction_id = stun::transaction_id();
+        let request = stun::format_binding_request(transaction_id);
+        let _exchange = self.exchange.lock().await;
+        let mut buf = [0u8; stun::MAX_RESPONSE_SIZE];
+        for attempt in 0..self.max_attempts {
+            self.socket.send(&request).await?;
+            let deadline = tokio::time::Instant::now() + stun::response_wait(attempt, self.max_attempts);
+            while let Ok(read) = timeout_at(deadline, self.socket.recv(&mut buf)).await {
+                match stun::parse_binding_response(&buf[..read?], transaction_id)? {
+                    Some(address) => return Ok(address),
+                    None => debug!("ignoring unexpected STUN packet from {}", self.server),
+                }
+            }
+        }
+        Err(StunError::NoResponse)
+    }
+
+    /// Compare the external address a gateway reports, from `Gateway::get_external_ip`, with
+    /// the reflexive address. A mismatch hints at a double NAT.
+    pub async fn check_external_ip(&self, gateway_ip: IpAddr) -> Result<ExternalIpCheck, StunError> {
+        Ok(ExternalIpCheck {
+            gateway_ip,
+            reflexive_addr: self.reflexive_address().await?,
+        })
+    }
+}
@@ -5,15 +5,17 @@ use futures::prelude::*;
 use http_body_util::{BodyExt, Empty, Limited};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::Request;
+use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::client::legacy::Client;
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
-use tokio::{net::UdpSocket, time::timeout};
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, timeout_at, Instant};
 
 use super::{Provider, HEADER_NAME, MAX_RESPONSE_SIZE};
 use crate::common::options::{DEFAULT_REQUEST_TIMEOUT, DEFAULT_TIMEOUT, MAX_RESPONSE_BYTES, RESPONSE_TIMEOUT};
-use crate::common::{messages, parsing, SearchOptions};
+use crate::common::{self, messages, parsing, parsing::ServiceDescription, SearchOptions};
 use crate::errors::SearchError;
 use crate::{aio::Gateway, RequestError};
 use log::debug;
@@ -24,7 +26,18 @@ pub struct Tokio;
 
 impl Provider for Tokio {
     async fn send_async(url: &str, action: &str, body: &str) -> Result<String, RequestError> {
-        let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build_http();
+        Self::send_async_from(url, action, body, None).await
+    }
+
+    async fn send_async_from(
+        url: &str,
+        action: &str,
+        body: &str,
+        bind_addr: Option<IpAddr>,
+    ) -> Result<String, RequestError> {
+        let mut connector = HttpConnector::new();
+        connector.set_local_address(bind_addr);
+        let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(connector);
 
         let body = body.to_string();
 
@@ -54,7 +67,8 @@ impl Provider for Tokio {
 /// Search for a gateway with the provided options.
 pub async fn search_gateway(options: SearchOptions) -> Result<Gateway<Tokio>, SearchError> {
     let search_timeout = options.timeout.unwrap_or(DEFAULT_TIMEOUT);
-    match timeout(search_timeout, search_gateway_inner(options)).await {
+    let deadline = Instant::now() + search_timeout;
+    match timeout_at(deadline, search_gateway_inner(options, deadline)).await {
         Ok(Ok(gateway)) => Ok(gateway),
         Ok(Err(err)) => Err(err),
         Err(_err) => {
@@ -64,7 +78,7 @@ pub async fn search_gateway(options: SearchOptions) -> Result<Gateway<Tokio>, Se
     }
 }
 
-async fn search_gateway_inner(options: SearchOptions) -> Result<Gateway<Tokio>, SearchError> {
+async fn search_gateway_inner(options: SearchOptions, deadline: Instant) -> Result<Gateway<Tokio>, SearchError> {
     // Create socket for future calls
     let mut socket = UdpSocket::bind(&options.bind_addr).await?;
 
@@ -95,7 +109,7 @@ async fn search_gateway_inner(options: SearchOptions) -> Result<Gateway<Tokio>,
             }
         };
 
-        let (service_type, control_schema_url, control_url) = match get_control_urls(&addr, &root_url).await {
+        let services = match get_services(&addr, &root_url).await {
             Ok(v) => v,
             Err(e) => {
                 debug!("error getting control URLs: {}", e);
@@ -103,24 +117,68 @@ async fn search_gateway_inner(options: SearchOptions) -> Result<Gateway<Tokio>,
             }
         };
 
-        let control_schema = match get_control_schemas(&addr, &control_schema_url).await {
-            Ok(v) => v,
-            Err(e) => {
-                debug!("error getting control schemas: {}", e);
+        let service = match services.iter().find(|service| service.is_wan_connection()) {
+            Some(service) => service.clone(),
+            None => {
+                debug!("no WAN connection service found at: {}{}", addr, root_url);
                 continue;
             }
         };
 
-        return Ok(Gateway {
+        let mut gateway = Gateway {
             addr,
             root_url,
-            control_url,
-            control_schema_url,
-            control_schema,
-            service_type,
+            control_url: service.control_url,
+            control_schema_url: service.scpd_url,
+            control_schema: HashMap::new(),
+            service_type: service.service_type,
+            services,
+            bind_addr: common::control_bind_addr(options.bind_addr, addr),
             provider: Tokio,
-        });
+        };
+        use_default_connection_service(&mut gateway, deadline).await;
+
+        gateway.control_schema = match get_control_schemas(&gateway.addr, &gateway.control_schema_url).await {
+            Ok(v) => v,
+            Err(e) => {
+                debug!("error getting control schemas: {}", e);
+                continue;
+            }
+        };
+
+        return Ok(gateway);
+    }
+}
+
+// Switch the gateway to the WAN connection service advertised by the root device's
+// Layer3Forwarding service, keeping the first one in document order if there is none or if
+// the gateway does not answer before the search's deadline.
+async fn use_default_connection_service(gateway: &mut Gateway<Tokio>, deadline: Instant) {
+    if !gateway
+        .services
+        .iter()
+        .any(|service| service.service_type == parsing::LAYER3_FORWARDING_SERVICE)
+    {
+        return;
     }
+    let service = match timeout_at(deadline, gateway.find_default_connection_service()).await {
+        Err(_) => {
+            debug!("timeout while getting the default connection service");
+            return;
+        }
+        Ok(Ok(Some(service))) => service.clone(),
+        Ok(Ok(None)) => {
+            debug!("default connection service is not one of the advertised services");
+            return;
+        }
+        Ok(Err(e)) => {
+            debug!("could not get the default connection service: {e}");
+            return;
+        }
+    };
+    gateway.control_url = service.control_url;
+    gateway.control_schema_url = service.scpd_url;
+    gateway.service_type = service.service_type;
 }
 
 // Create a new search.
@@ -157,7 +215,7 @@ fn handle_broadcast_resp(from: &SocketAddr, data: &[u8]) -> Result<(SocketAddr,
     Ok((addr, root_url))
 }
 
-async fn get_control_urls(addr: &SocketAddr, path: &str) -> Result<(String, String, String), SearchError> {
+async fn get_services(addr: &SocketAddr, path: &str) -> Result<Vec<ServiceDescription>, SearchError> {
     let uri = match format!("http://{addr}{path}").parse() {
         Ok(uri) => uri,
         Err(err) => return Err(SearchError::from(err)),
@@ -174,7 +232,7 @@ async fn get_control_urls(addr: &SocketAddr, path: &str) -> Result<(String, Stri
 
     debug!("handling control response from: {addr}");
     let c = std::io::Cursor::new(&resp);
-    parsing::parse_control_urls(c)
+    parsing::parse_services(c)
 }
 
 async fn get_control_schemas(
@@ -0,0 +1,30 @@
+use super::tokio::Tokio;
+use super::Gateway;
+use crate::common::topology::NatTopology;
+use crate::common::{cascade, SearchOptions};
+use crate::errors::GetExternalIpError;
+
+impl Gateway<Tokio> {
+    /// Classify the external address of the gateway, to tell whether its port mappings are
+    /// reachable from the internet. This makes no other request than for the external address.
+    ///
+    /// With `upstream_search`, when the external address is private, the gateway in front of
+    /// it is also searched for on that network as by `discover_upstream`, and reported as
+    /// upstream if found. That search can take up to twice its timeout.
+    pub async fn nat_topology(
+        &self,
+        upstream_search: Option<SearchOptions>,
+    ) -> Result<NatTopology, GetExternalIpError> {
+        let external_ip = self.get_external_ip().await?;
+        let upstream = match upstream_search {
+            Some(options) => {
+                let candidates = cascade::upstream_candidates(external_ip, options.broadcast_address.port());
+                self.search_upstream(candidates, &options)
+                    .await
+                    .map(|gateway| gateway.addr)
+            }
+            None => None,
+        };
+        Ok(NatTopology::new(external_ip, upstream))
+    }
+}
@@ -0,0 +1,173 @@
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::Stream;
+use log::debug;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use super::gena::{EventListener, Subscription};
+use super::{Gateway, Provider};
+use crate::common::messages;
+use crate::common::watcher::{self, AddressTracker, ExternalIpChange, PollInterval};
+use crate::common::WatchOptions;
+use crate::errors::GetExternalIpError;
+
+/// Watches the external IP address of a gateway on a tokio task. It is a [`Stream`] of the
+/// changes as `(old, new)`.
+///
+/// Dropping the watcher stops the task and cancels its event subscription.
+pub struct ExternalIpWatcher {
+    initial_address: Option<IpAddr>,
+    uses_events: bool,
+    changes: mpsc::UnboundedReceiver<ExternalIpChange>,
+    task: JoinHandle<()>,
+}
+
+impl ExternalIpWatcher {
+    /// The external address when the watcher started, `None` if the gateway was disconnected.
+    pub fn initial_address(&self) -> Option<IpAddr> {
+        self.initial_address
+    }
+
+    /// Whether changes are received as GENA events rather than only by polling.
+    pub fn uses_events(&self) -> bool {
+        self.uses_events
+    }
+
+    /// Wait for the next change.
+    pub async fn recv(&mut self) -> Option<ExternalIpChange> {
+        self.changes.recv().await
+    }
+}
+
+impl Stream for ExternalIpWatcher {
+    type Item = ExternalIpChange;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ExternalIpChange>> {
+        self.changes.poll_recv(cx)
+    }
+}
+
+impl Drop for ExternalIpWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl<P: Provider> Gateway<P> {
+    /// Get the external IP address, or `None` if the gateway reports an empty or unspecified
+    /// address because its WAN connection is down.
+    pub async fn get_external_ip_state(&self) -> Result<Option<IpAddr>, GetExternalIpError> {
+        let result = self
+            .perform_service_request(
+                &self.control_url,
+                &self.service_type,
+                messages::GET_EXTERNAL_IP_ACTION,
+                &messages::format_get_external_ip_message(&self.service_type),
+                "GetExternalIPAddressResponse",
+            )
+            .await;
+        watcher::parse_external_ip_state(result)
+    }
+}
+
+impl<P: Provider + Clone + Send + Sync + 'static> Gateway<P> {
+    /// Watch the external IP address for changes.
+    ///
+    /// The watcher subscribes to `ExternalIPAddress` events if the gateway supports them, and
+    /// otherwise polls the address, less often while it stays the same.
+    pub async fn watch_external_ip(&self, options: WatchOptions) -> Result<ExternalIpWatcher, GetExternalIpError> {
+        let initial_address = self.get_external_ip_state().await?;
+        let events = if options.use_events {
+            subscribe(self, options.listen_addr, options.subscription_timeout).await
+        } else {
+            None
+        };
+        let uses_events = events.is_some();
+
+        let (sender, changes) = mpsc::unbounded_channel();
+        let task = tokio::spawn(watch(self.clone(), options, initial_address, events, sender));
+        Ok(ExternalIpWatcher {
+            initial_address,
+            uses_events,
+            changes,
+            task,
+        })
+    }
+}
+
+async fn subscribe<P: Provider>(
+    gateway: &Gateway<P>,
+    listen_addr: SocketAddr,
+    timeout: Duration,
+) -> Option<(EventListener, Subscription)> {
+    let listener = match EventListener::bind(listen_addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            debug!("could not listen for events, polling the external IP address: {e}");
+            return None;
+        }
+    };
+    match gateway.subscribe(&listener, timeout).await {
+        Ok(subscription) => Some((listener, subscription)),
+        Err(e) => {
+            debug!("could not subscribe to events, polling the external IP address: {e}");
+            None
+        }
+    }
+}
+
+async fn watch<P: Provider>(
+    gateway: Gateway<P>,
+    options: WatchOptions,
+    initial_address: Option<IpAddr>,
+    mut events: Option<(EventListener, Subscription)>,
+    sender: mpsc::UnboundedSender<ExternalIpChange>,
+) {
+    let mut tracker = AddressTracker::new(initial_address);
+    // Events make polling a fallback for missed events, so it stays at the longest interval.
+    let min_poll_interval = match events {
+        Some(_) => options.max_poll_interval,
+        None => options.min_poll_interval,
+    };
+    let mut interval = PollInterval::new(min_poll_interval, options.max_poll_interval);
+    let mut next_poll = Instant::now() + interval.get();
+    loop {
+        let wait = next_poll.saturating_duration_since(Instant::now());
+        let event = match &mut events {
+            Some((listener, subscription)) => match timeout(wait, listener.recv()).await {
+                Ok(Some(notification)) => watcher::event_address(&notification, &subscription.sid()),
+                Ok(None) => return,
+                Err(_) => None,
+            },
+            None => {
+                tokio::time::sleep(wait).await;
+                None
+            }
+        };
+        let observed = match event {
+            Some(address) => address,
+            None if Instant::now() < next_poll => continue,
+            None => match gateway.get_external_ip_state().await {
+                Ok(address) => address,
+                Err(e) => {
+                    debug!("could not poll the external IP address: {e}");
+                    next_poll = Instant::now() + interval.get();
+                    continue;
+                }
+            },
+        };
+        let change = tracker.update(observed);
+        interval.observed(change.is_some());
+        next_poll = Instant::now() + interval.get();
+        if let Some(change) = change {
+            if sender.send(change).is_err() {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::common::batch::{self, BatchReport, MappingOperation, OperationOutcome};
+use crate::common::manager::MappingSpec;
+use crate::errors::Error;
+use crate::Gateway;
+
+impl Gateway {
+    /// Run a batch of add and remove operations, in order.
+    ///
+    /// If an add fails, the remaining operations are skipped and the mappings added by the
+    /// batch are removed again. Removed mappings are not restored. A failed remove does not
+    /// stop the batch.
+    pub fn run_batch(&self, operations: &[MappingOperation]) -> BatchReport {
+        let mut report = BatchReport { outcomes: Vec::new() };
+        let mut add_failed = false;
+        for operation in operations {
+            let outcome = if add_failed {
+                OperationOutcome::Skipped
+            } else {
+                match self.run_operation(operation) {
+                    Ok(()) => OperationOutcome::Applied,
+                    Err(e) => OperationOutcome::Failed(e),
+                }
+            };
+            add_failed |= batch::is_failed_add(operation, &outcome);
+            report.outcomes.push(outcome);
+        }
+        if add_failed {
+            for (index, mapping) in report.applied_adds(operations) {
+                report.outcomes[index] = match self.remove_port(mapping.protocol, mapping.external_port) {
+                    Ok(()) => OperationOutcome::RolledBack,
+                    Err(e) => OperationOutcome::RollbackFailed(e.into()),
+                };
+            }
+        }
+        report
+    }
+
+    fn run_operation(&self, operation: &MappingOperation) -> Result<(), Error> {
+        match operation {
+            MappingOperation::Add(MappingSpec {
+                protocol,
+                external_port,
+                local_addr,
+                lease,
+                description,
+            }) => {
+                self.add_port_with_lease(*protocol, *external_port, *local_addr, *lease, description)?;
+                Ok(())
+            }
+            MappingOperation::Remove {
+                protocol,
+                external_port,
+            } => Ok(self.remove_port(*protocol, *external_port)?),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::common::{cascade, SearchOptions};
+use crate::errors::CascadeError;
+use crate::{search_gateway, Gateway, PortMappingProtocol};
+
+impl Gateway {
+    /// Find the gateway in front of this one, on the network of its external address.
+    ///
+    /// The candidate routers of that network are searched in turn with `options`, whose
+    /// broadcast address only gives the port of the searches.
+    pub fn discover_upstream(&self, options: SearchOptions) -> Result<Gateway, CascadeError> {
+        let external_ip = self.get_external_ip()?;
+        let candidates = cascade::upstream_candidates(external_ip, options.broadcast_address.port());
+        if candidates.is_empty() {
+            return Err(CascadeError::NotCascaded);
+        }
+        self.search_upstream(candidates, &options)
+            .ok_or(CascadeError::NoUpstreamGateway)
+    }
+
+    /// Search the candidate upstream gateways in turn, returning the first one found.
+    pub(crate) fn search_upstream(&self, candidates: Vec<SocketAddr>, options: &SearchOptions) -> Option<Gateway> {
+        for candidate in candidates {
+            match search_gateway(cascade::candidate_search_options(options, candidate)) {
+                Ok(gateway) if gateway.addr != self.addr => return Some(gateway),
+                Ok(_) => {}
+                Err(e) => debug!("no upstream gateway at {candidate}: {e}"),
+            }
+        }
+        None
+    }
+
+    /// Map a port through this gateway and the `outer` gateway in front of it, found with
+    /// [`discover_upstream`](Self::discover_upstream).
+    ///
+    /// The same external port is mapped on both: on this gateway to `local_addr`, and on
+    /// the outer one to the external address of this gateway. If the outer mapping fails,
+    /// the inner one is removed again. Returns the external address of the outer gateway.
+    ///
+    /// The lease, `None` being infinite, is adjusted to the version of each gateway as for
+    /// `add_port_with_lease`.
+    pub fn add_cascaded_port(
+        &self,
+        outer: &Gateway,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<SocketAddr, CascadeError> {
+        let inner_ip = self.get_external_ip()?;
+        let outer_ip = outer.get_external_ip()?;
+        self.add_port_with_lease(protocol, external_port, local_addr, lease, description)
+            .map_err(CascadeError::InnerMapping)?;
+        let inner_addr = SocketAddr::new(inner_ip, external_port);
+        if let Err(error) = outer.add_port_with_lease(protocol, external_port, inner_addr, lease, description) {
+            if let Err(e) = self.remove_port(protocol, external_port) {
+                debug!("could not roll back the inner {protocol} mapping of external port {external_port}: {e}");
+            }
+            return Err(CascadeError::OuterMapping(error));
+        }
+        Ok(SocketAddr::new(outer_ip, external_port))
+    }
+}
@@ -0,0 +1,170 @@
+//! Operations and reports of mapping batches, shared by the sync and async gateways.
+
+use super::manager::MappingSpec;
+use crate::errors::Error;
+use crate::PortMappingProtocol;
+
+/// Default number of operations of a batch the async gateway runs at once.
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+
+/// An operation of a mapping batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MappingOperation {
+    /// Add a port mapping
+    Add(MappingSpec),
+    /// Remove the port mapping of an external port
+    Remove {
+        /// The protocol of the mapping
+        protocol: PortMappingProtocol,
+        /// The external port of the mapping
+        external_port: u16,
+    },
+}
+
+#[cfg(feature = "aio_tokio")]
+impl MappingOperation {
+    /// The protocol and external port of the mapping the operation applies to.
+    pub(crate) fn key(&self) -> (PortMappingProtocol, u16) {
+        match self {
+            MappingOperation::Add(mapping) => (mapping.protocol, mapping.external_port),
+            MappingOperation::Remove {
+                protocol,
+                external_port,
+            } => (*protocol, *external_port),
+        }
+    }
+}
+
+/// What happened to an operation of a mapping batch.
+#[derive(Debug)]
+pub enum OperationOutcome {
+    /// The operation was applied.
+    Applied,
+    /// The operation failed.
+    Failed(Error),
+    /// The mapping was added, then removed again because another add failed.
+    RolledBack,
+    /// The mapping was added, but could not be removed again after another add failed.
+    RollbackFailed(Error),
+    /// The operation was not run because an add failed first.
+    Skipped,
+}
+
+/// The outcome of each operation of a mapping batch, in the order of the operations.
+#[derive(Debug)]
+pub struct BatchReport {
+    /// The outcomes of the operations
+    pub outcomes: Vec<OperationOutcome>,
+}
+
+impl BatchReport {
+    /// Whether every operation was applied.
+    pub fn is_success(&self) -> bool {
+        self.outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, OperationOutcome::Applied))
+    }
+
+    /// Whether an add failed, which rolls back the batch.
+    pub fn add_failed(&self, operations: &[MappingOperation]) -> bool {
+        operations
+            .iter()
+            .zip(&self.outcomes)
+            .any(|(operation, outcome)| is_failed_add(operation, outcome))
+    }
+
+    /// The mappings added by the batch and the indices of their operations, to remove them
+    /// when rolling it back.
+    pub fn applied_adds<'a>(&self, operations: &'a [MappingOperation]) -> Vec<(usize, &'a MappingSpec)> {
+        operations
+            .iter()
+            .zip(&self.outcomes)
+            .enumerate()
+            .filter_map(|(index, (operation, outcome))| match (operation, outcome) {
+                (MappingOperation::Add(mapping), OperationOutcome::Applied) => Some((index, mapping)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Whether the outcome is that of an add that failed.
+pub fn is_failed_add(operation: &MappingOperation, outcome: &OperationOutcome) -> bool {
+    matches!(
+        (operation, outcome),
+        (MappingOperation::Add(_), OperationOutcome::Failed(_))
+    )
+}
+
+/// The indices of the operations, grouped by the mapping they apply to, in batch order
+/// within each group. The operations of a group must run in turn, as removing and adding
+/// the same port depend on each other, while groups can run concurrently.
+#[cfg(feature = "aio_tokio")]
+pub fn chains(operations: &[MappingOperation]) -> Vec<Vec<usize>> {
+    let mut chains: Vec<((PortMappingProtocol, u16), Vec<usize>)> = Vec::new();
+    for (index, operation) in operations.iter().enumerate() {
+        match chains.iter_mut().find(|(key, _)| *key == operation.key()) {
+            Some((_, chain)) => chain.push(index),
+            None => chains.push((operation.key(), vec![index])),
+        }
+    }
+    chains.into_iter().map(|(_, chain)| chain).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::AddPortError;
+
+    fn add(external_port: u16) -> MappingOperation {
+        MappingOperation::Add(MappingSpec {
+            protocol: PortMappingProtocol::UDP,
+            external_port,
+            local_addr: "192.168.1.5:5000".parse().unwrap(),
+            lease: None,
+            description: "test".to_string(),
+        })
+    }
+
+    #[test]
+    fn failed_add_rolls_back_applied_adds() {
+        let operations = [
+            add(1000),
+            MappingOperation::Remove {
+                protocol: PortMappingProtocol::TCP,
+                external_port: 2000,
+            },
+            add(3000),
+            add(4000),
+        ];
+        let report = BatchReport {
+            outcomes: vec![
+                OperationOutcome::Applied,
+                OperationOutcome::Applied,
+                OperationOutcome::Failed(AddPortError::PortInUse.into()),
+                OperationOutcome::Skipped,
+            ],
+        };
+        assert!(!report.is_success());
+        assert!(report.add_failed(&operations));
+        let applied = report.applied_adds(&operations);
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].0, 0);
+    }
+
+    #[cfg(feature = "aio_tokio")]
+    #[test]
+    fn operations_on_the_same_mapping_are_chained() {
+        let remove = |protocol, external_port| MappingOperation::Remove {
+            protocol,
+            external_port,
+        };
+        let operations = [
+            remove(PortMappingProtocol::UDP, 1000),
+            remove(PortMappingProtocol::TCP, 1000),
+            add(1000),
+            add(2000),
+        ];
+        assert_eq!(chains(&operations), vec![vec![0, 2], vec![1], vec![3]]);
+    }
+}
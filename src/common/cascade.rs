@@ -0,0 +1,57 @@
+//! Port mappings through cascaded routers, where the external address of a gateway is on
+//! the network of another gateway.
+
+use std::net::{IpAddr, SocketAddr};
+
+use super::options::SearchOptions;
+use super::topology::AddressKind;
+
+/// Addresses to search for the gateway in front of one whose external address is
+/// `external_ip`: the first and last host of its /24, where routers usually sit. Empty if
+/// the address is public.
+///
+/// The upstream gateway does not answer the multicast searches of hosts behind the inner
+/// one, so it is searched with unicast requests to each candidate.
+pub fn upstream_candidates(external_ip: IpAddr, ssdp_port: u16) -> Vec<SocketAddr> {
+    let ip = match external_ip {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(_) => return Vec::new(),
+    };
+    match AddressKind::of(external_ip) {
+        AddressKind::Private | AddressKind::CarrierGradeNat | AddressKind::LinkLocal => {}
+        _ => return Vec::new(),
+    }
+    [1, 254]
+        .into_iter()
+        .map(|host| super::network_host(ip, host))
+        .filter(|&candidate| candidate != ip)
+        .map(|candidate| SocketAddr::new(IpAddr::V4(candidate), ssdp_port))
+        .collect()
+}
+
+/// The options of the unicast search of a candidate upstream gateway.
+pub fn candidate_search_options(options: &SearchOptions, candidate: SocketAddr) -> SearchOptions {
+    SearchOptions {
+        bind_addr: options.bind_addr,
+        broadcast_address: candidate,
+        timeout: options.timeout,
+        single_search_timeout: options.single_search_timeout,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_are_routers_of_the_external_network() {
+        let candidates = |ip: &str| upstream_candidates(ip.parse().unwrap(), 1900);
+        assert_eq!(
+            candidates("192.168.0.2"),
+            ["192.168.0.1:1900", "192.168.0.254:1900"].map(|addr| addr.parse().unwrap())
+        );
+        assert_eq!(candidates("10.0.0.1"), ["10.0.0.254:1900".parse().unwrap()]);
+        assert!(candidates("203.0.113.7").is_empty());
+        assert!(candidates("fd00::2").is_empty());
+    }
+}
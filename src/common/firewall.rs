@@ -0,0 +1,308 @@
+//! Messages and responses of the `WANIPv6FirewallControl:1` service.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+use super::messages;
+use super::parsing::RequestResult;
+use crate::errors::{PinholeError, RequestError, UpnpErrorCode};
+use crate::PortMappingProtocol;
+
+/// Service type of the IPv6 firewall control service.
+pub const WAN_IPV6_FIREWALL_CONTROL_SERVICE: &str = "urn:schemas-upnp-org:service:WANIPv6FirewallControl:1";
+
+pub const GET_FIREWALL_STATUS_ACTION: &str = "GetFirewallStatus";
+
+pub const GET_OUTBOUND_PINHOLE_TIMEOUT_ACTION: &str = "GetOutboundPinholeTimeout";
+
+pub const ADD_PINHOLE_ACTION: &str = "AddPinhole";
+
+pub const UPDATE_PINHOLE_ACTION: &str = "UpdatePinhole";
+
+pub const DELETE_PINHOLE_ACTION: &str = "DeletePinhole";
+
+pub const GET_PINHOLE_PACKETS_ACTION: &str = "GetPinholePackets";
+
+pub const CHECK_PINHOLE_WORKING_ACTION: &str = "CheckPinholeWorking";
+
+/// Identifier of a pinhole, as assigned by the gateway on `AddPinhole`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PinholeId(pub u16);
+
+impl fmt::Display for PinholeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The IANA protocol number a pinhole applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PinholeProtocol {
+    /// TCP protocol (6)
+    TCP,
+    /// UDP protocol (17)
+    UDP,
+    /// SCTP protocol (132)
+    SCTP,
+    /// UDP-Lite protocol (136)
+    UDPLite,
+    /// Any protocol (the wildcard value 65535)
+    Any,
+    /// Any other IANA protocol number
+    Other(u16),
+}
+
+impl PinholeProtocol {
+    /// The IANA protocol number sent to the gateway.
+    pub fn number(self) -> u16 {
+        match self {
+            PinholeProtocol::TCP => 6,
+            PinholeProtocol::UDP => 17,
+            PinholeProtocol::SCTP => 132,
+            PinholeProtocol::UDPLite => 136,
+            PinholeProtocol::Any => 65535,
+            PinholeProtocol::Other(number) => number,
+        }
+    }
+}
+
+impl From<u16> for PinholeProtocol {
+    fn from(number: u16) -> PinholeProtocol {
+        match number {
+            6 => PinholeProtocol::TCP,
+            17 => PinholeProtocol::UDP,
+            132 => PinholeProtocol::SCTP,
+            136 => PinholeProtocol::UDPLite,
+            65535 => PinholeProtocol::Any,
+            number => PinholeProtocol::Other(number),
+        }
+    }
+}
+
+impl From<PortMappingProtocol> for PinholeProtocol {
+    fn from(protocol: PortMappingProtocol) -> PinholeProtocol {
+        match protocol {
+            PortMappingProtocol::TCP => PinholeProtocol::TCP,
+            PortMappingProtocol::UDP => PinholeProtocol::UDP,
+        }
+    }
+}
+
+impl fmt::Display for PinholeProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.number())
+    }
+}
+
+/// State of the gateway's IPv6 firewall as returned by GetFirewallStatus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FirewallStatus {
+    /// Whether the firewall is enabled
+    pub firewall_enabled: bool,
+    /// Whether the gateway allows creating inbound pinholes
+    pub inbound_pinhole_allowed: bool,
+}
+
+fn remote_host(remote_host: Option<IpAddr>) -> String {
+    remote_host.map(|ip| ip.to_string()).unwrap_or_default()
+}
+
+pub fn format_get_firewall_status_message() -> String {
+    messages::format_action_message(WAN_IPV6_FIREWALL_CONTROL_SERVICE, GET_FIREWALL_STATUS_ACTION, &[])
+}
+
+pub fn format_get_outbound_pinhole_timeout_message(
+    remote_host_addr: Option<IpAddr>,
+    remote_port: u16,
+    internal_addr: SocketAddr,
+    protocol: PinholeProtocol,
+) -> String {
+    messages::format_action_message(
+        WAN_IPV6_FIREWALL_CONTROL_SERVICE,
+        GET_OUTBOUND_PINHOLE_TIMEOUT_ACTION,
+        &[
+            ("RemoteHost", remote_host(remote_host_addr)),
+            ("RemotePort", remote_port.to_string()),
+            ("InternalClient", internal_addr.ip().to_string()),
+            ("InternalPort", internal_addr.port().to_string()),
+            ("Protocol", protocol.to_string()),
+        ],
+    )
+}
+
+pub fn format_add_pinhole_message(
+    remote_host_addr: Option<IpAddr>,
+    remote_port: u16,
+    internal_addr: SocketAddr,
+    protocol: PinholeProtocol,
+    lease_time: u32,
+) -> String {
+    messages::format_action_message(
+        WAN_IPV6_FIREWALL_CONTROL_SERVICE,
+        ADD_PINHOLE_ACTION,
+        &[
+            ("RemoteHost", remote_host(remote_host_addr)),
+            ("RemotePort", remote_port.to_string()),
+            ("InternalClient", internal_addr.ip().to_string()),
+            ("InternalPort", internal_addr.port().to_string()),
+            ("Protocol", protocol.to_string()),
+            ("LeaseTime", lease_time.to_string()),
+        ],
+    )
+}
+
+pub fn format_update_pinhole_message(id: PinholeId, lease_time: u32) -> String {
+    messages::format_action_message(
+        WAN_IPV6_FIREWALL_CONTROL_SERVICE,
+        UPDATE_PINHOLE_ACTION,
+        &[("UniqueID", id.to_string()), ("NewLeaseTime", lease_time.to_string())],
+    )
+}
+
+pub fn format_pinhole_id_message(action: &str, id: PinholeId) -> String {
+    messages::format_action_message(
+        WAN_IPV6_FIREWALL_CONTROL_SERVICE,
+        action,
+        &[("UniqueID", id.to_string())],
+    )
+}
+
+pub fn convert_pinhole_error(err: RequestError) -> PinholeError {
+    match err {
+        RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => PinholeError::ActionNotAuthorized,
+        RequestError::ErrorCode(UpnpErrorCode::Other(701), _) => PinholeError::PinholeSpaceExhausted,
+        RequestError::ErrorCode(UpnpErrorCode::Other(702), _) => PinholeError::FirewallDisabled,
+        RequestError::ErrorCode(UpnpErrorCode::Other(703), _) => PinholeError::InboundPinholeNotAllowed,
+        RequestError::ErrorCode(UpnpErrorCode::Other(704), _) => PinholeError::NoSuchEntry,
+        RequestError::ErrorCode(UpnpErrorCode::Other(705), _) => PinholeError::ProtocolNotSupported,
+        RequestError::ErrorCode(UpnpErrorCode::Other(706), _) => PinholeError::InternalPortWildcardingNotAllowed,
+        RequestError::ErrorCode(UpnpErrorCode::Other(707), _) => PinholeError::ProtocolWildcardingNotAllowed,
+        RequestError::ErrorCode(UpnpErrorCode::Other(708), _) => PinholeError::WildCardNotPermittedInSrcIp,
+        RequestError::ErrorCode(UpnpErrorCode::Other(709), _) => PinholeError::NoTrafficReceived,
+        e => PinholeError::RequestError(e),
+    }
+}
+
+pub fn parse_get_firewall_status_response(result: RequestResult) -> Result<FirewallStatus, PinholeError> {
+    let resp = result.map_err(convert_pinhole_error)?;
+    Ok(FirewallStatus {
+        firewall_enabled: resp.bool_argument("FirewallEnabled")?,
+        inbound_pinhole_allowed: resp.bool_argument("InboundPinholeAllowed")?,
+    })
+}
+
+pub fn parse_get_outbound_pinhole_timeout_response(result: RequestResult) -> Result<u32, PinholeError> {
+    Ok(result
+        .map_err(convert_pinhole_error)?
+        .parse_argument("OutboundPinholeTimeout")?)
+}
+
+pub fn parse_add_pinhole_response(result: RequestResult) -> Result<PinholeId, PinholeError> {
+    Ok(PinholeId(
+        result.map_err(convert_pinhole_error)?.parse_argument("UniqueID")?,
+    ))
+}
+
+pub fn parse_get_pinhole_packets_response(result: RequestResult) -> Result<u32, PinholeError> {
+    Ok(result
+        .map_err(convert_pinhole_error)?
+        .parse_argument("PinholePackets")?)
+}
+
+pub fn parse_check_pinhole_working_response(result: RequestResult) -> Result<bool, PinholeError> {
+    Ok(result.map_err(convert_pinhole_error)?.bool_argument("IsWorking")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::parsing::parse_response;
+
+    fn response(action: &str, args: &str) -> RequestResult {
+        let text = format!(
+            r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:{action}Response xmlns:u="{WAN_IPV6_FIREWALL_CONTROL_SERVICE}">{args}</u:{action}Response>
+</s:Body>
+</s:Envelope>"#
+        );
+        parse_response(text, &format!("{action}Response"))
+    }
+
+    #[test]
+    fn add_pinhole_message_uses_protocol_numbers_and_wildcards() {
+        let body = format_add_pinhole_message(
+            None,
+            0,
+            "[2001:db8::5]:8080".parse().unwrap(),
+            PortMappingProtocol::UDP.into(),
+            3600,
+        );
+        assert!(body.contains("<RemoteHost></RemoteHost>\n<RemotePort>0</RemotePort>"));
+        assert!(body.contains("<InternalClient>2001:db8::5</InternalClient>"));
+        assert!(body.contains("<InternalPort>8080</InternalPort>\n<Protocol>17</Protocol>"));
+        assert!(body.contains("<LeaseTime>3600</LeaseTime>"));
+    }
+
+    #[test]
+    fn protocol_numbers_round_trip() {
+        for number in [6, 17, 132, 136, 65535, 47] {
+            assert_eq!(PinholeProtocol::from(number).number(), number);
+        }
+        assert_eq!(PinholeProtocol::from(47), PinholeProtocol::Other(47));
+    }
+
+    #[test]
+    fn parse_firewall_responses() {
+        let status = parse_get_firewall_status_response(response(
+            GET_FIREWALL_STATUS_ACTION,
+            "<FirewallEnabled>1</FirewallEnabled><InboundPinholeAllowed>0</InboundPinholeAllowed>",
+        ))
+        .unwrap();
+        assert_eq!(
+            status,
+            FirewallStatus {
+                firewall_enabled: true,
+                inbound_pinhole_allowed: false
+            }
+        );
+
+        let id = parse_add_pinhole_response(response(ADD_PINHOLE_ACTION, "<UniqueID>42</UniqueID>")).unwrap();
+        assert_eq!(id, PinholeId(42));
+
+        assert!(parse_check_pinhole_working_response(response(
+            CHECK_PINHOLE_WORKING_ACTION,
+            "<IsWorking>true</IsWorking>"
+        ))
+        .unwrap());
+    }
+
+    #[test]
+    fn pinhole_error_codes() {
+        assert!(matches!(
+            parse_add_pinhole_response(Err(RequestError::ErrorCode(
+                UpnpErrorCode::Other(701),
+                "PinholeSpaceExhausted".into()
+            ))),
+            Err(PinholeError::PinholeSpaceExhausted)
+        ));
+        assert!(matches!(
+            parse_check_pinhole_working_response(Err(RequestError::ErrorCode(
+                UpnpErrorCode::Other(709),
+                "NoTrafficReceived".into()
+            ))),
+            Err(PinholeError::NoTrafficReceived)
+        ));
+        assert!(matches!(
+            parse_get_pinhole_packets_response(Err(RequestError::ErrorCode(
+                UpnpErrorCode::ActionFailed,
+                "ActionFailed".into()
+            ))),
+            Err(PinholeError::RequestError(RequestError::ErrorCode(
+                UpnpErrorCode::ActionFailed,
+                _
+            )))
+        ));
+    }
+}
@@ -0,0 +1,349 @@
+//! Messages of the GENA eventing protocol used to subscribe to state variable changes.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use xmltree::Element;
+
+use crate::errors::{RequestError, SubscriptionError};
+
+/// Maximum size of the head of a `NOTIFY` request.
+pub const MAX_HEAD_BYTES: usize = 8 * 1024;
+
+/// The `200 OK` reply to a `NOTIFY` request.
+pub const NOTIFY_OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// Build the reply to a `NOTIFY` request that is rejected with `status`.
+pub fn notify_error(status: u16) -> String {
+    let reason = match status {
+        412 => "Precondition Failed",
+        413 => "Payload Too Large",
+        _ => "Bad Request",
+    };
+    format!("HTTP/1.1 {status} {reason}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+}
+
+/// Status of a WAN connection, as reported by the `ConnectionStatus` state variable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConnectionStatus {
+    /// The connection is not configured
+    Unconfigured,
+    /// The connection is being established
+    Connecting,
+    /// The connection is being authenticated
+    Authenticating,
+    /// The connection is established
+    Connected,
+    /// The connection will be torn down
+    PendingDisconnect,
+    /// The connection is being torn down
+    Disconnecting,
+    /// The connection is down
+    Disconnected,
+    /// A value not defined by the specification
+    Other(String),
+}
+
+impl From<&str> for ConnectionStatus {
+    fn from(status: &str) -> ConnectionStatus {
+        match status {
+            "Unconfigured" => ConnectionStatus::Unconfigured,
+            "Connecting" => ConnectionStatus::Connecting,
+            "Authenticating" => ConnectionStatus::Authenticating,
+            "Connected" => ConnectionStatus::Connected,
+            "PendingDisconnect" => ConnectionStatus::PendingDisconnect,
+            "Disconnecting" => ConnectionStatus::Disconnecting,
+            "Disconnected" => ConnectionStatus::Disconnected,
+            other => ConnectionStatus::Other(other.to_string()),
+        }
+    }
+}
+
+/// A change of an evented state variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertyChange {
+    /// The external IP address changed. `None` if the gateway reported an empty or invalid address.
+    ExternalIpAddress(Option<IpAddr>),
+    /// The status of the WAN connection changed.
+    ConnectionStatus(ConnectionStatus),
+    /// The number of port mappings changed.
+    PortMappingNumberOfEntries(u32),
+    /// Any other state variable changed.
+    Other {
+        /// Name of the state variable
+        name: String,
+        /// New value of the state variable
+        value: String,
+    },
+}
+
+impl PropertyChange {
+    fn new(name: &str, value: &str) -> PropertyChange {
+        let value = value.trim();
+        match name {
+            "ExternalIPAddress" => PropertyChange::ExternalIpAddress(value.parse().ok()),
+            "ConnectionStatus" => PropertyChange::ConnectionStatus(ConnectionStatus::from(value)),
+            "PortMappingNumberOfEntries" => match value.parse() {
+                Ok(entries) => PropertyChange::PortMappingNumberOfEntries(entries),
+                Err(_) => PropertyChange::Other {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                },
+            },
+            _ => PropertyChange::Other {
+                name: name.to_string(),
+                value: value.to_string(),
+            },
+        }
+    }
+}
+
+/// The state variable changes delivered by one `NOTIFY` request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventNotification {
+    /// Id of the subscription the event belongs to
+    pub sid: String,
+    /// Sequence number of the event within the subscription, starting at 0
+    pub seq: u32,
+    /// The changed state variables
+    pub changes: Vec<PropertyChange>,
+}
+
+/// Absolute url to subscribe to, given a service's `eventSubURL`.
+pub fn event_url(addr: &SocketAddr, event_sub_url: &str) -> String {
+    if event_sub_url.starts_with("http://") {
+        event_sub_url.to_string()
+    } else {
+        format!("http://{addr}{event_sub_url}")
+    }
+}
+
+/// Value of the `CALLBACK` header for a listener reachable at `addr`.
+pub fn callback(addr: &SocketAddr) -> String {
+    format!("<http://{addr}/>")
+}
+
+/// Value of the `TIMEOUT` header requesting the given duration.
+pub fn format_timeout(timeout: Duration) -> String {
+    format!("Second-{}", timeout.as_secs().max(1))
+}
+
+/// Parse a `TIMEOUT` header (`Second-<n>` or `infinite`).
+pub fn parse_timeout(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("infinite") || value.eq_ignore_ascii_case("second-infinite") {
+        return Some(Duration::MAX);
+    }
+    if !value.get(..7)?.eq_ignore_ascii_case("second-") {
+        return None;
+    }
+    value[7..].trim().parse().ok().map(Duration::from_secs)
+}
+
+/// How long to wait before renewing a subscription granted for `timeout`, leaving enough time
+/// for the renewal to reach the gateway before the subscription expires.
+pub fn renew_interval(timeout: Duration) -> Duration {
+    const MAX_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+    (timeout / 2).clamp(Duration::from_secs(1), MAX_INTERVAL)
+}
+
+/// Check the reply to a `SUBSCRIBE` request, returning the subscription id and the duration
+/// the subscription was granted for.
+pub fn parse_subscribe_response(
+    status: u16,
+    sid: Option<&str>,
+    timeout: Option<&str>,
+    requested: Duration,
+) -> Result<(String, Duration), SubscriptionError> {
+    match status {
+        200 => {
+            let sid = sid
+                .map(str::trim)
+                .filter(|sid| !sid.is_empty())
+                .ok_or_else(|| RequestError::InvalidResponse("SID header is missing".to_string()))?;
+            let timeout = timeout.and_then(parse_timeout).unwrap_or(requested);
+            Ok((sid.to_string(), timeout))
+        }
+        412 => Err(SubscriptionError::PreconditionFailed),
+        status => Err(SubscriptionError::UnexpectedStatus(status)),
+    }
+}
+
+/// Check the reply to an `UNSUBSCRIBE` request.
+pub fn parse_unsubscribe_response(status: u16) -> Result<(), SubscriptionError> {
+    match status {
+        200 => Ok(()),
+        412 => Err(SubscriptionError::PreconditionFailed),
+        status => Err(SubscriptionError::UnexpectedStatus(status)),
+    }
+}
+
+/// The head of an incoming HTTP request.
+pub struct RequestHead {
+    /// Request method
+    pub method: String,
+    /// Header names and values
+    pub headers: Vec<(String, String)>,
+}
+
+impl RequestHead {
+    /// Value of the header `name`, which is matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Length of the request body, which is 0 if there is no `Content-Length` header.
+    pub fn content_length(&self) -> Option<usize> {
+        match self.header("Content-Length") {
+            Some(length) => length.trim().parse().ok(),
+            None => Some(0),
+        }
+    }
+}
+
+/// Position right after the `\r\n\r\n` ending the head of a request, if it was received.
+pub fn head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+/// Parse the head of an HTTP request.
+pub fn parse_request_head(head: &[u8]) -> Option<RequestHead> {
+    let head = std::str::from_utf8(head).ok()?;
+    let mut lines = head.split("\r\n");
+    let method = lines.next()?.split_whitespace().next()?.to_string();
+    let headers = lines
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+    Some(RequestHead { method, headers })
+}
+
+/// Parse a `NOTIFY` request, returning the status to reply with if it is invalid.
+pub fn parse_notification(head: &RequestHead, body: &[u8]) -> Result<EventNotification, u16> {
+    if !head.method.eq_ignore_ascii_case("NOTIFY") {
+        return Err(400);
+    }
+    if head.header("NT") != Some("upnp:event") || head.header("NTS") != Some("upnp:propchange") {
+        return Err(412);
+    }
+    let sid = head.header("SID").ok_or(412u16)?.to_string();
+    let seq = head.header("SEQ").and_then(|seq| seq.parse().ok()).unwrap_or(0);
+    let changes = parse_property_set(body).ok_or(400u16)?;
+    Ok(EventNotification { sid, seq, changes })
+}
+
+/// Parse the `propertyset` body of a `NOTIFY` request.
+pub fn parse_property_set(body: &[u8]) -> Option<Vec<PropertyChange>> {
+    let root = Element::parse(body).ok()?;
+    if root.name != "propertyset" {
+        return None;
+    }
+    Some(
+        root.children
+            .iter()
+            .filter_map(|child| child.as_element())
+            .filter(|property| property.name == "property")
+            .flat_map(|property| property.children.iter().filter_map(|child| child.as_element()))
+            .map(|variable| {
+                let value = variable.get_text().unwrap_or_default();
+                PropertyChange::new(&variable.name, &value)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::options::DEFAULT_SUBSCRIPTION_TIMEOUT;
+
+    const NOTIFY: &str = "NOTIFY / HTTP/1.1\r\n\
+        HOST: 192.168.1.10:49152\r\n\
+        CONTENT-TYPE: text/xml; charset=\"utf-8\"\r\n\
+        NT: upnp:event\r\n\
+        NTS: upnp:propchange\r\n\
+        SID: uuid:6a1c4e6e-0000-0000-0000-000000000001\r\n\
+        SEQ: 3\r\n\
+        Content-Length: 0\r\n\r\n";
+
+    const PROPERTY_SET: &str = r#"<?xml version="1.0"?>
+<e:propertyset xmlns:e="urn:schemas-upnp-org:event-1-0">
+<e:property><ExternalIPAddress>203.0.113.7</ExternalIPAddress></e:property>
+<e:property><ConnectionStatus>Connected</ConnectionStatus></e:property>
+<e:property><PortMappingNumberOfEntries>4</PortMappingNumberOfEntries></e:property>
+<e:property><PossibleConnectionTypes>IP_Routed</PossibleConnectionTypes></e:property>
+</e:propertyset>"#;
+
+    #[test]
+    fn timeout_header() {
+        assert_eq!(format_timeout(Duration::from_secs(1800)), "Second-1800");
+        assert_eq!(parse_timeout("Second-300"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_timeout("second-300"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_timeout("infinite"), Some(Duration::MAX));
+        assert_eq!(parse_timeout("300"), None);
+        assert_eq!(renew_interval(Duration::from_secs(300)), Duration::from_secs(150));
+        assert_eq!(renew_interval(Duration::MAX), Duration::from_secs(24 * 60 * 60));
+    }
+
+    #[test]
+    fn subscribe_response() {
+        let (sid, timeout) =
+            parse_subscribe_response(200, Some("uuid:1"), Some("Second-600"), DEFAULT_SUBSCRIPTION_TIMEOUT).unwrap();
+        assert_eq!(sid, "uuid:1");
+        assert_eq!(timeout, Duration::from_secs(600));
+
+        let (_, timeout) = parse_subscribe_response(200, Some("uuid:1"), None, DEFAULT_SUBSCRIPTION_TIMEOUT).unwrap();
+        assert_eq!(timeout, DEFAULT_SUBSCRIPTION_TIMEOUT);
+
+        assert!(matches!(
+            parse_subscribe_response(200, None, None, DEFAULT_SUBSCRIPTION_TIMEOUT),
+            Err(SubscriptionError::RequestError(RequestError::InvalidResponse(_)))
+        ));
+        assert!(matches!(
+            parse_subscribe_response(412, None, None, DEFAULT_SUBSCRIPTION_TIMEOUT),
+            Err(SubscriptionError::PreconditionFailed)
+        ));
+    }
+
+    #[test]
+    fn parse_notify_request() {
+        let end = head_end(NOTIFY.as_bytes()).unwrap();
+        assert_eq!(end, NOTIFY.len());
+        let head = parse_request_head(&NOTIFY.as_bytes()[..end]).unwrap();
+        assert_eq!(head.content_length(), Some(0));
+        let notification = parse_notification(&head, PROPERTY_SET.as_bytes()).unwrap();
+        assert_eq!(notification.sid, "uuid:6a1c4e6e-0000-0000-0000-000000000001");
+        assert_eq!(notification.seq, 3);
+        assert_eq!(
+            notification.changes,
+            vec![
+                PropertyChange::ExternalIpAddress(Some("203.0.113.7".parse().unwrap())),
+                PropertyChange::ConnectionStatus(ConnectionStatus::Connected),
+                PropertyChange::PortMappingNumberOfEntries(4),
+                PropertyChange::Other {
+                    name: "PossibleConnectionTypes".to_string(),
+                    value: "IP_Routed".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_notify_requests_are_rejected() {
+        let head = parse_request_head(b"NOTIFY / HTTP/1.1\r\nNT: upnp:event\r\nSID: uuid:1\r\n\r\n").unwrap();
+        assert_eq!(parse_notification(&head, PROPERTY_SET.as_bytes()), Err(412));
+        let head = parse_request_head(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(parse_notification(&head, b""), Err(400));
+        let end = head_end(NOTIFY.as_bytes()).unwrap();
+        let head = parse_request_head(&NOTIFY.as_bytes()[..end]).unwrap();
+        assert_eq!(parse_notification(&head, b"<html/>"), Err(400));
+    }
+}
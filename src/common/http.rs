@@ -0,0 +1,141 @@
+//! Minimal HTTP/1.1 client messages, used to send control and event subscription requests
+//! over connections bound to a local address, which attohttpc does not support.
+
+use std::io;
+use std::net::SocketAddr;
+
+use super::gena::head_end;
+
+/// Format a SOAP control request. The connection is closed after the response.
+pub fn format_soap_request(addr: SocketAddr, path: &str, soap_action: &str, body: &str) -> String {
+    format_request(
+        "POST",
+        addr,
+        path,
+        &[("SOAPAction", soap_action), ("Content-Type", "text/xml")],
+        body,
+    )
+}
+
+/// Format a request with the given headers. The connection is closed after the response.
+pub fn format_request(method: &str, addr: SocketAddr, path: &str, headers: &[(&str, &str)], body: &str) -> String {
+    let mut request = format!("{method} {path} HTTP/1.1\r\nHost: {addr}\r\n");
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    ));
+    request
+}
+
+/// A response read by [`parse_response`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Response {
+    /// Status code of the response
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    /// Body of the response, decoded if it was chunked
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// The value of the header with the given name, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse a complete HTTP response, read until the connection was closed. The body is
+/// returned whatever the status, as SOAP faults come with 500.
+pub fn parse_response(bytes: &[u8]) -> io::Result<Response> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let end = head_end(bytes).ok_or_else(|| invalid("incomplete HTTP response head"))?;
+    let head = std::str::from_utf8(&bytes[..end]).map_err(|_| invalid("invalid HTTP response head"))?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("invalid HTTP status line"))?;
+    let mut response = Response {
+        status,
+        headers: lines
+            .filter_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                Some((key.trim().to_string(), value.trim().to_string()))
+            })
+            .collect(),
+        body: Vec::new(),
+    };
+
+    let body = &bytes[end..];
+    if response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+    {
+        response.body = decode_chunked(body).ok_or_else(|| invalid("invalid chunked body"))?;
+        return Ok(response);
+    }
+    response.body = match response.header("Content-Length").map(str::parse::<usize>) {
+        Some(Ok(length)) if length <= body.len() => body[..length].to_vec(),
+        Some(_) => return Err(invalid("truncated HTTP response body")),
+        None => body.to_vec(),
+    };
+    Ok(response)
+}
+
+fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|window| window == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_are_parsed() {
+        let request = format_soap_request("192.168.1.1:5000".parse().unwrap(), "/ctl", "\"a#b\"", "<x/>");
+        assert!(request.starts_with("POST /ctl HTTP/1.1\r\nHost: 192.168.1.1:5000\r\n"));
+        assert!(request.ends_with("Content-Length: 4\r\nConnection: close\r\n\r\n<x/>"));
+
+        let request = format_request(
+            "UNSUBSCRIBE",
+            "192.168.1.1:5000".parse().unwrap(),
+            "/evt",
+            &[("SID", "uuid:1")],
+            "",
+        );
+        assert!(request.starts_with("UNSUBSCRIBE /evt HTTP/1.1\r\nHost: 192.168.1.1:5000\r\nSID: uuid:1\r\n"));
+
+        let response =
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\nsid: uuid:1\r\n\r\n<x/>trailing").unwrap();
+        assert_eq!((response.status, response.body.as_slice()), (200, &b"<x/>"[..]));
+        assert_eq!(response.header("SID"), Some("uuid:1"));
+
+        let response =
+            parse_response(b"HTTP/1.1 500 Internal Server Error\r\nconnection: close\r\n\r\n<fault/>").unwrap();
+        assert_eq!((response.status, response.body.as_slice()), (500, &b"<fault/>"[..]));
+
+        let response = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\n<x/\r\n1;ext\r\n>\r\n0\r\n\r\n";
+        assert_eq!(parse_response(response).unwrap().body, b"<x/>");
+
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n<x/>").is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Messages and responses of the `LANHostConfigManagement:1` service.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use super::messages;
+use super::parsing::{RequestReponse, RequestResult};
+use crate::errors::RequestError;
+
+/// Service type of the LAN host configuration service.
+pub const LAN_HOST_CONFIG_MANAGEMENT_SERVICE: &str = "urn:schemas-upnp-org:service:LANHostConfigManagement:1";
+
+pub const GET_DHCP_SERVER_CONFIGURABLE_ACTION: &str = "GetDHCPServerConfigurable";
+
+pub const GET_SUBNET_MASK_ACTION: &str = "GetSubnetMask";
+
+pub const GET_IP_ROUTERS_LIST_ACTION: &str = "GetIPRoutersList";
+
+pub const GET_DOMAIN_NAME_ACTION: &str = "GetDomainName";
+
+pub const GET_ADDRESS_RANGE_ACTION: &str = "GetAddressRange";
+
+pub const GET_DNS_SERVERS_ACTION: &str = "GetDNSServers";
+
+pub const GET_RESERVED_ADDRESSES_ACTION: &str = "GetReservedAddresses";
+
+/// Range of addresses handed out by the gateway's DHCP server as returned by GetAddressRange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+    /// First address of the range
+    pub min_address: Ipv4Addr,
+    /// Last address of the range
+    pub max_address: Ipv4Addr,
+}
+
+impl AddressRange {
+    /// Whether the DHCP server may hand out the given address.
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        self.min_address <= addr && addr <= self.max_address
+    }
+}
+
+pub fn format_lan_host_config_message(service_type: &str, action: &str) -> String {
+    messages::format_action_message(service_type, action, &[])
+}
+
+fn invalid(name: &str) -> RequestError {
+    RequestError::InvalidResponse(format!("Field {name} is invalid"))
+}
+
+/// Parse a comma separated list of addresses, which is empty if the argument is empty.
+fn address_list_argument(resp: &RequestReponse, name: &str) -> Result<Vec<IpAddr>, RequestError> {
+    resp.argument(name)?
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(|addr| addr.parse().map_err(|_| invalid(name)))
+        .collect()
+}
+
+pub fn parse_get_subnet_mask_response(result: RequestResult) -> Result<Ipv4Addr, RequestError> {
+    result?.parse_argument("NewSubnetMask")
+}
+
+pub fn parse_get_address_range_response(result: RequestResult) -> Result<AddressRange, RequestError> {
+    let resp = result?;
+    Ok(AddressRange {
+        min_address: resp.parse_argument("NewMinAddress")?,
+        max_address: resp.parse_argument("NewMaxAddress")?,
+    })
+}
+
+pub fn parse_address_list_response(result: RequestResult, argument: &str) -> Result<Vec<IpAddr>, RequestError> {
+    address_list_argument(&result?, argument)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::parsing::parse_response;
+
+    fn response(action: &str, args: &str) -> RequestResult {
+        let text = format!(
+            r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:{action}Response xmlns:u="{LAN_HOST_CONFIG_MANAGEMENT_SERVICE}">{args}</u:{action}Response>
+</s:Body>
+</s:Envelope>"#
+        );
+        parse_response(text, &format!("{action}Response"))
+    }
+
+    #[test]
+    fn parse_address_range() {
+        let range = parse_get_address_range_response(response(
+            GET_ADDRESS_RANGE_ACTION,
+            "<NewMinAddress>192.168.1.100</NewMinAddress><NewMaxAddress>192.168.1.200</NewMaxAddress>",
+        ))
+        .unwrap();
+        assert!(range.contains(Ipv4Addr::new(192, 168, 1, 150)));
+        assert!(!range.contains(Ipv4Addr::new(192, 168, 1, 20)));
+    }
+
+    #[test]
+    fn parse_address_lists() {
+        let servers = parse_address_list_response(
+            response(
+                GET_DNS_SERVERS_ACTION,
+                "<NewDNSServers>192.168.1.1, 8.8.8.8</NewDNSServers>",
+            ),
+            "NewDNSServers",
+        )
+        .unwrap();
+        assert_eq!(
+            servers,
+            vec![
+                IpAddr::from(Ipv4Addr::new(192, 168, 1, 1)),
+                IpAddr::from(Ipv4Addr::new(8, 8, 8, 8))
+            ]
+        );
+
+        let reserved = parse_address_list_response(
+            response(
+                GET_RESERVED_ADDRESSES_ACTION,
+                "<NewReservedAddresses></NewReservedAddresses>",
+            ),
+            "NewReservedAddresses",
+        )
+        .unwrap();
+        assert!(reserved.is_empty());
+
+        assert!(parse_address_list_response(
+            response(GET_IP_ROUTERS_LIST_ACTION, "<NewIPRouters>router</NewIPRouters>"),
+            "NewIPRouters",
+        )
+        .is_err());
+    }
+}
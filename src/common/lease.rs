@@ -0,0 +1,84 @@
+//! Lease durations of port mappings, which depend on the version of the WAN connection
+//! service.
+
+use std::time::Duration;
+
+/// Longest lease duration accepted by an IGD v2 gateway, in seconds. It is also the lease
+/// such a gateway grants when asked for an infinite lease.
+pub const MAX_LEASE_DURATION_V2: u32 = 604_800;
+
+/// Version of a service type such as `urn:schemas-upnp-org:service:WANIPConnection:2`, or 1
+/// if it has none.
+pub fn service_version(service_type: &str) -> u32 {
+    service_type
+        .rsplit(':')
+        .next()
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(1)
+}
+
+/// The lease duration in seconds that the gateway grants for `lease_duration`, 0 being
+/// infinite. IGD v2 does not allow infinite leases, and caps leases at one week.
+pub fn effective_lease_duration(service_type: &str, lease_duration: u32) -> u32 {
+    if service_version(service_type) < 2 {
+        return lease_duration;
+    }
+    match lease_duration {
+        0 => MAX_LEASE_DURATION_V2,
+        lease_duration => lease_duration.min(MAX_LEASE_DURATION_V2),
+    }
+}
+
+/// The lease duration in seconds to request for `lease`, `None` being infinite.
+///
+/// Leases are rounded up to whole seconds. An empty lease is rejected, as is a lease that
+/// does not fit the request of an IGD v1 gateway, returning the lease as error. IGD v2
+/// leases are capped at one week.
+pub fn lease_duration(service_type: &str, lease: Option<Duration>) -> Result<u32, Duration> {
+    let Some(lease) = lease else {
+        return Ok(0);
+    };
+    let secs = lease.as_secs() + u64::from(lease.subsec_nanos() > 0);
+    if secs == 0 {
+        return Err(lease);
+    }
+    if service_version(service_type) >= 2 {
+        return Ok(secs.min(MAX_LEASE_DURATION_V2.into()) as u32);
+    }
+    u32::try_from(secs).map_err(|_| lease)
+}
+
+/// The lease granted for `lease_duration`, `None` being infinite.
+pub fn granted_lease(service_type: &str, lease_duration: u32) -> Option<Duration> {
+    match effective_lease_duration(service_type, lease_duration) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1: &str = "urn:schemas-upnp-org:service:WANIPConnection:1";
+    const V2: &str = "urn:schemas-upnp-org:service:WANIPConnection:2";
+
+    #[test]
+    fn leases_follow_the_service_version() {
+        assert_eq!(service_version(V2), 2);
+        assert_eq!(service_version("WANIPConnection"), 1);
+
+        assert_eq!(lease_duration(V1, None).unwrap(), 0);
+        assert_eq!(granted_lease(V1, 0), None);
+        assert_eq!(granted_lease(V2, 0), Some(Duration::from_secs(604_800)));
+
+        assert_eq!(lease_duration(V1, Some(Duration::from_millis(1500))).unwrap(), 2);
+        assert_eq!(lease_duration(V1, Some(Duration::from_secs(1 << 40))).ok(), None);
+        assert_eq!(lease_duration(V2, Some(Duration::from_secs(1 << 40))).unwrap(), 604_800);
+        assert_eq!(lease_duration(V2, Some(Duration::ZERO)).ok(), None);
+
+        assert_eq!(effective_lease_duration(V1, 1_000_000), 1_000_000);
+        assert_eq!(effective_lease_duration(V2, 1_000_000), 604_800);
+        assert_eq!(effective_lease_duration(V2, 3600), 3600);
+    }
+}
@@ -0,0 +1,220 @@
+//! Messages and responses of the `WANDSLLinkConfig:1`, `WANEthernetLinkConfig:1` and
+//! `WANCableLinkConfig:1` services.
+
+use std::fmt;
+
+use super::messages;
+use super::parsing::{RequestResult, ServiceDescription};
+use crate::errors::RequestError;
+
+/// Service type of the DSL link configuration service.
+pub const WAN_DSL_LINK_CONFIG_SERVICE: &str = "urn:schemas-upnp-org:service:WANDSLLinkConfig:1";
+
+/// Service type of the Ethernet link configuration service.
+pub const WAN_ETHERNET_LINK_CONFIG_SERVICE: &str = "urn:schemas-upnp-org:service:WANEthernetLinkConfig:1";
+
+/// Service type of the cable modem link configuration service.
+pub const WAN_CABLE_LINK_CONFIG_SERVICE: &str = "urn:schemas-upnp-org:service:WANCableLinkConfig:1";
+
+pub const GET_DSL_LINK_INFO_ACTION: &str = "GetDSLLinkInfo";
+
+pub const GET_AUTO_CONFIG_ACTION: &str = "GetAutoConfig";
+
+pub const GET_MODULATION_TYPE_ACTION: &str = "GetModulationType";
+
+pub const GET_DESTINATION_ADDRESS_ACTION: &str = "GetDestinationAddress";
+
+pub const GET_ATM_ENCAPSULATION_ACTION: &str = "GetATMEncapsulation";
+
+pub const GET_ETHERNET_LINK_STATUS_ACTION: &str = "GetEthernetLinkStatus";
+
+pub const GET_CABLE_LINK_CONFIG_INFO_ACTION: &str = "GetCableLinkConfigInfo";
+
+pub const GET_DOWNSTREAM_FREQUENCY_ACTION: &str = "GetDownstreamFrequency";
+
+pub const GET_DOWNSTREAM_MODULATION_ACTION: &str = "GetDownstreamModulation";
+
+pub const GET_UPSTREAM_FREQUENCY_ACTION: &str = "GetUpstreamFrequency";
+
+pub const GET_UPSTREAM_MODULATION_ACTION: &str = "GetUpstreamModulation";
+
+pub const GET_UPSTREAM_POWER_LEVEL_ACTION: &str = "GetUpstreamPowerLevel";
+
+/// State of a physical WAN link.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LinkStatus {
+    /// The link is up
+    Up,
+    /// The link is down
+    Down,
+    /// The link is being established
+    Initializing,
+    /// The link is unavailable
+    Unavailable,
+    /// A value not defined by the specification
+    Other(String),
+}
+
+impl From<&str> for LinkStatus {
+    fn from(status: &str) -> LinkStatus {
+        match status {
+            "Up" => LinkStatus::Up,
+            "Down" => LinkStatus::Down,
+            "Initializing" => LinkStatus::Initializing,
+            "Unavailable" => LinkStatus::Unavailable,
+            other => LinkStatus::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for LinkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkStatus::Up => write!(f, "Up"),
+            LinkStatus::Down => write!(f, "Down"),
+            LinkStatus::Initializing => write!(f, "Initializing"),
+            LinkStatus::Unavailable => write!(f, "Unavailable"),
+            LinkStatus::Other(status) => write!(f, "{status}"),
+        }
+    }
+}
+
+/// DSL link information as returned by GetDSLLinkInfo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DslLinkInfo {
+    /// Type of the DSL connection (e.g. `EoA`, `PPPoA` or `IPoA`)
+    pub link_type: String,
+    /// State of the DSL link
+    pub link_status: LinkStatus,
+}
+
+/// Cable modem link information as returned by GetCableLinkConfigInfo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CableLinkConfigInfo {
+    /// State of the cable modem configuration (e.g. `notReady`, `dsSyncComplete`, `operational`)
+    pub config_state: String,
+    /// Type of the link (e.g. `Ethernet`)
+    pub link_type: String,
+}
+
+/// Find the link configuration service of the given type, preferring the one on the same
+/// device as the WAN connection service at `control_url`.
+pub fn find_link_config_service<'a>(
+    services: &'a [ServiceDescription],
+    service_type: &str,
+    control_url: &str,
+) -> Option<&'a ServiceDescription> {
+    let device_udn = services
+        .iter()
+        .find(|service| service.control_url == control_url)
+        .map(|service| service.device_udn.as_str());
+    let mut candidates = services.iter().filter(|service| service.service_type == service_type);
+    let first = candidates.next()?;
+    std::iter::once(first)
+        .chain(candidates)
+        .find(|service| Some(service.device_udn.as_str()) == device_udn)
+        .or(Some(first))
+}
+
+pub fn format_link_config_message(service_type: &str, action: &str) -> String {
+    messages::format_action_message(service_type, action, &[])
+}
+
+pub fn parse_get_dsl_link_info_response(result: RequestResult) -> Result<DslLinkInfo, RequestError> {
+    let resp = result?;
+    Ok(DslLinkInfo {
+        link_type: resp.argument("NewLinkType")?,
+        link_status: LinkStatus::from(resp.argument("NewLinkStatus")?.as_str()),
+    })
+}
+
+pub fn parse_get_ethernet_link_status_response(result: RequestResult) -> Result<LinkStatus, RequestError> {
+    Ok(LinkStatus::from(result?.argument("NewEthernetLinkStatus")?.as_str()))
+}
+
+pub fn parse_get_cable_link_config_info_response(result: RequestResult) -> Result<CableLinkConfigInfo, RequestError> {
+    let resp = result?;
+    Ok(CableLinkConfigInfo {
+        config_state: resp.argument("NewCableLinkConfigState")?,
+        link_type: resp.argument("NewLinkType")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::parsing::{parse_response, parse_services};
+
+    #[test]
+    fn link_config_service_on_connection_device_is_preferred() {
+        let text = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+   <device>
+      <deviceType>urn:schemas-upnp-org:device:WANDevice:1</deviceType>
+      <UDN>uuid:1</UDN>
+      <deviceList>
+         <device>
+            <deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>
+            <UDN>uuid:2</UDN>
+            <serviceList>
+               <service>
+                  <serviceType>urn:schemas-upnp-org:service:WANDSLLinkConfig:1</serviceType>
+                  <serviceId>urn:upnp-org:serviceId:WANDSLLinkC1</serviceId>
+                  <controlURL>/ctl/DSL1</controlURL>
+                  <SCPDURL>/dsl.xml</SCPDURL>
+               </service>
+            </serviceList>
+         </device>
+         <device>
+            <deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>
+            <UDN>uuid:3</UDN>
+            <serviceList>
+               <service>
+                  <serviceType>urn:schemas-upnp-org:service:WANDSLLinkConfig:1</serviceType>
+                  <serviceId>urn:upnp-org:serviceId:WANDSLLinkC1</serviceId>
+                  <controlURL>/ctl/DSL2</controlURL>
+                  <SCPDURL>/dsl.xml</SCPDURL>
+               </service>
+               <service>
+                  <serviceType>urn:schemas-upnp-org:service:WANPPPConnection:1</serviceType>
+                  <serviceId>urn:upnp-org:serviceId:WANPPPConn1</serviceId>
+                  <controlURL>/ctl/PPP</controlURL>
+                  <SCPDURL>/ppp.xml</SCPDURL>
+               </service>
+            </serviceList>
+         </device>
+      </deviceList>
+   </device>
+</root>"#;
+        let services = parse_services(text.as_bytes()).unwrap();
+        let service = find_link_config_service(&services, WAN_DSL_LINK_CONFIG_SERVICE, "/ctl/PPP").unwrap();
+        assert_eq!(service.control_url, "/ctl/DSL2");
+        let service = find_link_config_service(&services, WAN_DSL_LINK_CONFIG_SERVICE, "/ctl/Other").unwrap();
+        assert_eq!(service.control_url, "/ctl/DSL1");
+        assert!(find_link_config_service(&services, WAN_CABLE_LINK_CONFIG_SERVICE, "/ctl/PPP").is_none());
+    }
+
+    #[test]
+    fn parse_dsl_link_info() {
+        let text = format!(
+            r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:GetDSLLinkInfoResponse xmlns:u="{WAN_DSL_LINK_CONFIG_SERVICE}">
+<NewLinkType>PPPoE</NewLinkType>
+<NewLinkStatus>Up</NewLinkStatus>
+</u:GetDSLLinkInfoResponse>
+</s:Body>
+</s:Envelope>"#
+        );
+        let info = parse_get_dsl_link_info_response(parse_response(text, "GetDSLLinkInfoResponse")).unwrap();
+        assert_eq!(
+            info,
+            DslLinkInfo {
+                link_type: "PPPoE".to_string(),
+                link_status: LinkStatus::Up,
+            }
+        );
+        assert_eq!(LinkStatus::from("NoSignal"), LinkStatus::Other("NoSignal".to_string()));
+    }
+}
@@ -0,0 +1,247 @@
+//! Renewal schedule shared by the sync and async mapping managers.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use rand::{self, RngExt};
+
+use crate::errors::AddPortError;
+use crate::PortMappingProtocol;
+
+/// How often a mapping with an infinite lease is added again, in case the gateway lost it.
+pub const PERMANENT_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait before retrying a renewal that failed.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long the manager sleeps when it has no mapping to renew.
+pub const IDLE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A port mapping, as passed to `add_port`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MappingSpec {
+    /// The protocol of the mapping
+    pub protocol: PortMappingProtocol,
+    /// The external port of the mapping
+    pub external_port: u16,
+    /// The local address traffic is forwarded to
+    pub local_addr: SocketAddr,
+    /// The lease of the mapping, `None` being infinite. It is adjusted to the version of the
+    /// gateway as for `add_port_with_lease`.
+    pub lease: Option<Duration>,
+    /// A description for the mapping
+    pub description: String,
+}
+
+impl MappingSpec {
+    fn key(&self) -> (PortMappingProtocol, u16) {
+        (self.protocol, self.external_port)
+    }
+}
+
+/// A renewal that failed. The manager keeps retrying it.
+#[derive(Debug)]
+pub struct MappingFailure {
+    /// The mapping that could not be renewed
+    pub mapping: MappingSpec,
+    /// The error returned by the gateway
+    pub error: AddPortError,
+}
+
+/// When to add a mapping with the given granted lease again: around half of the lease, with
+/// 10% jitter so that many mappings do not hit the gateway at once.
+pub fn renewal_delay(lease: Option<Duration>) -> Duration {
+    let base = match lease {
+        None => PERMANENT_REFRESH_INTERVAL,
+        Some(lease) => lease / 2,
+    };
+    base.mul_f64(rand::rng().random_range(0.9..1.1))
+        .max(Duration::from_secs(1))
+}
+
+/// The managed mappings and when each one is due for renewal.
+///
+/// Renewals run without holding the schedule, so each entry carries a generation, bumped
+/// whenever the mapping is added again, which tells whether a renewal still applies once it
+/// is done.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    entries: Vec<Entry>,
+    next_generation: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    mapping: MappingSpec,
+    due: Instant,
+    generation: u64,
+}
+
+impl Schedule {
+    /// Add a mapping that was just added to the gateway with the `granted` lease, replacing
+    /// any mapping of the same external port.
+    pub fn insert(&mut self, mapping: MappingSpec, granted: Option<Duration>, now: Instant) {
+        self.remove(mapping.protocol, mapping.external_port);
+        let due = now + renewal_delay(granted);
+        self.next_generation += 1;
+        self.entries.push(Entry {
+            mapping,
+            due,
+            generation: self.next_generation,
+        });
+    }
+
+    /// Stop managing the mapping of an external port.
+    pub fn remove(&mut self, protocol: PortMappingProtocol, external_port: u16) -> Option<MappingSpec> {
+        let index = self.position(protocol, external_port)?;
+        Some(self.entries.remove(index).mapping)
+    }
+
+    fn position(&self, protocol: PortMappingProtocol, external_port: u16) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.mapping.key() == (protocol, external_port))
+    }
+
+    /// Whether the mapping of an external port is managed.
+    pub fn contains(&self, protocol: PortMappingProtocol, external_port: u16) -> bool {
+        self.position(protocol, external_port).is_some()
+    }
+
+    /// The managed mappings.
+    pub fn mappings(&self) -> Vec<MappingSpec> {
+        self.entries.iter().map(|entry| entry.mapping.clone()).collect()
+    }
+
+    /// When the next mapping is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.entries.iter().map(|entry| entry.due).min()
+    }
+
+    /// The mappings due for renewal at `now`, with their generation to pass to `renewed`.
+    pub fn due(&self, now: Instant) -> Vec<(MappingSpec, u64)> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.due <= now)
+            .map(|entry| (entry.mapping.clone(), entry.generation))
+            .collect()
+    }
+
+    /// Reschedule a mapping after a renewal attempt, which either granted a lease or failed.
+    ///
+    /// Returns false, leaving the schedule unchanged, if the mapping was removed or added
+    /// again since `due` returned it.
+    pub fn renewed<E>(
+        &mut self,
+        mapping: &MappingSpec,
+        generation: u64,
+        granted: Result<Option<Duration>, E>,
+        now: Instant,
+    ) -> bool {
+        let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.mapping.key() == mapping.key() && entry.generation == generation)
+        else {
+            return false;
+        };
+        entry.due = match granted {
+            Ok(granted) => now + renewal_delay(granted),
+            Err(_) => now + RETRY_INTERVAL,
+        };
+        true
+    }
+
+    /// Make the mapping of an external port due at `now`, if it is managed.
+    pub fn renew_now(&mut self, protocol: PortMappingProtocol, external_port: u16, now: Instant) {
+        if let Some(index) = self.position(protocol, external_port) {
+            self.entries[index].due = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lease(secs: u64) -> Option<Duration> {
+        Some(Duration::from_secs(secs))
+    }
+
+    fn mapping(external_port: u16, lease_secs: u64) -> MappingSpec {
+        MappingSpec {
+            protocol: PortMappingProtocol::TCP,
+            external_port,
+            local_addr: "192.168.1.5:8080".parse().unwrap(),
+            lease: lease(lease_secs),
+            description: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn renewal_is_jittered_around_half_the_lease() {
+        for _ in 0..100 {
+            let delay = renewal_delay(lease(3600));
+            assert!(delay >= Duration::from_secs(1620) && delay <= Duration::from_secs(1980));
+        }
+        assert!(renewal_delay(None) >= PERMANENT_REFRESH_INTERVAL.mul_f64(0.9));
+        assert_eq!(renewal_delay(lease(1)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn renewal_follows_the_granted_lease() {
+        let now = Instant::now();
+        let mut schedule = Schedule::default();
+        // An IGD v2 gateway grants at most a week
+        schedule.insert(mapping(1000, 2 * 604_800), lease(604_800), now);
+        assert!(schedule.next_due().unwrap() <= now + Duration::from_secs(332_640));
+    }
+
+    #[test]
+    fn schedule_renews_due_mappings() {
+        let now = Instant::now();
+        let mut schedule = Schedule::default();
+        schedule.insert(mapping(1000, 60), lease(60), now);
+        schedule.insert(mapping(2000, 3600), lease(3600), now);
+        schedule.insert(mapping(1000, 120), lease(120), now);
+        assert_eq!(schedule.mappings().len(), 2);
+        assert!(schedule.due(now).is_empty());
+
+        let later = now + Duration::from_secs(70);
+        let due = schedule.due(later);
+        assert_eq!(due.len(), 1);
+        let (due, generation) = due[0].clone();
+        assert_eq!(due, mapping(1000, 120));
+        assert!(schedule.renewed(&due, generation, Err(AddPortError::PortInUse), later));
+        assert_eq!(schedule.next_due(), Some(later + RETRY_INTERVAL));
+
+        assert_eq!(
+            schedule.remove(PortMappingProtocol::TCP, 1000),
+            Some(mapping(1000, 120))
+        );
+        assert_eq!(schedule.remove(PortMappingProtocol::UDP, 2000), None);
+        assert_eq!(schedule.mappings(), vec![mapping(2000, 3600)]);
+    }
+
+    #[test]
+    fn renewals_of_removed_or_replaced_mappings_do_not_apply() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(70);
+        let mut schedule = Schedule::default();
+        schedule.insert(mapping(1000, 60), lease(60), now);
+        let (due, generation) = schedule.due(later).remove(0);
+
+        // Added again while the renewal was in flight
+        schedule.insert(mapping(1000, 60), lease(60), later);
+        assert!(!schedule.renewed(&due, generation, Ok::<_, AddPortError>(lease(60)), later));
+        assert!(schedule.due(later).is_empty());
+        schedule.renew_now(PortMappingProtocol::TCP, 1000, later);
+        assert_eq!(schedule.due(later).len(), 1);
+
+        // Removed while the renewal was in flight
+        let (due, generation) = schedule.due(later).remove(0);
+        schedule.remove(PortMappingProtocol::TCP, 1000);
+        assert!(!schedule.renewed(&due, generation, Ok::<_, AddPortError>(lease(60)), later));
+        assert!(!schedule.contains(PortMappingProtocol::TCP, 1000));
+    }
+}
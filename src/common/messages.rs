@@ -1,3 +1,4 @@
+use super::lease;
 use crate::PortMappingProtocol;
 use std::net::SocketAddr;
 
@@ -19,6 +20,12 @@ pub const DELETE_PORT_MAPPING_ACTION: &str = "DeletePortMapping";
 
 pub const GET_GENERIC_PORT_MAPPING_ENTRY_ACTION: &str = "GetGenericPortMappingEntry";
 
+pub const GET_SPECIFIC_PORT_MAPPING_ENTRY_ACTION: &str = "GetSpecificPortMappingEntry";
+
+pub const GET_DEFAULT_CONNECTION_SERVICE_ACTION: &str = "GetDefaultConnectionService";
+
+pub const SET_DEFAULT_CONNECTION_SERVICE_ACTION: &str = "SetDefaultConnectionService";
+
 /// Build the quoted `SOAPAction` header value (`"<service_type>#<action>"`) for a request.
 pub fn soap_action(service_type: &str, action: &str) -> String {
     format!("\"{service_type}#{action}\"")
@@ -35,6 +42,42 @@ fn format_message(body: String) -> String {
     format!("{MESSAGE_HEAD}{body}{MESSAGE_TAIL}")
 }
 
+/// Build the request body for `action` with the given in-arguments, in the given order.
+pub fn format_action_message(service_type: &str, action: &str, args: &[(&str, String)]) -> String {
+    let args = args
+        .iter()
+        .map(|(argument, value)| format!("<{argument}>{}</{argument}>", xml_escape(value)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format_message(format!(
+        r#"<u:{action} xmlns:u="{service_type}">
+        {args}
+        </u:{action}>"#
+    ))
+}
+
+/// Order the in-arguments of `action` by the given schema, as some gateways reject requests
+/// whose arguments are not in the order of their SCPD. Arguments missing from the schema are
+/// kept, in their given order, after the known ones.
+pub fn order_arguments<'a>(schema: Option<&[String]>, args: &[(&'a str, String)]) -> Vec<(&'a str, String)> {
+    let schema = match schema {
+        Some(schema) => schema,
+        None => return args.to_vec(),
+    };
+    let mut ordered = schema
+        .iter()
+        .filter_map(|argument| args.iter().find(|(name, _)| name == argument).cloned())
+        .collect::<Vec<_>>();
+    for (name, value) in args {
+        if !schema.iter().any(|argument| argument == name) {
+            log::warn!("Unknown argument: {}", name);
+            ordered.push((name, value.clone()));
+        }
+    }
+    ordered
+}
+
 /// Escape a string for inclusion in XML text/attribute content, so a user-supplied value
 /// cannot produce malformed XML or inject elements into the SOAP request body.
 fn xml_escape(input: &str) -> String {
@@ -76,7 +119,7 @@ pub fn format_add_any_port_mapping_message(
                 "NewExternalPort" => external_port.to_string(),
                 "NewInternalClient" => local_addr.ip().to_string(),
                 "NewInternalPort" => local_addr.port().to_string(),
-                "NewLeaseDuration" => lease_duration.to_string(),
+                "NewLeaseDuration" => lease::effective_lease_duration(service_type, lease_duration).to_string(),
                 "NewPortMappingDescription" => description.to_string(),
                 "NewProtocol" => protocol.to_string(),
                 "NewRemoteHost" => "".to_string(),
@@ -114,7 +157,7 @@ pub fn format_add_port_mapping_message(
                 "NewExternalPort" => external_port.to_string(),
                 "NewInternalClient" => local_addr.ip().to_string(),
                 "NewInternalPort" => local_addr.port().to_string(),
-                "NewLeaseDuration" => lease_duration.to_string(),
+                "NewLeaseDuration" => lease::effective_lease_duration(service_type, lease_duration).to_string(),
                 "NewPortMappingDescription" => description.to_string(),
                 "NewProtocol" => protocol.to_string(),
                 "NewRemoteHost" => "".to_string(),
@@ -173,6 +216,36 @@ pub fn formate_get_generic_port_mapping_entry_message(service_type: &str, port_m
     ))
 }
 
+pub fn format_get_specific_port_mapping_entry_message(
+    service_type: &str,
+    protocol: PortMappingProtocol,
+    external_port: u16,
+) -> String {
+    format_message(format!(
+        r#"<u:GetSpecificPortMappingEntry xmlns:u="{service_type}">
+        <NewRemoteHost></NewRemoteHost>
+        <NewExternalPort>{external_port}</NewExternalPort>
+        <NewProtocol>{protocol}</NewProtocol>
+        </u:GetSpecificPortMappingEntry>"#
+    ))
+}
+
+pub fn format_get_default_connection_service_message(service_type: &str) -> String {
+    format_message(format!(
+        r#"<u:GetDefaultConnectionService xmlns:u="{service_type}">
+        </u:GetDefaultConnectionService>"#
+    ))
+}
+
+pub fn format_set_default_connection_service_message(service_type: &str, default_connection_service: &str) -> String {
+    format_message(format!(
+        r#"<u:SetDefaultConnectionService xmlns:u="{service_type}">
+        <NewDefaultConnectionService>{}</NewDefaultConnectionService>
+        </u:SetDefaultConnectionService>"#,
+        xml_escape(default_connection_service)
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,6 +276,58 @@ mod tests {
         assert!(body.contains("<NewExternalPort>12345</NewExternalPort>"));
     }
 
+    #[test]
+    fn lease_duration_is_capped_for_igd_v2() {
+        let schema = ["NewLeaseDuration".to_string()];
+        let local_addr = "192.168.1.5:80".parse().unwrap();
+        let v1 = format_add_port_mapping_message(PPP, &schema, PortMappingProtocol::TCP, 80, local_addr, 0, "");
+        assert!(v1.contains("<NewLeaseDuration>0</NewLeaseDuration>"));
+        let v2 = format_add_any_port_mapping_message(
+            "urn:schemas-upnp-org:service:WANIPConnection:2",
+            &schema,
+            PortMappingProtocol::TCP,
+            80,
+            local_addr,
+            0,
+            "",
+        );
+        assert!(v2.contains("<NewLeaseDuration>604800</NewLeaseDuration>"));
+    }
+
+    #[test]
+    fn action_message_keeps_argument_order() {
+        let body = format_action_message(
+            "urn:schemas-upnp-org:service:WANIPv6FirewallControl:1",
+            "UpdatePinhole",
+            &[("UniqueID", "7".to_string()), ("NewLeaseTime", "3600".to_string())],
+        );
+        assert!(body.contains(r#"<u:UpdatePinhole xmlns:u="urn:schemas-upnp-org:service:WANIPv6FirewallControl:1">"#));
+        assert!(body.contains("<UniqueID>7</UniqueID>\n<NewLeaseTime>3600</NewLeaseTime>"));
+        assert!(body.contains("</u:UpdatePinhole>"));
+    }
+
+    #[test]
+    fn arguments_are_ordered_by_schema() {
+        let schema = [
+            "NewRemoteHost".to_string(),
+            "NewExternalPort".to_string(),
+            "NewProtocol".to_string(),
+        ];
+        let args = [
+            ("NewProtocol", "UDP".to_string()),
+            ("NewVendorFlag", "1".to_string()),
+            ("NewExternalPort", "1234".to_string()),
+            ("NewRemoteHost", "".to_string()),
+        ];
+        let ordered = order_arguments(Some(&schema), &args);
+        let names = ordered.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["NewRemoteHost", "NewExternalPort", "NewProtocol", "NewVendorFlag"]
+        );
+        assert_eq!(order_arguments(None, &args), args);
+    }
+
     #[test]
     fn xml_escape_escapes_special_characters() {
         assert_eq!(
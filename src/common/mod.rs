@@ -1,8 +1,30 @@
+pub mod batch;
+pub mod cascade;
+pub mod firewall;
+pub mod gena;
+#[cfg(feature = "io_sync")]
+pub mod http;
+pub mod lan_host;
+pub mod lease;
+pub mod link_config;
+pub mod manager;
 pub mod messages;
+pub mod natpmp;
 pub mod options;
 pub mod parsing;
+pub mod pcp;
+pub mod port_mapper;
+pub mod ppp;
+pub mod range;
+pub mod reconcile;
+pub mod stun;
+pub mod topology;
+pub mod watcher;
+
+pub use self::options::{MapOptions, SearchOptions, WatchOptions};
 
-pub use self::options::SearchOptions;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
 
 use rand::{self, RngExt};
 
@@ -10,6 +32,35 @@ pub fn random_port() -> u16 {
     rand::rng().random_range(32_768_u16..65_535_u16)
 }
 
+/// Address of the local interface that routes to `remote`.
+///
+/// Connecting a UDP socket sends nothing, it only makes the OS pick the source address.
+pub fn local_ip_for(remote: SocketAddr) -> io::Result<IpAddr> {
+    let unspecified = match remote {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((unspecified, 0))?;
+    socket.connect(remote)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Address `host` of the network of `ip`. Routers do not report the netmask of their
+/// networks, so this assumes the usual /24.
+pub fn network_host(ip: Ipv4Addr, host: u8) -> Ipv4Addr {
+    let [a, b, c, _] = ip.octets();
+    Ipv4Addr::new(a, b, c, host)
+}
+
+/// The local address control connections to `gateway` are bound to by default: the one the
+/// search socket was bound to, or else the one routing to the gateway.
+pub fn control_bind_addr(search_bind_addr: SocketAddr, gateway: SocketAddr) -> Option<IpAddr> {
+    match search_bind_addr.ip() {
+        ip if ip.is_unspecified() => local_ip_for(gateway).ok(),
+        ip => Some(ip),
+    }
+}
+
 /// Read response body, rejecting a body larger than `max` bytes (and never
 /// buffering more than that) so a malicious or buggy gateway cannot exhaust memory.
 #[cfg(feature = "io_sync")]
@@ -26,3 +77,21 @@ pub fn read_response_body(response: attohttpc::Response, max: usize) -> std::io:
     }
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bind_addr_follows_the_search_socket() {
+        let gateway = "127.0.0.1:1900".parse().unwrap();
+        assert_eq!(
+            control_bind_addr("0.0.0.0:0".parse().unwrap(), gateway),
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+        assert_eq!(
+            control_bind_addr("192.168.1.5:0".parse().unwrap(), gateway),
+            Some("192.168.1.5".parse().unwrap())
+        );
+    }
+}
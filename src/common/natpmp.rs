@@ -0,0 +1,278 @@
+//! Messages of the NAT Port Mapping Protocol (RFC 6886).
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use crate::errors::NatPmpError;
+use crate::PortMappingProtocol;
+
+/// Port NAT-PMP servers listen on.
+pub const NATPMP_PORT: u16 = 5351;
+
+/// Delay before the first retransmission of a request, doubled after each one.
+pub const INITIAL_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Number of times a request is sent before giving up, which waits about 2 minutes in total.
+pub const MAX_ATTEMPTS: u32 = 9;
+
+const VERSION: u8 = 0;
+
+const EXTERNAL_ADDRESS_OPCODE: u8 = 0;
+
+const RESPONSE_OPCODE: u8 = 128;
+
+/// Size of the largest response, used as the receive buffer size.
+pub const MAX_RESPONSE_SIZE: usize = 16;
+
+/// A port mapping granted by a NAT-PMP gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatPmpMapping {
+    /// The protocol of the mapping
+    pub protocol: PortMappingProtocol,
+    /// The internal (local) port
+    pub internal_port: u16,
+    /// The external port, which may differ from the one requested
+    pub external_port: u16,
+    /// How long the mapping lasts unless it is renewed
+    pub lifetime: Duration,
+    /// Seconds since the gateway's port mapping table was initialized
+    pub epoch: u32,
+    /// Whether the epoch shows the gateway restarted, and so lost its mappings, since the
+    /// previous response
+    pub gateway_restarted: bool,
+}
+
+/// The external address reported by a NAT-PMP gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatPmpExternalAddress {
+    /// The external IPv4 address
+    pub address: Ipv4Addr,
+    /// Seconds since the gateway's port mapping table was initialized
+    pub epoch: u32,
+    /// Whether the epoch shows the gateway restarted, and so lost its mappings, since the
+    /// previous response
+    pub gateway_restarted: bool,
+}
+
+fn mapping_opcode(protocol: PortMappingProtocol) -> u8 {
+    match protocol {
+        PortMappingProtocol::UDP => 1,
+        PortMappingProtocol::TCP => 2,
+    }
+}
+
+pub fn format_external_address_request() -> [u8; 2] {
+    [VERSION, EXTERNAL_ADDRESS_OPCODE]
+}
+
+/// Build a mapping request. A lifetime of 0 deletes the mapping of `internal_port`.
+pub fn format_mapping_request(
+    protocol: PortMappingProtocol,
+    internal_port: u16,
+    suggested_external_port: u16,
+    lifetime: u32,
+) -> [u8; 12] {
+    let mut request = [0u8; 12];
+    request[0] = VERSION;
+    request[1] = mapping_opcode(protocol);
+    request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    request[6..8].copy_from_slice(&suggested_external_port.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime.to_be_bytes());
+    request
+}
+
+fn convert_result_code(code: u16) -> Result<(), NatPmpError> {
+    match code {
+        0 => Ok(()),
+        1 => Err(NatPmpError::UnsupportedVersion),
+        2 => Err(NatPmpError::NotAuthorized),
+        3 => Err(NatPmpError::NetworkFailure),
+        4 => Err(NatPmpError::OutOfResources),
+        5 => Err(NatPmpError::UnsupportedOpcode),
+        code => Err(NatPmpError::UnknownResultCode(code)),
+    }
+}
+
+fn u16_at(packet: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([packet[at], packet[at + 1]])
+}
+
+fn u32_at(packet: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([packet[at], packet[at + 1], packet[at + 2], packet[at + 3]])
+}
+
+/// Check the header of a response to a request with `opcode`, returning its epoch.
+///
+/// `Ok(None)` means the packet is not a response to the request and should be ignored.
+fn parse_header(packet: &[u8], opcode: u8) -> Result<Option<u32>, NatPmpError> {
+    if packet.len() < 4 || packet[1] != RESPONSE_OPCODE + opcode {
+        return Ok(None);
+    }
+    // Errors may be reported with a truncated packet, e.g. an unsupported version.
+    convert_result_code(u16_at(packet, 2))?;
+    if packet[0] != VERSION {
+        return Err(NatPmpError::UnsupportedVersion);
+    }
+    if packet.len() < 8 {
+        return Err(NatPmpError::InvalidResponse(format!(
+            "response of {} bytes",
+            packet.len()
+        )));
+    }
+    Ok(Some(u32_at(packet, 4)))
+}
+
+/// Parse a response to an external address request, returning the address and epoch.
+pub fn parse_external_address_response(packet: &[u8]) -> Result<Option<(Ipv4Addr, u32)>, NatPmpError> {
+    let epoch = match parse_header(packet, EXTERNAL_ADDRESS_OPCODE)? {
+        Some(epoch) => epoch,
+        None => return Ok(None),
+    };
+    if packet.len() < 12 {
+        return Err(NatPmpError::InvalidResponse(format!(
+            "response of {} bytes",
+            packet.len()
+        )));
+    }
+    let address = Ipv4Addr::new(packet[8], packet[9], packet[10], packet[11]);
+    Ok(Some((address, epoch)))
+}
+
+/// Parse a response to a mapping request for `internal_port`.
+///
+/// The returned mapping has not been checked against the epoch yet.
+pub fn parse_mapping_response(
+    packet: &[u8],
+    protocol: PortMappingProtocol,
+    internal_port: u16,
+) -> Result<Option<NatPmpMapping>, NatPmpError> {
+    let epoch = match parse_header(packet, mapping_opcode(protocol))? {
+        Some(epoch) => epoch,
+        None => return Ok(None),
+    };
+    if packet.len() < 16 {
+        return Err(NatPmpError::InvalidResponse(format!(
+            "response of {} bytes",
+            packet.len()
+        )));
+    }
+    if u16_at(packet, 8) != internal_port {
+        return Ok(None);
+    }
+    Ok(Some(NatPmpMapping {
+        protocol,
+        internal_port,
+        external_port: u16_at(packet, 10),
+        lifetime: Duration::from_secs(u32_at(packet, 12).into()),
+        epoch,
+        gateway_restarted: false,
+    }))
+}
+
+/// Detects gateway restarts from the epochs of successive responses (RFC 6886 section 3.6).
+#[derive(Debug, Default)]
+pub struct EpochTracker {
+    last: Option<(u32, Instant)>,
+}
+
+impl EpochTracker {
+    /// Record the epoch of a response received at `now`, returning whether it shows the
+    /// gateway restarted since the previous response.
+    pub fn observe(&mut self, epoch: u32, now: Instant) -> bool {
+        let restarted = match self.last {
+            Some((last_epoch, last_time)) => {
+                // The gateway's clock may run up to 1/8 slower than ours, with 2 seconds of slack.
+                let elapsed = now.saturating_duration_since(last_time).as_secs();
+                let expected = u64::from(last_epoch) + elapsed * 7 / 8;
+                u64::from(epoch) + 2 < expected
+            }
+            None => false,
+        };
+        self.last = Some((epoch, now));
+        restarted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping_response(opcode: u8, result: u16, epoch: u32, internal: u16, external: u16, lifetime: u32) -> Vec<u8> {
+        let mut packet = vec![0, RESPONSE_OPCODE + opcode];
+        packet.extend_from_slice(&result.to_be_bytes());
+        packet.extend_from_slice(&epoch.to_be_bytes());
+        packet.extend_from_slice(&internal.to_be_bytes());
+        packet.extend_from_slice(&external.to_be_bytes());
+        packet.extend_from_slice(&lifetime.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn mapping_request_layout() {
+        let request = format_mapping_request(PortMappingProtocol::TCP, 8080, 18080, 7200);
+        assert_eq!(request, [0, 2, 0, 0, 0x1f, 0x90, 0x46, 0xa0, 0, 0, 0x1c, 0x20]);
+        assert_eq!(format_mapping_request(PortMappingProtocol::UDP, 1, 0, 0)[1], 1);
+        assert_eq!(format_external_address_request(), [0, 0]);
+    }
+
+    #[test]
+    fn parse_responses() {
+        let packet = [0, 128, 0, 0, 0, 0, 0, 10, 203, 0, 113, 7];
+        assert_eq!(
+            parse_external_address_response(&packet).unwrap(),
+            Some((Ipv4Addr::new(203, 0, 113, 7), 10))
+        );
+
+        let packet = mapping_response(2, 0, 10, 8080, 18080, 3600);
+        let mapping = parse_mapping_response(&packet, PortMappingProtocol::TCP, 8080)
+            .unwrap()
+            .unwrap();
+        assert_eq!(mapping.external_port, 18080);
+        assert_eq!(mapping.lifetime, Duration::from_secs(3600));
+        assert_eq!(mapping.epoch, 10);
+
+        // Responses to other requests are ignored.
+        assert!(parse_mapping_response(&packet, PortMappingProtocol::UDP, 8080)
+            .unwrap()
+            .is_none());
+        assert!(parse_mapping_response(&packet, PortMappingProtocol::TCP, 9090)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn result_codes() {
+        let packet = mapping_response(1, 2, 10, 8080, 0, 0);
+        assert!(matches!(
+            parse_mapping_response(&packet, PortMappingProtocol::UDP, 8080),
+            Err(NatPmpError::NotAuthorized)
+        ));
+        let packet = mapping_response(1, 4, 10, 8080, 0, 0);
+        assert!(matches!(
+            parse_mapping_response(&packet, PortMappingProtocol::UDP, 8080),
+            Err(NatPmpError::OutOfResources)
+        ));
+        assert!(matches!(
+            parse_external_address_response(&[0, 128, 0, 1]),
+            Err(NatPmpError::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            parse_external_address_response(&[0, 128, 0, 42, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(NatPmpError::UnknownResultCode(42))
+        ));
+    }
+
+    #[test]
+    fn epoch_tracking_detects_restarts() {
+        let start = Instant::now();
+        let mut tracker = EpochTracker::default();
+        assert!(!tracker.observe(1000, start));
+        // A gateway clock running slightly slow is not a restart.
+        assert!(!tracker.observe(1090, start + Duration::from_secs(100)));
+        // The epoch went back, so the gateway restarted.
+        assert!(tracker.observe(5, start + Duration::from_secs(110)));
+        assert!(!tracker.observe(15, start + Duration::from_secs(120)));
+        // The epoch did not advance while our clock did.
+        assert!(tracker.observe(15, start + Duration::from_secs(220)));
+    }
+}
@@ -9,6 +9,9 @@ pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
 /// Default timeout for a control request to the gateway.
 #[allow(dead_code)]
 pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default duration requested for an event subscription.
+#[allow(dead_code)]
+pub const DEFAULT_SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(1800);
 /// Default size (in bytes) of an HTTP response body accepted from the gateway.
 #[allow(dead_code)]
 pub const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
@@ -49,3 +52,42 @@ impl Default for SearchOptions {
         }
     }
 }
+
+/// Port mapping configuration of `Gateway::map_port_with_options`
+///
+/// MapOptions::default() behaves like `Gateway::map_port`.
+#[derive(Debug, Clone, Default)]
+pub struct MapOptions {
+    /// Add the mapping with an infinite lease when the gateway only supports permanent leases
+    /// (error 725), and remove it once the requested lease has passed (defaults to false)
+    pub permanent_lease_fallback: bool,
+}
+
+/// External IP address watcher configuration
+///
+/// WatchOptions::default() should suffice for most situations.
+pub struct WatchOptions {
+    /// Address the event listener is bound to (defaults to `0.0.0.0:0`)
+    pub listen_addr: SocketAddr,
+    /// Whether to subscribe to `ExternalIPAddress` events before falling back to polling (defaults to true)
+    pub use_events: bool,
+    /// Duration requested for the event subscription (defaults to 30 minutes)
+    pub subscription_timeout: Duration,
+    /// Shortest interval between polls, used right after a change (defaults to 30s)
+    pub min_poll_interval: Duration,
+    /// Longest interval between polls, reached while the address stays the same (defaults to 5 minutes).
+    /// The address is also polled at this interval while events are received, in case the gateway misses some.
+    pub max_poll_interval: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            listen_addr: (IpAddr::from([0, 0, 0, 0]), 0).into(),
+            use_events: true,
+            subscription_timeout: DEFAULT_SUBSCRIPTION_TIMEOUT,
+            min_poll_interval: Duration::from_secs(30),
+            max_poll_interval: Duration::from_secs(5 * 60),
+        }
+    }
+}
@@ -6,8 +6,8 @@ use url::Url;
 use xmltree::{self, Element};
 
 use crate::errors::{
-    AddAnyPortError, AddPortError, GetExternalIpError, GetGenericPortMappingEntryError, RemovePortError, RequestError,
-    SearchError,
+    AddAnyPortError, AddPortError, DefaultConnectionServiceError, GetExternalIpError, GetGenericPortMappingEntryError,
+    GetSpecificPortMappingEntryError, RemovePortError, RequestError, SearchError, UpnpErrorCode,
 };
 use crate::PortMappingProtocol;
 
@@ -34,92 +34,140 @@ pub fn parse_search_result(text: &str) -> Result<(SocketAddr, String), SearchErr
     Err(InvalidResponse)
 }
 
-/// Parse the device description, returning `(service_type, control_schema_url, control_url)`
-/// for the first supported WAN connection service found.
-pub fn parse_control_urls<R>(resp: R) -> Result<(String, String, String), SearchError>
+/// Service types of the WAN connection services a `Gateway` can control.
+pub const WAN_CONNECTION_SERVICES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+];
+
+/// Service type of the root device's Layer3Forwarding service.
+pub const LAYER3_FORWARDING_SERVICE: &str = "urn:schemas-upnp-org:service:Layer3Forwarding:1";
+
+/// A service advertised in the gateway's device description.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ServiceDescription {
+    /// Unique device name of the device exposing the service (e.g. `uuid:...`)
+    pub device_udn: String,
+    /// Device type of the device exposing the service
+    pub device_type: String,
+    /// Service type (e.g. `urn:schemas-upnp-org:service:WANIPConnection:1`)
+    pub service_type: String,
+    /// Service id (e.g. `urn:upnp-org:serviceId:WANIPConn1`)
+    pub service_id: String,
+    /// Url to get schema data from
+    pub scpd_url: String,
+    /// Control url of the service
+    pub control_url: String,
+    /// Url to subscribe to the service's events
+    pub event_sub_url: String,
+}
+
+impl ServiceDescription {
+    /// Whether this is one of the WAN connection services a `Gateway` can control.
+    pub fn is_wan_connection(&self) -> bool {
+        WAN_CONNECTION_SERVICES.contains(&self.service_type.as_str())
+    }
+}
+
+/// Parse the device description, returning every service of every (embedded) device in
+/// document order.
+pub fn parse_services<R>(resp: R) -> Result<Vec<ServiceDescription>, SearchError>
 where
     R: io::Read,
 {
     let root = Element::parse(resp)?;
 
-    let mut urls = root.children.iter().filter_map(|child| {
-        let child = child.as_element()?;
-        if child.name == "device" {
-            Some(parse_device(child)?)
-        } else {
-            None
+    let mut services = Vec::new();
+    for child in root.children.iter() {
+        if let Some(child) = child.as_element() {
+            if child.name == "device" {
+                parse_device(child, &mut services);
+            }
         }
-    });
-
-    urls.next().ok_or(SearchError::InvalidResponse)
-}
-
-fn parse_device(device: &Element) -> Option<(String, String, String)> {
-    let services = device.get_child("serviceList").and_then(|service_list| {
-        service_list
-            .children
-            .iter()
-            .filter_map(|child| {
-                let child = child.as_element()?;
-                if child.name == "service" {
-                    parse_service(child)
-                } else {
-                    None
-                }
-            })
-            .next()
-    });
-    let devices = device.get_child("deviceList").and_then(parse_device_list);
-    services.or(devices)
+    }
+    Ok(services)
 }
 
-fn parse_device_list(device_list: &Element) -> Option<(String, String, String)> {
-    device_list
-        .children
-        .iter()
-        .filter_map(|child| {
+fn parse_device(device: &Element, services: &mut Vec<ServiceDescription>) {
+    let device_udn = child_text(device, "UDN");
+    let device_type = child_text(device, "deviceType");
+    if let Some(service_list) = device.get_child("serviceList") {
+        services.extend(service_list.children.iter().filter_map(|child| {
             let child = child.as_element()?;
-            if child.name == "device" {
-                parse_device(child)
+            if child.name == "service" {
+                parse_service(child, &device_udn, &device_type)
             } else {
                 None
             }
-        })
-        .next()
+        }));
+    }
+    if let Some(device_list) = device.get_child("deviceList") {
+        parse_device_list(device_list, services);
+    }
 }
 
-fn parse_service(service: &Element) -> Option<(String, String, String)> {
-    let service_type = service.get_child("serviceType")?;
-    let service_type = service_type
-        .get_text()
-        .map(|s| s.into_owned())
-        .unwrap_or_else(|| "".into());
-    if [
-        "urn:schemas-upnp-org:service:WANPPPConnection:1",
-        "urn:schemas-upnp-org:service:WANIPConnection:1",
-        "urn:schemas-upnp-org:service:WANIPConnection:2",
-    ]
-    .contains(&service_type.as_str())
-    {
-        let scpd_url = service.get_child("SCPDURL");
-        let control_url = service.get_child("controlURL");
-        if let (Some(scpd_url), Some(control_url)) = (scpd_url, control_url) {
-            Some((
-                service_type,
-                scpd_url.get_text().map(|s| s.into_owned()).unwrap_or_else(|| "".into()),
-                control_url
-                    .get_text()
-                    .map(|s| s.into_owned())
-                    .unwrap_or_else(|| "".into()),
-            ))
-        } else {
-            None
+fn parse_device_list(device_list: &Element, services: &mut Vec<ServiceDescription>) {
+    for child in device_list.children.iter() {
+        if let Some(child) = child.as_element() {
+            if child.name == "device" {
+                parse_device(child, services);
+            }
         }
-    } else {
-        None
     }
 }
 
+fn parse_service(service: &Element, device_udn: &str, device_type: &str) -> Option<ServiceDescription> {
+    let service_type = service.get_child("serviceType")?;
+    let scpd_url = service.get_child("SCPDURL")?;
+    let control_url = service.get_child("controlURL")?;
+    let text = |element: &Element| element.get_text().map(|s| s.trim().to_owned()).unwrap_or_default();
+    Some(ServiceDescription {
+        device_udn: device_udn.to_owned(),
+        device_type: device_type.to_owned(),
+        service_type: text(service_type),
+        service_id: child_text(service, "serviceId"),
+        scpd_url: text(scpd_url),
+        control_url: text(control_url),
+        event_sub_url: child_text(service, "eventSubURL"),
+    })
+}
+
+fn child_text(element: &Element, name: &str) -> String {
+    element
+        .get_child(name)
+        .and_then(|child| child.get_text())
+        .map(|s| s.trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// Find the WAN connection service referenced by a Layer3Forwarding `DefaultConnectionService`
+/// value, which has the form `uuid:<device-uuid>:WANConnectionDevice:1,<service-id>`.
+///
+/// The service whose device UDN and service id both match is preferred. Since some gateways
+/// report a device part that does not match any UDN, a service with a matching service id is
+/// accepted otherwise.
+pub fn find_default_connection_service<'a>(
+    services: &'a [ServiceDescription],
+    default_connection_service: &str,
+) -> Option<&'a ServiceDescription> {
+    let (device, service_id) = default_connection_service.trim().split_once(',')?;
+    let (device, service_id) = (device.trim(), service_id.trim());
+    let mut candidates = services
+        .iter()
+        .filter(|service| service.is_wan_connection() && service.service_id == service_id);
+    let first = candidates.next()?;
+    std::iter::once(first)
+        .chain(candidates)
+        .find(|service| {
+            !service.device_udn.is_empty()
+                && device
+                    .strip_prefix(&service.device_udn)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+        })
+        .or(Some(first))
+}
+
 pub fn parse_schemas<R>(resp: R) -> Result<HashMap<String, Vec<String>>, SearchError>
 where
     R: io::Read,
@@ -194,6 +242,92 @@ pub struct RequestReponse {
 
 pub type RequestResult = Result<RequestReponse, RequestError>;
 
+impl RequestReponse {
+    /// Text of the out-argument `name`, which is empty if the element has no text.
+    pub fn argument(&self, name: &str) -> Result<String, RequestError> {
+        let element = self
+            .xml
+            .get_child(name)
+            .ok_or_else(|| RequestError::InvalidResponse(format!("{name} is missing")))?;
+        Ok(element.get_text().map(|t| t.trim().to_owned()).unwrap_or_default())
+    }
+
+    /// Parse the out-argument `name`.
+    pub fn parse_argument<T: std::str::FromStr>(&self, name: &str) -> Result<T, RequestError> {
+        self.argument(name)?
+            .parse()
+            .map_err(|_| RequestError::InvalidResponse(format!("Field {name} is invalid")))
+    }
+
+    /// Parse the UPnP `boolean` out-argument `name`.
+    pub fn bool_argument(&self, name: &str) -> Result<bool, RequestError> {
+        parse_bool(&self.argument(name)?)
+            .ok_or_else(|| RequestError::InvalidResponse(format!("Field {name} is invalid")))
+    }
+}
+
+/// The out-arguments of an action, in the order the gateway returned them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OutArguments(Vec<(String, String)>);
+
+impl OutArguments {
+    /// Get the value of the out-argument `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(argument, _)| argument == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterate over the out-arguments as `(name, value)` pairs, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Number of out-arguments.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the action returned no out-arguments.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl IntoIterator for OutArguments {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+pub fn parse_out_arguments(result: RequestResult) -> Result<OutArguments, RequestError> {
+    let resp = result?;
+    Ok(OutArguments(
+        resp.xml
+            .children
+            .iter()
+            .filter_map(|child| {
+                let child = child.as_element()?;
+                let value = child.get_text().map(|t| t.into_owned()).unwrap_or_default();
+                Some((child.name.clone(), value))
+            })
+            .collect(),
+    ))
+}
+
+/// Parse a UPnP `boolean` value.
+pub fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
 pub fn parse_response(text: String, ok: &str) -> RequestResult {
     let mut xml = match xmltree::Element::parse(text.as_bytes()) {
         Ok(xml) => xml,
@@ -221,7 +355,7 @@ pub fn parse_response(text: String, ok: &str) -> RequestResult {
     ) {
         (Some(e), Some(d)) => match (e.get_text().as_ref(), d.get_text().as_ref()) {
             (Some(et), Some(dt)) => match et.parse::<u16>() {
-                Ok(en) => Err(RequestError::ErrorCode(en, From::from(&dt[..]))),
+                Ok(en) => Err(RequestError::ErrorCode(en.into(), From::from(&dt[..]))),
                 Err(..) => Err(RequestError::InvalidResponse(text)),
             },
             _ => Err(RequestError::InvalidResponse(text)),
@@ -243,7 +377,9 @@ pub fn parse_get_external_ip_response(result: RequestResult) -> Result<IpAddr, G
                 resp.text,
             ))),
         },
-        Err(RequestError::ErrorCode(606, _)) => Err(GetExternalIpError::ActionNotAuthorized),
+        Err(RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _)) => {
+            Err(GetExternalIpError::ActionNotAuthorized)
+        }
         Err(e) => Err(GetExternalIpError::RequestError(e)),
     }
 }
@@ -262,9 +398,12 @@ pub fn parse_add_any_port_mapping_response(result: RequestResult) -> Result<u16,
             }
         }
         Err(err) => Err(match err {
-            RequestError::ErrorCode(605, _) => AddAnyPortError::DescriptionTooLong,
-            RequestError::ErrorCode(606, _) => AddAnyPortError::ActionNotAuthorized,
-            RequestError::ErrorCode(728, _) => AddAnyPortError::NoPortsAvailable,
+            RequestError::ErrorCode(UpnpErrorCode::StringArgumentTooLong, _) => AddAnyPortError::DescriptionTooLong,
+            RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => AddAnyPortError::ActionNotAuthorized,
+            RequestError::ErrorCode(UpnpErrorCode::NoPortMapsAvailable, _) => AddAnyPortError::NoPortsAvailable,
+            RequestError::ErrorCode(UpnpErrorCode::OnlyPermanentLeasesSupported, _) => {
+                AddAnyPortError::OnlyPermanentLeasesSupported
+            }
             e => AddAnyPortError::RequestError(e),
         }),
     }
@@ -272,47 +411,113 @@ pub fn parse_add_any_port_mapping_response(result: RequestResult) -> Result<u16,
 
 pub fn convert_add_random_port_mapping_error(error: RequestError) -> Option<AddAnyPortError> {
     match error {
-        RequestError::ErrorCode(724, _) => None,
-        RequestError::ErrorCode(605, _) => Some(AddAnyPortError::DescriptionTooLong),
-        RequestError::ErrorCode(606, _) => Some(AddAnyPortError::ActionNotAuthorized),
-        RequestError::ErrorCode(718, _) => Some(AddAnyPortError::NoPortsAvailable),
-        RequestError::ErrorCode(725, _) => Some(AddAnyPortError::OnlyPermanentLeasesSupported),
+        RequestError::ErrorCode(UpnpErrorCode::SamePortValuesRequired, _) => None,
+        RequestError::ErrorCode(UpnpErrorCode::StringArgumentTooLong, _) => Some(AddAnyPortError::DescriptionTooLong),
+        RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => Some(AddAnyPortError::ActionNotAuthorized),
+        RequestError::ErrorCode(UpnpErrorCode::ConflictWithOtherMechanisms, _) => {
+            Some(AddAnyPortError::ConflictWithOtherMechanisms)
+        }
+        RequestError::ErrorCode(UpnpErrorCode::OnlyPermanentLeasesSupported, _) => {
+            Some(AddAnyPortError::OnlyPermanentLeasesSupported)
+        }
         e => Some(AddAnyPortError::RequestError(e)),
     }
 }
 
+/// Whether adding a random port failed because the port is in use, so that another one
+/// should be tried.
+pub fn is_random_port_conflict(error: &AddAnyPortError) -> bool {
+    matches!(
+        error,
+        AddAnyPortError::RequestError(RequestError::ErrorCode(UpnpErrorCode::ConflictInMappingEntry, _))
+    )
+}
+
 pub fn convert_add_same_port_mapping_error(error: RequestError) -> AddAnyPortError {
     match error {
-        RequestError::ErrorCode(606, _) => AddAnyPortError::ActionNotAuthorized,
-        RequestError::ErrorCode(718, _) => AddAnyPortError::ExternalPortInUse,
-        RequestError::ErrorCode(725, _) => AddAnyPortError::OnlyPermanentLeasesSupported,
+        RequestError::ErrorCode(UpnpErrorCode::StringArgumentTooLong, _) => AddAnyPortError::DescriptionTooLong,
+        RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => AddAnyPortError::ActionNotAuthorized,
+        RequestError::ErrorCode(UpnpErrorCode::ConflictInMappingEntry, _) => AddAnyPortError::ExternalPortInUse,
+        RequestError::ErrorCode(UpnpErrorCode::ConflictWithOtherMechanisms, _) => {
+            AddAnyPortError::ConflictWithOtherMechanisms
+        }
+        RequestError::ErrorCode(UpnpErrorCode::OnlyPermanentLeasesSupported, _) => {
+            AddAnyPortError::OnlyPermanentLeasesSupported
+        }
         e => AddAnyPortError::RequestError(e),
     }
 }
 
 pub fn convert_add_port_error(err: RequestError) -> AddPortError {
     match err {
-        RequestError::ErrorCode(605, _) => AddPortError::DescriptionTooLong,
-        RequestError::ErrorCode(606, _) => AddPortError::ActionNotAuthorized,
-        RequestError::ErrorCode(718, _) => AddPortError::PortInUse,
-        RequestError::ErrorCode(724, _) => AddPortError::SamePortValuesRequired,
-        RequestError::ErrorCode(725, _) => AddPortError::OnlyPermanentLeasesSupported,
+        RequestError::ErrorCode(UpnpErrorCode::StringArgumentTooLong, _) => AddPortError::DescriptionTooLong,
+        RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => AddPortError::ActionNotAuthorized,
+        RequestError::ErrorCode(UpnpErrorCode::ConflictInMappingEntry, _) => AddPortError::PortInUse,
+        RequestError::ErrorCode(UpnpErrorCode::ConflictWithOtherMechanisms, _) => {
+            AddPortError::ConflictWithOtherMechanisms
+        }
+        RequestError::ErrorCode(UpnpErrorCode::SamePortValuesRequired, _) => AddPortError::SamePortValuesRequired,
+        RequestError::ErrorCode(UpnpErrorCode::OnlyPermanentLeasesSupported, _) => {
+            AddPortError::OnlyPermanentLeasesSupported
+        }
         e => AddPortError::RequestError(e),
     }
 }
 
+/// Convert the error of removing a mapping in order to refresh it.
+pub fn convert_refresh_remove_error(err: RemovePortError) -> AddPortError {
+    match err {
+        RemovePortError::ActionNotAuthorized => AddPortError::ActionNotAuthorized,
+        RemovePortError::NoSuchPortMapping => AddPortError::PortInUse,
+        RemovePortError::RequestError(e) => AddPortError::RequestError(e),
+    }
+}
+
 pub fn parse_delete_port_mapping_response(result: RequestResult) -> Result<(), RemovePortError> {
     match result {
         Ok(_) => Ok(()),
         Err(err) => Err(match err {
-            RequestError::ErrorCode(606, _) => RemovePortError::ActionNotAuthorized,
-            RequestError::ErrorCode(714, _) => RemovePortError::NoSuchPortMapping,
+            RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => RemovePortError::ActionNotAuthorized,
+            RequestError::ErrorCode(UpnpErrorCode::NoSuchEntryInArray, _) => RemovePortError::NoSuchPortMapping,
             e => RemovePortError::RequestError(e),
         }),
     }
 }
 
+pub fn parse_get_default_connection_service_response(
+    result: RequestResult,
+) -> Result<String, DefaultConnectionServiceError> {
+    let resp = result.map_err(convert_default_connection_service_error)?;
+    match resp
+        .xml
+        .get_child("NewDefaultConnectionService")
+        .and_then(|e| e.get_text())
+    {
+        Some(service) => Ok(service.trim().to_owned()),
+        None => Err(DefaultConnectionServiceError::RequestError(
+            RequestError::InvalidResponse(resp.text),
+        )),
+    }
+}
+
+pub fn convert_default_connection_service_error(err: RequestError) -> DefaultConnectionServiceError {
+    match err {
+        RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => {
+            DefaultConnectionServiceError::ActionNotAuthorized
+        }
+        RequestError::ErrorCode(UpnpErrorCode::InvalidDeviceUuid, _) => {
+            DefaultConnectionServiceError::InvalidDeviceUuid
+        }
+        RequestError::ErrorCode(UpnpErrorCode::InvalidServiceId, _) => DefaultConnectionServiceError::InvalidServiceId,
+        RequestError::ErrorCode(UpnpErrorCode::InvalidConnServiceSelection, _) => {
+            DefaultConnectionServiceError::InvalidConnServiceSelection
+        }
+        e => DefaultConnectionServiceError::RequestError(e),
+    }
+}
+
 /// One port mapping entry as returned by GetGenericPortMappingEntry
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PortMappingEntry {
     /// The remote host for which the mapping is valid
     /// Can be an IP address or a host name
@@ -334,6 +539,32 @@ pub struct PortMappingEntry {
     pub lease_duration: u32,
 }
 
+impl PortMappingEntry {
+    /// The internal client and port, if the internal client is an IP address.
+    pub fn internal_addr(&self) -> Option<SocketAddr> {
+        let ip = self.internal_client.parse::<IpAddr>().ok()?;
+        Some(SocketAddr::new(ip, self.internal_port))
+    }
+}
+
+pub fn parse_get_specific_port_mapping_entry(
+    result: RequestResult,
+    protocol: PortMappingProtocol,
+    external_port: u16,
+) -> Result<PortMappingEntry, GetSpecificPortMappingEntryError> {
+    let response = result?;
+    Ok(PortMappingEntry {
+        remote_host: String::new(),
+        external_port,
+        protocol,
+        internal_port: response.parse_argument("NewInternalPort")?,
+        internal_client: response.argument("NewInternalClient")?,
+        enabled: response.bool_argument("NewEnabled")?,
+        port_mapping_description: response.argument("NewPortMappingDescription")?,
+        lease_duration: response.parse_argument("NewLeaseDuration")?,
+    })
+}
+
 pub fn parse_get_generic_port_mapping_entry(
     result: RequestResult,
 ) -> Result<PortMappingEntry, GetGenericPortMappingEntryError> {
@@ -497,7 +728,12 @@ fn test_parse_device1() {
    </device>
 </root>"#;
 
-    let (service_type, control_schema_url, control_url) = parse_control_urls(text.as_bytes()).unwrap();
+    let service = parse_services(text.as_bytes())
+        .unwrap()
+        .into_iter()
+        .find(ServiceDescription::is_wan_connection)
+        .unwrap();
+    let (service_type, control_schema_url, control_url) = (service.service_type, service.scpd_url, service.control_url);
     assert_eq!(service_type, "urn:schemas-upnp-org:service:WANIPConnection:1");
     assert_eq!(control_url, "/ctl/IPConn");
     assert_eq!(control_schema_url, "/WANIPCn.xml");
@@ -603,9 +839,12 @@ fn test_parse_device2() {
         </device>
     </root>
     "#;
-    let result = parse_control_urls(text.as_bytes());
+    let result = parse_services(text.as_bytes());
     assert!(result.is_ok());
-    let (service_type, control_schema_url, control_url) = result.unwrap();
+    let services = result.unwrap();
+    let service = services.iter().find(|s| s.is_wan_connection()).unwrap();
+    let (service_type, control_schema_url, control_url) =
+        (&service.service_type, &service.scpd_url, &service.control_url);
     assert_eq!(service_type, "urn:schemas-upnp-org:service:WANIPConnection:1");
     assert_eq!(control_url, "/igdupnp/control/WANIPConn1");
     assert_eq!(control_schema_url, "/igdconnSCPD.xml");
@@ -693,7 +932,12 @@ fn test_parse_device3() {
 </device>
 </root>"#;
 
-    let (service_type, control_schema_url, control_url) = parse_control_urls(text.as_bytes()).unwrap();
+    let service = parse_services(text.as_bytes())
+        .unwrap()
+        .into_iter()
+        .find(ServiceDescription::is_wan_connection)
+        .unwrap();
+    let (service_type, control_schema_url, control_url) = (service.service_type, service.scpd_url, service.control_url);
     assert_eq!(service_type, "urn:schemas-upnp-org:service:WANIPConnection:1");
     assert_eq!(control_url, "/upnp/control/WANIPConn1");
     assert_eq!(control_schema_url, "/332b484d/wanipconnSCPD.xml");
@@ -728,8 +972,270 @@ fn test_parse_device_pppconnection() {
    </device>
 </root>"#;
 
-    let (service_type, control_schema_url, control_url) = parse_control_urls(text.as_bytes()).unwrap();
+    let service = parse_services(text.as_bytes())
+        .unwrap()
+        .into_iter()
+        .find(ServiceDescription::is_wan_connection)
+        .unwrap();
+    let (service_type, control_schema_url, control_url) = (service.service_type, service.scpd_url, service.control_url);
     assert_eq!(service_type, "urn:schemas-upnp-org:service:WANPPPConnection:1");
     assert_eq!(control_url, "/ctl/PPPConn");
     assert_eq!(control_schema_url, "/WANPPPCn.xml");
 }
+
+#[test]
+fn test_find_default_connection_service() {
+    // A gateway with two WAN connection devices, whose Layer3Forwarding service points at the
+    // second one.
+    let text = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+   <device>
+      <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+      <UDN>uuid:11111111-0000-0000-0000-000000000000</UDN>
+      <serviceList>
+         <service>
+            <serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>
+            <serviceId>urn:upnp-org:serviceId:L3Forwarding1</serviceId>
+            <controlURL>/ctl/L3F</controlURL>
+            <eventSubURL>/evt/L3F</eventSubURL>
+            <SCPDURL>/L3F.xml</SCPDURL>
+         </service>
+      </serviceList>
+      <deviceList>
+         <device>
+            <deviceType>urn:schemas-upnp-org:device:WANDevice:1</deviceType>
+            <UDN>uuid:22222222-0000-0000-0000-000000000000</UDN>
+            <deviceList>
+               <device>
+                  <deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>
+                  <UDN>uuid:33333333-0000-0000-0000-000000000000</UDN>
+                  <serviceList>
+                     <service>
+                        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+                        <serviceId>urn:upnp-org:serviceId:WANIPConn1</serviceId>
+                        <controlURL>/ctl/IPConn1</controlURL>
+                        <eventSubURL>/evt/IPConn1</eventSubURL>
+                        <SCPDURL>/WANIPCn.xml</SCPDURL>
+                     </service>
+                  </serviceList>
+               </device>
+               <device>
+                  <deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>
+                  <UDN>uuid:44444444-0000-0000-0000-000000000000</UDN>
+                  <serviceList>
+                     <service>
+                        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+                        <serviceId>urn:upnp-org:serviceId:WANIPConn1</serviceId>
+                        <controlURL>/ctl/IPConn2</controlURL>
+                        <eventSubURL>/evt/IPConn2</eventSubURL>
+                        <SCPDURL>/WANIPCn.xml</SCPDURL>
+                     </service>
+                  </serviceList>
+               </device>
+            </deviceList>
+         </device>
+      </deviceList>
+   </device>
+</root>"#;
+
+    let services = parse_services(text.as_bytes()).unwrap();
+    assert_eq!(services.len(), 3);
+    assert_eq!(services[0].service_type, LAYER3_FORWARDING_SERVICE);
+    assert_eq!(services[0].device_udn, "uuid:11111111-0000-0000-0000-000000000000");
+    assert_eq!(services[2].event_sub_url, "/evt/IPConn2");
+
+    let service = find_default_connection_service(
+        &services,
+        "uuid:44444444-0000-0000-0000-000000000000:WANConnectionDevice:1,urn:upnp-org:serviceId:WANIPConn1",
+    )
+    .unwrap();
+    assert_eq!(service.control_url, "/ctl/IPConn2");
+
+    // An unknown device part falls back to the first service with a matching service id.
+    let service = find_default_connection_service(
+        &services,
+        "uuid:55555555-0000-0000-0000-000000000000:WANConnectionDevice:1,urn:upnp-org:serviceId:WANIPConn1",
+    )
+    .unwrap();
+    assert_eq!(service.control_url, "/ctl/IPConn1");
+
+    // The device part must be the whole UDN, not only start with it.
+    let service = find_default_connection_service(
+        &services,
+        "uuid:44444444-0000-0000-0000-0000000000001:WANConnectionDevice:1,urn:upnp-org:serviceId:WANIPConn1",
+    )
+    .unwrap();
+    assert_eq!(service.control_url, "/ctl/IPConn1");
+
+    assert!(find_default_connection_service(&services, "urn:upnp-org:serviceId:WANIPConn1").is_none());
+    assert!(find_default_connection_service(
+        &services,
+        "uuid:44444444-0000-0000-0000-000000000000:WANConnectionDevice:1,urn:upnp-org:serviceId:WANPPPConn1"
+    )
+    .is_none());
+}
+
+#[test]
+fn test_parse_out_arguments() {
+    let text = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:GetStatusInfoResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+<NewConnectionStatus>Connected</NewConnectionStatus>
+<NewLastConnectionError>ERROR_NONE</NewLastConnectionError>
+<NewUptime>1234</NewUptime>
+</u:GetStatusInfoResponse>
+</s:Body>
+</s:Envelope>"#;
+    let args = parse_out_arguments(parse_response(text.to_string(), "GetStatusInfoResponse")).unwrap();
+    assert_eq!(
+        args.iter().collect::<Vec<_>>(),
+        [
+            ("NewConnectionStatus", "Connected"),
+            ("NewLastConnectionError", "ERROR_NONE"),
+            ("NewUptime", "1234")
+        ]
+    );
+    assert_eq!(args.get("NewUptime"), Some("1234"));
+    assert_eq!(args.get("NewMissing"), None);
+}
+
+#[test]
+fn test_parse_get_default_connection_service_response() {
+    let text = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:GetDefaultConnectionServiceResponse xmlns:u="urn:schemas-upnp-org:service:Layer3Forwarding:1">
+<NewDefaultConnectionService>uuid:44444444-0000-0000-0000-000000000000:WANConnectionDevice:1,urn:upnp-org:serviceId:WANIPConn1</NewDefaultConnectionService>
+</u:GetDefaultConnectionServiceResponse>
+</s:Body>
+</s:Envelope>"#;
+    let service = parse_get_default_connection_service_response(parse_response(
+        text.to_string(),
+        "GetDefaultConnectionServiceResponse",
+    ))
+    .unwrap();
+    assert_eq!(
+        service,
+        "uuid:44444444-0000-0000-0000-000000000000:WANConnectionDevice:1,urn:upnp-org:serviceId:WANIPConn1"
+    );
+
+    assert!(matches!(
+        parse_get_default_connection_service_response(Err(RequestError::ErrorCode(
+            UpnpErrorCode::InvalidServiceId,
+            "Invalid".into()
+        ))),
+        Err(DefaultConnectionServiceError::InvalidServiceId)
+    ));
+}
+
+#[test]
+fn test_parse_get_specific_port_mapping_entry() {
+    let text = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:GetSpecificPortMappingEntryResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+<NewInternalPort>8080</NewInternalPort>
+<NewInternalClient>192.168.1.5</NewInternalClient>
+<NewEnabled>1</NewEnabled>
+<NewPortMappingDescription>web</NewPortMappingDescription>
+<NewLeaseDuration>3600</NewLeaseDuration>
+</u:GetSpecificPortMappingEntryResponse>
+</s:Body>
+</s:Envelope>"#;
+    let entry = parse_get_specific_port_mapping_entry(
+        parse_response(text.to_string(), "GetSpecificPortMappingEntryResponse"),
+        PortMappingProtocol::TCP,
+        80,
+    )
+    .unwrap();
+    assert_eq!(entry.external_port, 80);
+    assert_eq!(entry.internal_addr(), Some("192.168.1.5:8080".parse().unwrap()));
+    assert!(entry.enabled);
+    assert_eq!(entry.port_mapping_description, "web");
+    assert_eq!(entry.lease_duration, 3600);
+
+    assert!(matches!(
+        parse_get_specific_port_mapping_entry(
+            Err(RequestError::ErrorCode(
+                UpnpErrorCode::NoSuchEntryInArray,
+                "NoSuchEntryInArray".into()
+            )),
+            PortMappingProtocol::TCP,
+            80
+        ),
+        Err(GetSpecificPortMappingEntryError::NoSuchEntryInArray)
+    ));
+}
+
+#[test]
+fn test_parse_upnp_error_code() {
+    let fault = |code: u16| {
+        format!(
+            r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<s:Fault>
+<faultcode>s:Client</faultcode>
+<faultstring>UPnPError</faultstring>
+<detail>
+<UPnPError xmlns="urn:schemas-upnp-org:control-1-0">
+<errorCode>{code}</errorCode>
+<errorDescription>Error {code}</errorDescription>
+</UPnPError>
+</detail>
+</s:Fault>
+</s:Body>
+</s:Envelope>"#
+        )
+    };
+
+    let error = convert_add_port_error(parse_response(fault(718), "AddPortMappingResponse").err().unwrap());
+    assert!(matches!(error, AddPortError::PortInUse));
+    assert_eq!(error.upnp_error_code(), Some(UpnpErrorCode::ConflictInMappingEntry));
+
+    let error = convert_add_port_error(parse_response(fault(729), "AddPortMappingResponse").err().unwrap());
+    assert!(matches!(error, AddPortError::ConflictWithOtherMechanisms));
+    assert_eq!(
+        error.upnp_error_code(),
+        Some(UpnpErrorCode::ConflictWithOtherMechanisms)
+    );
+
+    let error =
+        convert_add_same_port_mapping_error(parse_response(fault(729), "AddPortMappingResponse").err().unwrap());
+    assert_eq!(
+        error.upnp_error_code(),
+        Some(UpnpErrorCode::ConflictWithOtherMechanisms)
+    );
+
+    // A conflict on a random port keeps its code, so that another port is tried
+    let error =
+        convert_add_random_port_mapping_error(parse_response(fault(718), "AddPortMappingResponse").err().unwrap());
+    assert_eq!(
+        error.unwrap().upnp_error_code(),
+        Some(UpnpErrorCode::ConflictInMappingEntry)
+    );
+
+    // The wrapping errors hand out the code of the IGD error
+    let error =
+        convert_add_same_port_mapping_error(parse_response(fault(728), "AddPortMappingResponse").err().unwrap());
+    let error = crate::Error::from(crate::PortMapperError::from(error));
+    assert_eq!(error.upnp_error_code(), Some(UpnpErrorCode::NoPortMapsAvailable));
+
+    let error = convert_add_port_error(parse_response(fault(732), "AddPortMappingResponse").err().unwrap());
+    assert_eq!(
+        error.upnp_error_code(),
+        Some(UpnpErrorCode::WildCardNotPermittedInIntPort)
+    );
+    assert_eq!(
+        error.to_string(),
+        "Request error. Gateway response error 732 (WildCardNotPermittedInIntPort): Error 732"
+    );
+
+    let error = parse_delete_port_mapping_response(parse_response(fault(899), "DeletePortMappingResponse"));
+    assert_eq!(error.unwrap_err().upnp_error_code(), Some(UpnpErrorCode::Other(899)));
+
+    for code in [401, 402, 501, 600, 605, 606, 713, 726, 727, 729, 730, 731, 733, 899] {
+        assert_eq!(UpnpErrorCode::from(code).code(), code);
+    }
+}
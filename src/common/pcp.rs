@@ -0,0 +1,487 @@
+//! Messages of the Port Control Protocol (RFC 6887).
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use rand::{self, RngExt};
+
+use crate::errors::PcpError;
+use crate::PortMappingProtocol;
+
+/// Port PCP servers listen on.
+pub const PCP_PORT: u16 = 5351;
+
+/// Delay before the first retransmission of a request (IRT).
+pub const INITIAL_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Longest delay between retransmissions (MRT).
+pub const MAX_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(1024);
+
+/// Default number of times a request is sent before giving up, which waits about 45 seconds.
+pub const MAX_ATTEMPTS: u32 = 4;
+
+/// Size of the largest message, used as the receive buffer size.
+pub const MAX_MESSAGE_SIZE: usize = 1100;
+
+const VERSION: u8 = 2;
+
+const RESPONSE_BIT: u8 = 0x80;
+
+const HEADER_SIZE: usize = 24;
+
+const ANNOUNCE_OPCODE: u8 = 0;
+
+const MAP_OPCODE: u8 = 1;
+
+const PEER_OPCODE: u8 = 2;
+
+const MAP_PAYLOAD_SIZE: usize = 36;
+
+const PEER_PAYLOAD_SIZE: usize = 56;
+
+const PREFER_FAILURE_OPTION: u8 = 2;
+
+/// A mapping nonce, which identifies the mappings of a client to the server.
+pub type Nonce = [u8; 12];
+
+/// The opcode a mapping was created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PcpMappingKind {
+    /// An inbound mapping, created with the MAP opcode
+    Map,
+    /// An outbound mapping to a single remote peer, created with the PEER opcode
+    Peer {
+        /// The address of the remote peer
+        remote_peer: SocketAddr,
+    },
+}
+
+/// A mapping granted by a PCP server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcpMapping {
+    /// The opcode of the mapping
+    pub kind: PcpMappingKind,
+    /// The nonce of the mapping, which must be sent again to renew or delete it
+    pub nonce: Nonce,
+    /// The protocol of the mapping
+    pub protocol: PortMappingProtocol,
+    /// The internal (local) port
+    pub internal_port: u16,
+    /// The external address and port, which may differ from the suggested ones
+    pub external_addr: SocketAddr,
+    /// How long the mapping lasts unless it is renewed
+    pub lifetime: Duration,
+    /// Seconds since the server's mapping state was initialized
+    pub epoch: u32,
+    /// Whether the epoch shows the server lost its state, and so its mappings, since the
+    /// previous response
+    pub gateway_restarted: bool,
+}
+
+impl PcpMapping {
+    /// When to renew the mapping after it was granted, i.e. at 5/8 of its lifetime
+    /// (RFC 6887 section 11.2.1).
+    pub fn renew_after(&self) -> Duration {
+        self.lifetime * 5 / 8
+    }
+}
+
+/// Options of a MAP request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PcpMapOptions {
+    /// External address and port to ask for. A port of 0 lets the server choose, as does an
+    /// unspecified address.
+    pub suggested_external_addr: Option<SocketAddr>,
+    /// Fail with `CannotProvideExternal` rather than map another external address or port
+    /// than the suggested one.
+    pub prefer_failure: bool,
+}
+
+/// The reply to an ANNOUNCE request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcpAnnounce {
+    /// Seconds since the server's mapping state was initialized
+    pub epoch: u32,
+    /// Whether the epoch shows the server lost its state since the previous response
+    pub gateway_restarted: bool,
+}
+
+/// Generate a new mapping nonce.
+pub fn new_nonce() -> Nonce {
+    let mut nonce = [0u8; 12];
+    rand::rng().fill(&mut nonce);
+    nonce
+}
+
+/// Delay before the retransmission following one that waited `previous`, with the
+/// random factor of RFC 6887 section 8.1.1.
+pub fn retransmission_timeout(previous: Option<Duration>) -> Duration {
+    let jitter = rand::rng().random_range(0.9..1.1);
+    match previous {
+        None => INITIAL_RETRANSMISSION_TIMEOUT.mul_f64(jitter),
+        Some(previous) => (previous * 2).min(MAX_RETRANSMISSION_TIMEOUT).mul_f64(jitter),
+    }
+}
+
+fn protocol_number(protocol: PortMappingProtocol) -> u8 {
+    match protocol {
+        PortMappingProtocol::TCP => 6,
+        PortMappingProtocol::UDP => 17,
+    }
+}
+
+/// The 128-bit form of an address, with IPv4 addresses mapped into IPv6.
+fn address_bytes(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+fn parse_address(bytes: &[u8]) -> IpAddr {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&bytes[..16]);
+    let ip = Ipv6Addr::from(octets);
+    match ip.to_ipv4_mapped() {
+        Some(ip) => IpAddr::V4(ip),
+        None => IpAddr::V6(ip),
+    }
+}
+
+fn format_header(opcode: u8, lifetime: u32, client: IpAddr) -> Vec<u8> {
+    let mut request = Vec::with_capacity(HEADER_SIZE + PEER_PAYLOAD_SIZE + 4);
+    request.extend_from_slice(&[VERSION, opcode, 0, 0]);
+    request.extend_from_slice(&lifetime.to_be_bytes());
+    request.extend_from_slice(&address_bytes(client));
+    request
+}
+
+/// The wildcard suggestion for the external address, in the family of the client address.
+fn unspecified_like(client: IpAddr) -> IpAddr {
+    match client {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
+
+pub fn format_announce_request(client: IpAddr) -> Vec<u8> {
+    format_header(ANNOUNCE_OPCODE, 0, client)
+}
+
+/// Build a MAP request. A lifetime of 0 deletes the mapping with the same nonce.
+pub fn format_map_request(
+    client: IpAddr,
+    nonce: &Nonce,
+    protocol: PortMappingProtocol,
+    internal_port: u16,
+    lifetime: u32,
+    options: &PcpMapOptions,
+) -> Vec<u8> {
+    let suggested = options
+        .suggested_external_addr
+        .unwrap_or_else(|| SocketAddr::new(unspecified_like(client), 0));
+    let mut request = format_header(MAP_OPCODE, lifetime, client);
+    request.extend_from_slice(nonce);
+    request.extend_from_slice(&[protocol_number(protocol), 0, 0, 0]);
+    request.extend_from_slice(&internal_port.to_be_bytes());
+    request.extend_from_slice(&suggested.port().to_be_bytes());
+    request.extend_from_slice(&address_bytes(suggested.ip()));
+    if options.prefer_failure {
+        request.extend_from_slice(&[PREFER_FAILURE_OPTION, 0, 0, 0]);
+    }
+    request
+}
+
+/// Build a PEER request for the connection from `internal_port` to `remote_peer`.
+pub fn format_peer_request(
+    client: IpAddr,
+    nonce: &Nonce,
+    protocol: PortMappingProtocol,
+    internal_port: u16,
+    remote_peer: SocketAddr,
+    lifetime: u32,
+    suggested_external_addr: Option<SocketAddr>,
+) -> Vec<u8> {
+    let suggested = suggested_external_addr.unwrap_or_else(|| SocketAddr::new(unspecified_like(client), 0));
+    let mut request = format_header(PEER_OPCODE, lifetime, client);
+    request.extend_from_slice(nonce);
+    request.extend_from_slice(&[protocol_number(protocol), 0, 0, 0]);
+    request.extend_from_slice(&internal_port.to_be_bytes());
+    request.extend_from_slice(&suggested.port().to_be_bytes());
+    request.extend_from_slice(&address_bytes(suggested.ip()));
+    request.extend_from_slice(&remote_peer.port().to_be_bytes());
+    request.extend_from_slice(&[0, 0]);
+    request.extend_from_slice(&address_bytes(remote_peer.ip()));
+    request
+}
+
+fn convert_result_code(code: u8) -> Result<(), PcpError> {
+    match code {
+        0 => Ok(()),
+        1 => Err(PcpError::UnsupportedVersion),
+        2 => Err(PcpError::NotAuthorized),
+        3 => Err(PcpError::MalformedRequest),
+        4 => Err(PcpError::UnsupportedOpcode),
+        5 => Err(PcpError::UnsupportedOption),
+        6 => Err(PcpError::MalformedOption),
+        7 => Err(PcpError::NetworkFailure),
+        8 => Err(PcpError::NoResources),
+        9 => Err(PcpError::UnsupportedProtocol),
+        10 => Err(PcpError::UserExceededQuota),
+        11 => Err(PcpError::CannotProvideExternal),
+        12 => Err(PcpError::AddressMismatch),
+        13 => Err(PcpError::ExcessiveRemotePeers),
+        code => Err(PcpError::UnknownResultCode(code)),
+    }
+}
+
+fn u16_at(packet: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([packet[at], packet[at + 1]])
+}
+
+fn u32_at(packet: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([packet[at], packet[at + 1], packet[at + 2], packet[at + 3]])
+}
+
+/// The header of a response: its lifetime and epoch.
+struct ResponseHeader {
+    lifetime: u32,
+    epoch: u32,
+}
+
+/// Check the header of a response to a request with `opcode`.
+///
+/// `Ok(None)` means the packet is not a response to the request and should be ignored.
+fn parse_header(packet: &[u8], opcode: u8, payload_size: usize) -> Result<Option<ResponseHeader>, PcpError> {
+    if packet.len() < 4 || packet[1] != RESPONSE_BIT | opcode {
+        return Ok(None);
+    }
+    // A server that only speaks NAT-PMP replies with version 0 and its own result code.
+    if packet[0] != VERSION {
+        return Err(PcpError::UnsupportedVersion);
+    }
+    if packet.len() < HEADER_SIZE + payload_size || packet.len() % 4 != 0 {
+        return Err(PcpError::InvalidResponse(format!("response of {} bytes", packet.len())));
+    }
+    let header = ResponseHeader {
+        lifetime: u32_at(packet, 4),
+        epoch: u32_at(packet, 8),
+    };
+    convert_result_code(packet[3])?;
+    Ok(Some(header))
+}
+
+fn packet_nonce(packet: &[u8]) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce.copy_from_slice(&packet[HEADER_SIZE..HEADER_SIZE + 12]);
+    nonce
+}
+
+/// Parse a response to an ANNOUNCE request, returning its epoch.
+pub fn parse_announce_response(packet: &[u8]) -> Result<Option<u32>, PcpError> {
+    Ok(parse_header(packet, ANNOUNCE_OPCODE, 0)?.map(|header| header.epoch))
+}
+
+/// Parse a response to a MAP or PEER request, ignoring responses whose nonce, protocol or
+/// internal port differ from the request's.
+///
+/// The returned mapping has not been checked against the epoch yet.
+pub fn parse_mapping_response(
+    packet: &[u8],
+    kind: PcpMappingKind,
+    nonce: &Nonce,
+    protocol: PortMappingProtocol,
+    internal_port: u16,
+) -> Result<Option<PcpMapping>, PcpError> {
+    let (opcode, payload_size) = match kind {
+        PcpMappingKind::Map => (MAP_OPCODE, MAP_PAYLOAD_SIZE),
+        PcpMappingKind::Peer { .. } => (PEER_OPCODE, PEER_PAYLOAD_SIZE),
+    };
+    // Error responses echo the request too, so the nonce tells whether an error is for it.
+    if packet.len() >= HEADER_SIZE + 12 && packet_nonce(packet) != *nonce {
+        return Ok(None);
+    }
+    let header = match parse_header(packet, opcode, payload_size)? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let payload = &packet[HEADER_SIZE..];
+    if payload[12] != protocol_number(protocol) || u16_at(payload, 16) != internal_port {
+        return Ok(None);
+    }
+    if let PcpMappingKind::Peer { remote_peer } = kind {
+        if SocketAddr::new(parse_address(&payload[40..56]), u16_at(payload, 36)) != remote_peer {
+            return Ok(None);
+        }
+    }
+    Ok(Some(PcpMapping {
+        kind,
+        nonce: *nonce,
+        protocol,
+        internal_port,
+        external_addr: SocketAddr::new(parse_address(&payload[20..36]), u16_at(payload, 18)),
+        lifetime: Duration::from_secs(header.lifetime.into()),
+        epoch: header.epoch,
+        gateway_restarted: false,
+    }))
+}
+
+/// Detects servers that lost their state from the epochs of successive responses
+/// (RFC 6887 section 8.5).
+#[derive(Debug, Default)]
+pub struct EpochTracker {
+    last: Option<(u32, Instant)>,
+}
+
+impl EpochTracker {
+    /// Record the epoch of a response received at `now`, returning whether it shows the
+    /// server lost its state since the previous response.
+    pub fn observe(&mut self, epoch: u32, now: Instant) -> bool {
+        let restarted = match self.last {
+            Some((last_epoch, _)) if epoch < last_epoch => true,
+            Some((last_epoch, last_time)) => {
+                let client_delta = now.saturating_duration_since(last_time).as_secs();
+                let server_delta = u64::from(epoch - last_epoch);
+                server_delta + 2 < client_delta - client_delta / 16
+                    || client_delta + 2 < server_delta - server_delta / 16
+            }
+            None => false,
+        };
+        self.last = Some((epoch, now));
+        restarted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONCE: Nonce = [7; 12];
+
+    fn map_response(result: u8, nonce: &Nonce, internal_port: u16, external: SocketAddr) -> Vec<u8> {
+        let mut packet = vec![VERSION, RESPONSE_BIT | MAP_OPCODE, 0, result];
+        packet.extend_from_slice(&3600u32.to_be_bytes());
+        packet.extend_from_slice(&50u32.to_be_bytes());
+        packet.extend_from_slice(&[0; 12]);
+        packet.extend_from_slice(nonce);
+        packet.extend_from_slice(&[6, 0, 0, 0]);
+        packet.extend_from_slice(&internal_port.to_be_bytes());
+        packet.extend_from_slice(&external.port().to_be_bytes());
+        packet.extend_from_slice(&address_bytes(external.ip()));
+        packet
+    }
+
+    #[test]
+    fn map_request_layout() {
+        let client: IpAddr = "192.168.1.5".parse().unwrap();
+        let options = PcpMapOptions {
+            suggested_external_addr: Some("203.0.113.7:8080".parse().unwrap()),
+            prefer_failure: true,
+        };
+        let request = format_map_request(client, &NONCE, PortMappingProtocol::TCP, 80, 7200, &options);
+        assert_eq!(request.len(), HEADER_SIZE + MAP_PAYLOAD_SIZE + 4);
+        assert_eq!(&request[..8], &[2, 1, 0, 0, 0, 0, 0x1c, 0x20]);
+        assert_eq!(&request[8..24], &address_bytes(client));
+        assert_eq!(&request[20..24], &[192, 168, 1, 5]);
+        assert_eq!(&request[24..36], &NONCE);
+        assert_eq!(&request[36..42], &[6, 0, 0, 0, 0, 80]);
+        assert_eq!(&request[42..44], &8080u16.to_be_bytes());
+        assert_eq!(&request[56..60], &[203, 0, 113, 7]);
+        assert_eq!(&request[60..], &[PREFER_FAILURE_OPTION, 0, 0, 0]);
+
+        let request = format_map_request(
+            client,
+            &NONCE,
+            PortMappingProtocol::UDP,
+            80,
+            0,
+            &PcpMapOptions::default(),
+        );
+        assert_eq!(request.len(), HEADER_SIZE + MAP_PAYLOAD_SIZE);
+        assert_eq!(parse_address(&request[44..60]), IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+
+    #[test]
+    fn peer_request_layout() {
+        let client: IpAddr = "2001:db8::5".parse().unwrap();
+        let remote: SocketAddr = "[2001:db8:1::9]:443".parse().unwrap();
+        let request = format_peer_request(client, &NONCE, PortMappingProtocol::TCP, 5000, remote, 600, None);
+        assert_eq!(request.len(), HEADER_SIZE + PEER_PAYLOAD_SIZE);
+        assert_eq!(request[1], PEER_OPCODE);
+        assert_eq!(u16_at(&request, 60), 443);
+        assert_eq!(parse_address(&request[64..80]), remote.ip());
+    }
+
+    #[test]
+    fn parse_map_response_checks_the_request() {
+        let external: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+        let packet = map_response(0, &NONCE, 80, external);
+        let mapping = parse_mapping_response(&packet, PcpMappingKind::Map, &NONCE, PortMappingProtocol::TCP, 80)
+            .unwrap()
+            .unwrap();
+        assert_eq!(mapping.external_addr, external);
+        assert_eq!(mapping.lifetime, Duration::from_secs(3600));
+        assert_eq!(mapping.epoch, 50);
+        assert_eq!(mapping.renew_after(), Duration::from_secs(2250));
+
+        // Responses with another nonce, protocol or port are not ours.
+        assert!(
+            parse_mapping_response(&packet, PcpMappingKind::Map, &[1; 12], PortMappingProtocol::TCP, 80)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            parse_mapping_response(&packet, PcpMappingKind::Map, &NONCE, PortMappingProtocol::UDP, 80)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            parse_mapping_response(&packet, PcpMappingKind::Map, &NONCE, PortMappingProtocol::TCP, 81)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn result_codes() {
+        let external: SocketAddr = "0.0.0.0:0".parse().unwrap();
+        let packet = map_response(11, &NONCE, 80, external);
+        assert!(matches!(
+            parse_mapping_response(&packet, PcpMappingKind::Map, &NONCE, PortMappingProtocol::TCP, 80),
+            Err(PcpError::CannotProvideExternal)
+        ));
+        let packet = map_response(8, &NONCE, 80, external);
+        assert!(matches!(
+            parse_mapping_response(&packet, PcpMappingKind::Map, &NONCE, PortMappingProtocol::TCP, 80),
+            Err(PcpError::NoResources)
+        ));
+        // A NAT-PMP server rejects the version.
+        assert!(matches!(
+            parse_announce_response(&[0, 128, 0, 1, 0, 0, 0, 0]),
+            Err(PcpError::UnsupportedVersion)
+        ));
+        let mut packet = vec![VERSION, RESPONSE_BIT, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9];
+        packet.resize(HEADER_SIZE, 0);
+        assert_eq!(parse_announce_response(&packet).unwrap(), Some(9));
+    }
+
+    #[test]
+    fn epoch_tracking_detects_lost_state() {
+        let start = Instant::now();
+        let mut tracker = EpochTracker::default();
+        assert!(!tracker.observe(1000, start));
+        assert!(!tracker.observe(1100, start + Duration::from_secs(100)));
+        assert!(tracker.observe(10, start + Duration::from_secs(110)));
+        assert!(!tracker.observe(20, start + Duration::from_secs(120)));
+        assert!(tracker.observe(20, start + Duration::from_secs(220)));
+    }
+
+    #[test]
+    fn retransmission_timeouts_back_off() {
+        let first = retransmission_timeout(None);
+        assert!(first >= Duration::from_millis(2700) && first <= Duration::from_millis(3300));
+        let second = retransmission_timeout(Some(Duration::from_secs(3)));
+        assert!(second >= Duration::from_millis(5400) && second <= Duration::from_millis(6600));
+        assert!(retransmission_timeout(Some(MAX_RETRANSMISSION_TIMEOUT)) <= MAX_RETRANSMISSION_TIMEOUT.mul_f64(1.1));
+    }
+}
@@ -0,0 +1,110 @@
+//! Types shared by the sync and async port mappers.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use super::pcp::PcpMapping;
+use super::SearchOptions;
+use crate::PortMappingProtocol;
+
+/// Number of attempts made when probing a PCP server, which waits about 9 seconds.
+pub const PCP_PROBE_ATTEMPTS: u32 = 2;
+
+/// Number of attempts made when probing a NAT-PMP gateway, which waits about 4 seconds.
+pub const NATPMP_PROBE_ATTEMPTS: u32 = 4;
+
+/// Port PCP servers and NAT-PMP gateways listen on.
+pub const SERVER_PORT: u16 = 5351;
+
+/// Address used to find the interface of the default route. Connecting a UDP socket to it
+/// sends nothing.
+const DEFAULT_ROUTE_PROBE: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 9);
+
+/// The router of the local network, for when none is configured and no UPnP gateway answers:
+/// the usual first host of the /24 of `bind_addr`, or of the address of the interface of the
+/// default route if `bind_addr` is unspecified.
+pub fn default_router(bind_addr: SocketAddr) -> Option<IpAddr> {
+    let local_ip = match bind_addr.ip() {
+        ip if ip.is_unspecified() => super::local_ip_for(DEFAULT_ROUTE_PROBE).ok()?,
+        ip => ip,
+    };
+    match local_ip {
+        IpAddr::V4(ip) => Some(IpAddr::V4(super::network_host(ip, 1))),
+        IpAddr::V6(_) => None,
+    }
+}
+
+/// A protocol used to map ports on the router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MappingMechanism {
+    /// UPnP Internet Gateway Device
+    Igd,
+    /// Port Control Protocol (RFC 6887)
+    Pcp,
+    /// NAT Port Mapping Protocol (RFC 6886)
+    NatPmp,
+}
+
+impl fmt::Display for MappingMechanism {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                MappingMechanism::Igd => "UPnP IGD",
+                MappingMechanism::Pcp => "PCP",
+                MappingMechanism::NatPmp => "NAT-PMP",
+            }
+        )
+    }
+}
+
+/// Port mapper configuration
+///
+/// PortMapperOptions::default() should suffice for most situations.
+pub struct PortMapperOptions {
+    /// Options of the UPnP gateway search
+    pub search: SearchOptions,
+    /// Address of the router to send PCP and NAT-PMP requests to. If it is not set, they are
+    /// sent to [`SERVER_PORT`] of the UPnP gateway found by the search, or else of the guess
+    /// of [`default_router`].
+    pub gateway: Option<SocketAddr>,
+    /// Mechanisms to probe, in order of preference (defaults to IGD, PCP, then NAT-PMP)
+    pub mechanisms: Vec<MappingMechanism>,
+    /// Description of the UPnP port mappings (defaults to the crate name)
+    pub description: String,
+}
+
+impl Default for PortMapperOptions {
+    fn default() -> Self {
+        Self {
+            search: SearchOptions::default(),
+            gateway: None,
+            mechanisms: vec![MappingMechanism::Igd, MappingMechanism::Pcp, MappingMechanism::NatPmp],
+            description: env!("CARGO_PKG_NAME").to_string(),
+        }
+    }
+}
+
+/// A port mapped by a port mapper.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedPort {
+    /// The mechanism the port was mapped with
+    pub mechanism: MappingMechanism,
+    /// The protocol of the mapping
+    pub protocol: PortMappingProtocol,
+    /// The local address traffic is forwarded to
+    pub local_addr: SocketAddr,
+    /// The external address and port
+    pub external_addr: SocketAddr,
+    /// The lease granted by the router. Zero means the mapping does not expire.
+    pub lease: Duration,
+    pub(crate) pcp: Option<PcpMapping>,
+}
+
+/// The lease to request from a UPnP gateway for `lease`, zero meaning a mapping that does
+/// not expire.
+pub fn igd_lease(lease: Duration) -> Option<Duration> {
+    (!lease.is_zero()).then_some(lease)
+}
@@ -0,0 +1,118 @@
+//! Messages and responses of the actions specific to the `WANPPPConnection:1` service.
+
+use super::messages;
+use super::parsing::RequestResult;
+use crate::errors::RequestError;
+
+pub const GET_LINK_LAYER_MAX_BIT_RATES_ACTION: &str = "GetLinkLayerMaxBitRates";
+
+pub const GET_USER_NAME_ACTION: &str = "GetUserName";
+
+pub const GET_PPP_ENCRYPTION_PROTOCOL_ACTION: &str = "GetPPPEncryptionProtocol";
+
+pub const GET_PPP_COMPRESSION_PROTOCOL_ACTION: &str = "GetPPPCompressionProtocol";
+
+pub const GET_PPP_AUTHENTICATION_PROTOCOL_ACTION: &str = "GetPPPAuthenticationProtocol";
+
+pub const CONFIGURE_CONNECTION_ACTION: &str = "ConfigureConnection";
+
+/// Maximum bit rates of the PPP link as returned by GetLinkLayerMaxBitRates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkLayerMaxBitRates {
+    /// Maximum upstream bit rate in bits per second
+    pub upstream_max_bit_rate: u32,
+    /// Maximum downstream bit rate in bits per second
+    pub downstream_max_bit_rate: u32,
+}
+
+/// Fail with `UnsupportedAction(action)` unless the service is a WANPPPConnection service.
+pub fn check_ppp_service(service_type: &str, action: &str) -> Result<(), RequestError> {
+    if service_type.starts_with("urn:schemas-upnp-org:service:WANPPPConnection:") {
+        Ok(())
+    } else {
+        Err(RequestError::UnsupportedAction(action.to_string()))
+    }
+}
+
+pub fn format_configure_connection_message(
+    service_type: &str,
+    schema: Option<&[String]>,
+    user_name: &str,
+    password: &str,
+) -> String {
+    messages::format_action_message(
+        service_type,
+        CONFIGURE_CONNECTION_ACTION,
+        &messages::order_arguments(
+            schema,
+            &[
+                ("NewUserName", user_name.to_string()),
+                ("NewPassword", password.to_string()),
+            ],
+        ),
+    )
+}
+
+pub fn parse_get_link_layer_max_bit_rates_response(
+    result: RequestResult,
+) -> Result<LinkLayerMaxBitRates, RequestError> {
+    let resp = result?;
+    Ok(LinkLayerMaxBitRates {
+        upstream_max_bit_rate: resp.parse_argument("NewUpstreamMaxBitRate")?,
+        downstream_max_bit_rate: resp.parse_argument("NewDownstreamMaxBitRate")?,
+    })
+}
+
+/// Parse the single string out-argument of the `GetUserName` and `GetPPP*Protocol` actions.
+pub fn parse_string_response(result: RequestResult, argument: &str) -> Result<String, RequestError> {
+    result?.argument(argument)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::parsing::parse_response;
+
+    const PPP: &str = "urn:schemas-upnp-org:service:WANPPPConnection:1";
+
+    #[test]
+    fn ppp_actions_require_ppp_service() {
+        assert!(check_ppp_service(PPP, GET_USER_NAME_ACTION).is_ok());
+        assert!(matches!(
+            check_ppp_service("urn:schemas-upnp-org:service:WANIPConnection:1", GET_USER_NAME_ACTION),
+            Err(RequestError::UnsupportedAction(action)) if action == GET_USER_NAME_ACTION
+        ));
+    }
+
+    #[test]
+    fn configure_connection_message_follows_schema() {
+        let schema = ["NewUserName".to_string(), "NewPassword".to_string()];
+        let body = format_configure_connection_message(PPP, Some(&schema), "user@isp", "p<ss");
+        assert!(body.contains("<NewUserName>user@isp</NewUserName>\n<NewPassword>p&lt;ss</NewPassword>"));
+    }
+
+    #[test]
+    fn parse_link_layer_max_bit_rates() {
+        let text = format!(
+            r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:GetLinkLayerMaxBitRatesResponse xmlns:u="{PPP}">
+<NewUpstreamMaxBitRate>1048576</NewUpstreamMaxBitRate>
+<NewDownstreamMaxBitRate>16777216</NewDownstreamMaxBitRate>
+</u:GetLinkLayerMaxBitRatesResponse>
+</s:Body>
+</s:Envelope>"#
+        );
+        let rates =
+            parse_get_link_layer_max_bit_rates_response(parse_response(text, "GetLinkLayerMaxBitRatesResponse"))
+                .unwrap();
+        assert_eq!(
+            rates,
+            LinkLayerMaxBitRates {
+                upstream_max_bit_rate: 1048576,
+                downstream_max_bit_rate: 16777216,
+            }
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Port ranges mapped as a whole by `add_port_range`.
+
+use std::net::SocketAddr;
+
+use crate::errors::{AddPortError, AddPortRangeError};
+
+/// The external ports of a range and the local addresses they are mapped to.
+pub fn port_range(
+    external_start: u16,
+    local_addr_start: SocketAddr,
+    count: u16,
+) -> Result<Vec<(u16, SocketAddr)>, AddPortRangeError> {
+    let last = count.checked_sub(1).ok_or(AddPortRangeError::InvalidRange)?;
+    if external_start.checked_add(last).is_none() || local_addr_start.port().checked_add(last).is_none() {
+        return Err(AddPortRangeError::InvalidRange);
+    }
+    Ok((0..count)
+        .map(|offset| {
+            let mut local_addr = local_addr_start;
+            local_addr.set_port(local_addr_start.port() + offset);
+            (external_start + offset, local_addr)
+        })
+        .collect())
+}
+
+/// The error of a range for the error of one of its ports.
+pub fn range_error(port: u16, error: AddPortError) -> AddPortRangeError {
+    match error {
+        AddPortError::PortInUse => AddPortRangeError::PortInUse(port),
+        error => AddPortRangeError::AddPortError { port, error },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_maps_consecutive_ports() {
+        let ports = port_range(10000, "192.168.1.5:20000".parse().unwrap(), 3).unwrap();
+        assert_eq!(
+            ports,
+            vec![
+                (10000, "192.168.1.5:20000".parse().unwrap()),
+                (10001, "192.168.1.5:20001".parse().unwrap()),
+                (10002, "192.168.1.5:20002".parse().unwrap()),
+            ]
+        );
+
+        let local_addr = "192.168.1.5:65535".parse().unwrap();
+        assert!(port_range(65535, local_addr, 1).is_ok());
+        assert!(matches!(
+            port_range(10000, local_addr, 2),
+            Err(AddPortRangeError::InvalidRange)
+        ));
+        assert!(matches!(
+            port_range(10000, local_addr, 0),
+            Err(AddPortRangeError::InvalidRange)
+        ));
+        assert!(matches!(
+            range_error(10001, AddPortError::PortInUse),
+            AddPortRangeError::PortInUse(10001)
+        ));
+    }
+}
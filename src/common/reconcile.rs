@@ -0,0 +1,194 @@
+//! Reconciliation of the gateway's port mappings with the desired ones.
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use super::batch::MappingOperation;
+use super::manager::MappingSpec;
+use super::parsing::PortMappingEntry;
+use crate::errors::{GetGenericPortMappingEntryError, UpnpErrorCode};
+use crate::RequestError;
+
+/// Upper bound on the entries read from the gateway, in case it never reports the end of the
+/// list.
+pub const MAX_PORT_MAPPING_ENTRIES: u32 = 1 << 16;
+
+/// Whether the error marks the end of the port mapping list. Some gateways answer with
+/// InvalidArgs instead of SpecifiedArrayIndexInvalid.
+pub fn is_end_of_entries(error: &GetGenericPortMappingEntryError) -> bool {
+    matches!(
+        error,
+        GetGenericPortMappingEntryError::SpecifiedArrayIndexInvalid
+            | GetGenericPortMappingEntryError::RequestError(RequestError::ErrorCode(UpnpErrorCode::InvalidArgs, _))
+    )
+}
+
+/// The changes that make the gateway's port mappings match the desired ones.
+///
+/// Only the mappings to this host that the caller claims as ours, usually by their
+/// description, are changed or removed; the others are left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReconcilePlan {
+    /// Desired mappings the gateway does not have
+    pub missing: Vec<MappingSpec>,
+    /// Our mappings that differ from the desired ones, with the desired mapping
+    pub changed: Vec<(PortMappingEntry, MappingSpec)>,
+    /// Our mappings that are not desired anymore
+    pub stale: Vec<PortMappingEntry>,
+}
+
+impl ReconcilePlan {
+    /// Whether the gateway already matches the desired mappings.
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.changed.is_empty() && self.stale.is_empty()
+    }
+
+    /// The operations applying the plan: the removes, then the adds.
+    pub fn operations(&self) -> Vec<MappingOperation> {
+        let removes = self
+            .stale
+            .iter()
+            .chain(self.changed.iter().map(|(entry, _)| entry))
+            .map(|entry| MappingOperation::Remove {
+                protocol: entry.protocol,
+                external_port: entry.external_port,
+            });
+        let adds = self
+            .changed
+            .iter()
+            .map(|(_, mapping)| mapping)
+            .chain(&self.missing)
+            .map(|mapping| MappingOperation::Add(mapping.clone()));
+        removes.chain(adds).collect()
+    }
+}
+
+/// Compare the gateway's entries with the desired mappings. Entries are ours when their
+/// internal client is one of `own_addresses` and `owns` accepts them, as other applications
+/// of this host map ports too.
+pub fn plan(
+    desired: &[MappingSpec],
+    entries: &[PortMappingEntry],
+    own_addresses: &HashSet<IpAddr>,
+    owns: impl Fn(&PortMappingEntry) -> bool,
+) -> ReconcilePlan {
+    let is_ours = |entry: &PortMappingEntry| {
+        entry
+            .internal_client
+            .parse::<IpAddr>()
+            .is_ok_and(|client| own_addresses.contains(&client))
+            && owns(entry)
+    };
+    let mut plan = ReconcilePlan::default();
+    for mapping in desired {
+        let entry = entries
+            .iter()
+            .find(|entry| entry.protocol == mapping.protocol && entry.external_port == mapping.external_port);
+        match entry {
+            Some(entry) if is_ours(entry) && !matches(entry, mapping) => {
+                plan.changed.push((entry.clone(), mapping.clone()))
+            }
+            Some(entry) if is_ours(entry) => {}
+            // A mapping of another host is left alone, adding ours reports the conflict.
+            _ => plan.missing.push(mapping.clone()),
+        }
+    }
+    plan.stale = entries
+        .iter()
+        .filter(|entry| is_ours(entry))
+        .filter(|entry| {
+            !desired
+                .iter()
+                .any(|mapping| entry.protocol == mapping.protocol && entry.external_port == mapping.external_port)
+        })
+        .cloned()
+        .collect();
+    plan
+}
+
+fn matches(entry: &PortMappingEntry, mapping: &MappingSpec) -> bool {
+    entry.enabled
+        && entry.internal_client.parse::<IpAddr>().ok() == Some(mapping.local_addr.ip())
+        && entry.internal_port == mapping.local_addr.port()
+        && entry.port_mapping_description == mapping.description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PortMappingProtocol;
+    use std::time::Duration;
+
+    fn mapping(external_port: u16, local_addr: &str) -> MappingSpec {
+        MappingSpec {
+            protocol: PortMappingProtocol::TCP,
+            external_port,
+            local_addr: local_addr.parse().unwrap(),
+            lease: Some(Duration::from_secs(3600)),
+            description: "app".to_string(),
+        }
+    }
+
+    fn entry(external_port: u16, internal_client: &str, internal_port: u16) -> PortMappingEntry {
+        PortMappingEntry {
+            remote_host: String::new(),
+            external_port,
+            protocol: PortMappingProtocol::TCP,
+            internal_port,
+            internal_client: internal_client.to_string(),
+            enabled: true,
+            port_mapping_description: "app".to_string(),
+            lease_duration: 1200,
+        }
+    }
+
+    #[test]
+    fn plan_only_touches_our_entries() {
+        let own_addresses = HashSet::from(["192.168.1.5".parse().unwrap()]);
+        let desired = [
+            mapping(1000, "192.168.1.5:1000"),
+            mapping(2000, "192.168.1.5:2001"),
+            mapping(3000, "192.168.1.5:3000"),
+            mapping(4000, "192.168.1.5:4000"),
+        ];
+        let entries = [
+            entry(1000, "192.168.1.5", 1000),
+            entry(2000, "192.168.1.5", 2000),
+            entry(3000, "192.168.1.9", 3000),
+            entry(5000, "192.168.1.5", 5000),
+            entry(6000, "192.168.1.9", 6000),
+        ];
+        let plan = plan(&desired, &entries, &own_addresses, |_| true);
+        assert_eq!(plan.missing, vec![desired[2].clone(), desired[3].clone()]);
+        assert_eq!(plan.changed, vec![(entries[1].clone(), desired[1].clone())]);
+        assert_eq!(plan.stale, vec![entries[3].clone()]);
+
+        let operations = plan.operations();
+        assert_eq!(operations.len(), 5);
+        assert_eq!(
+            operations[1],
+            MappingOperation::Remove {
+                protocol: PortMappingProtocol::TCP,
+                external_port: 2000
+            }
+        );
+        assert_eq!(operations[2], MappingOperation::Add(desired[1].clone()));
+    }
+
+    #[test]
+    fn entries_of_other_applications_on_this_host_survive() {
+        let own_addresses = HashSet::from(["192.168.1.5".parse().unwrap()]);
+        let other = |external_port| PortMappingEntry {
+            port_mapping_description: "other app".to_string(),
+            ..entry(external_port, "192.168.1.5", external_port)
+        };
+        let desired = [mapping(1000, "192.168.1.5:1001")];
+        let entries = [other(1000), other(2000), entry(3000, "192.168.1.5", 3000)];
+        let owns = |entry: &PortMappingEntry| entry.port_mapping_description == "app";
+
+        let plan = plan(&desired, &entries, &own_addresses, owns);
+        assert_eq!(plan.missing, desired);
+        assert!(plan.changed.is_empty());
+        assert_eq!(plan.stale, vec![entries[2].clone()]);
+    }
+}
@@ -0,0 +1,261 @@
+//! Binding requests of Session Traversal Utilities for NAT (RFC 5389), used to check the
+//! external address reported by a gateway.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use rand::{self, RngExt};
+
+use crate::errors::StunError;
+
+/// Port STUN servers listen on.
+pub const STUN_PORT: u16 = 3478;
+
+/// Delay before the first retransmission of a request, doubled after each one.
+pub const INITIAL_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Number of times a request is sent before giving up (Rc in RFC 5389 section 7.2.1).
+pub const MAX_ATTEMPTS: u32 = 7;
+
+/// How long the last request is waited for, in multiples of the initial retransmission
+/// timeout (Rm in RFC 5389 section 7.2.1).
+const LAST_WAIT_FACTOR: u32 = 16;
+
+/// Size of the receive buffer, larger than any response to a binding request.
+pub const MAX_RESPONSE_SIZE: usize = 576;
+
+const MAGIC_COOKIE: u32 = 0x2112_a442;
+
+const HEADER_SIZE: usize = 20;
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS_RESPONSE: u16 = 0x0101;
+const BINDING_ERROR_RESPONSE: u16 = 0x0111;
+
+const MAPPED_ADDRESS: u16 = 0x0001;
+const ERROR_CODE: u16 = 0x0009;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+/// The external address reported by a gateway, checked against the one a STUN server sees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalIpCheck {
+    /// The external address reported by the gateway
+    pub gateway_ip: IpAddr,
+    /// The address and port the STUN server received the request from
+    pub reflexive_addr: SocketAddr,
+}
+
+impl ExternalIpCheck {
+    /// Whether the STUN server saw another address than the gateway reports. The gateway
+    /// then likely is behind another NAT, or reports a stale address.
+    pub fn is_mismatch(&self) -> bool {
+        canonical(self.gateway_ip) != canonical(self.reflexive_addr.ip())
+    }
+}
+
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        ip => ip,
+    }
+}
+
+/// How long to wait for a response after sending request `attempt`, counted from 0: the
+/// retransmission timeout doubles after each request, and the last one is waited for 16
+/// times the initial timeout.
+pub fn response_wait(attempt: u32, max_attempts: u32) -> Duration {
+    if attempt + 1 >= max_attempts {
+        INITIAL_RETRANSMISSION_TIMEOUT * LAST_WAIT_FACTOR
+    } else {
+        INITIAL_RETRANSMISSION_TIMEOUT * 2u32.saturating_pow(attempt)
+    }
+}
+
+/// A random transaction ID, which matches a response to its request.
+pub fn transaction_id() -> [u8; 12] {
+    rand::rng().random()
+}
+
+/// Build a binding request without attributes.
+pub fn format_binding_request(transaction_id: [u8; 12]) -> [u8; HEADER_SIZE] {
+    let mut request = [0u8; HEADER_SIZE];
+    request[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    request[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request[8..20].copy_from_slice(&transaction_id);
+    request
+}
+
+fn u16_at(packet: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([packet[at], packet[at + 1]])
+}
+
+/// Parse a response to the binding request with `transaction_id`, returning the reflexive
+/// address.
+///
+/// `Ok(None)` means the packet is not a response to the request and should be ignored.
+pub fn parse_binding_response(packet: &[u8], transaction_id: [u8; 12]) -> Result<Option<SocketAddr>, StunError> {
+    if packet.len() < HEADER_SIZE
+        || packet[0] & 0xc0 != 0
+        || packet[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || packet[8..20] != transaction_id
+    {
+        return Ok(None);
+    }
+    let message_type = u16_at(packet, 0);
+    let length = usize::from(u16_at(packet, 2));
+    let attributes = packet
+        .get(HEADER_SIZE..HEADER_SIZE + length)
+        .ok_or_else(|| StunError::InvalidResponse(format!("response of {} bytes", packet.len())))?;
+    let mut mapped_address = None;
+    let mut xor_mapped_address = None;
+    let mut error = None;
+    for (kind, value) in Attributes(attributes) {
+        match kind {
+            MAPPED_ADDRESS => mapped_address = Some(value),
+            XOR_MAPPED_ADDRESS => xor_mapped_address = Some(value),
+            ERROR_CODE => error = Some(value),
+            _ => {}
+        }
+    }
+    match message_type {
+        BINDING_SUCCESS_RESPONSE => match (xor_mapped_address, mapped_address) {
+            (Some(value), _) => parse_address(value, Some(&packet[4..20])).map(Some),
+            (None, Some(value)) => parse_address(value, None).map(Some),
+            (None, None) => Err(StunError::InvalidResponse("no mapped address".to_string())),
+        },
+        BINDING_ERROR_RESPONSE => Err(parse_error_code(error.unwrap_or_default())),
+        _ => Ok(None),
+    }
+}
+
+// The attributes of a message, as (type, value). Values are padded to 4 bytes.
+struct Attributes<'a>(&'a [u8]);
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.len() < 4 {
+            return None;
+        }
+        let kind = u16_at(self.0, 0);
+        let length = usize::from(u16_at(self.0, 2));
+        let value = self.0.get(4..4 + length)?;
+        self.0 = self.0.get(4 + length.next_multiple_of(4)..).unwrap_or_default();
+        Some((kind, value))
+    }
+}
+
+// Parse a (XOR-)MAPPED-ADDRESS, XORed with the magic cookie and transaction ID if given.
+fn parse_address(value: &[u8], xor: Option<&[u8]>) -> Result<SocketAddr, StunError> {
+    let invalid = || StunError::InvalidResponse(format!("invalid mapped address of {} bytes", value.len()));
+    let xor_byte = |at: usize| xor.map_or(0, |xor| xor[at]);
+    if value.len() < 4 {
+        return Err(invalid());
+    }
+    let port = u16_at(value, 2) ^ u16::from_be_bytes([xor_byte(0), xor_byte(1)]);
+    let address = &value[4..];
+    let ip = match (value[1], address.len()) {
+        (0x01, 4) => {
+            let octets: [u8; 4] = std::array::from_fn(|i| address[i] ^ xor_byte(i));
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        (0x02, 16) => {
+            let octets: [u8; 16] = std::array::from_fn(|i| address[i] ^ xor_byte(i));
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+fn parse_error_code(value: &[u8]) -> StunError {
+    if value.len() < 4 {
+        return StunError::InvalidResponse("error response without error code".to_string());
+    }
+    StunError::ErrorResponse {
+        code: u16::from(value[2] & 0x07) * 100 + u16::from(value[3]),
+        reason: String::from_utf8_lossy(&value[4..]).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: [u8; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+
+    fn response(message_type: u16, attributes: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (kind, value) in attributes {
+            body.extend_from_slice(&kind.to_be_bytes());
+            body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            body.extend_from_slice(value);
+            body.resize(body.len().next_multiple_of(4), 0);
+        }
+        let mut packet = message_type.to_be_bytes().to_vec();
+        packet.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        packet.extend_from_slice(&ID);
+        packet.extend_from_slice(&body);
+        packet
+    }
+
+    #[test]
+    fn binding_request_layout() {
+        let request = format_binding_request(ID);
+        assert_eq!(request[..8], [0, 1, 0, 0, 0x21, 0x12, 0xa4, 0x42]);
+        assert_eq!(request[8..], ID);
+    }
+
+    #[test]
+    fn parse_responses() {
+        // 203.0.113.7:40000, XORed with the magic cookie
+        let xor_mapped: &[u8] = &[0, 1, 0xbd, 0x52, 0xea, 0x12, 0xd5, 0x45];
+        let packet = response(
+            BINDING_SUCCESS_RESPONSE,
+            &[(0x8022, b"stand-in"), (XOR_MAPPED_ADDRESS, xor_mapped)],
+        );
+        assert_eq!(
+            parse_binding_response(&packet, ID).unwrap(),
+            Some("203.0.113.7:40000".parse().unwrap())
+        );
+
+        let mapped: &[u8] = &[0, 1, 0x9c, 0x40, 198, 51, 100, 1];
+        let packet = response(BINDING_SUCCESS_RESPONSE, &[(MAPPED_ADDRESS, mapped)]);
+        assert_eq!(
+            parse_binding_response(&packet, ID).unwrap(),
+            Some("198.51.100.1:40000".parse().unwrap())
+        );
+
+        let packet = response(BINDING_ERROR_RESPONSE, &[(ERROR_CODE, b"\0\0\x04\x00Bad Request")]);
+        assert!(matches!(
+            parse_binding_response(&packet, ID),
+            Err(StunError::ErrorResponse { code: 400, reason }) if reason == "Bad Request"
+        ));
+
+        assert_eq!(parse_binding_response(&packet, [0; 12]).unwrap(), None);
+        assert!(parse_binding_response(&packet[..24], ID).is_err());
+    }
+
+    #[test]
+    fn requests_are_waited_for_as_in_rfc_5389() {
+        let total: Duration = (0..MAX_ATTEMPTS)
+            .map(|attempt| response_wait(attempt, MAX_ATTEMPTS))
+            .sum();
+        assert_eq!(total, Duration::from_millis(39_500));
+        assert_eq!(response_wait(5, MAX_ATTEMPTS), Duration::from_secs(16));
+        assert_eq!(response_wait(0, 1), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn mismatch_is_likely_double_nat() {
+        let check = |gateway_ip: &str, reflexive_addr: &str| ExternalIpCheck {
+            gateway_ip: gateway_ip.parse().unwrap(),
+            reflexive_addr: reflexive_addr.parse().unwrap(),
+        };
+        assert!(!check("203.0.113.7", "203.0.113.7:40000").is_mismatch());
+        assert!(!check("203.0.113.7", "[::ffff:203.0.113.7]:40000").is_mismatch());
+        assert!(check("192.168.0.2", "203.0.113.7:40000").is_mismatch());
+    }
+}
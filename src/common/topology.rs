@@ -0,0 +1,117 @@
+//! Classification of the external address of a gateway, to tell whether its port mappings
+//! are reachable from the internet.
+
+use std::net::{IpAddr, SocketAddr};
+
+/// The kind of address a gateway reports as its external address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressKind {
+    /// A private address: RFC 1918 for IPv4, unique local `fc00::/7` for IPv6
+    Private,
+    /// The shared address space `100.64.0.0/10` of carrier-grade NATs (RFC 6598)
+    CarrierGradeNat,
+    /// A link-local address
+    LinkLocal,
+    /// A loopback address
+    Loopback,
+    /// The unspecified address, reported by gateways that are not connected
+    Unspecified,
+    /// Any other address
+    Public,
+}
+
+impl AddressKind {
+    /// Classify an address.
+    pub fn of(ip: IpAddr) -> AddressKind {
+        match ip {
+            IpAddr::V4(ip) if ip.is_unspecified() => AddressKind::Unspecified,
+            IpAddr::V4(ip) if ip.is_loopback() => AddressKind::Loopback,
+            IpAddr::V4(ip) if ip.is_link_local() => AddressKind::LinkLocal,
+            IpAddr::V4(ip) if ip.is_private() => AddressKind::Private,
+            IpAddr::V4(ip) if ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64 => AddressKind::CarrierGradeNat,
+            IpAddr::V4(_) => AddressKind::Public,
+            IpAddr::V6(ip) if ip.is_unspecified() => AddressKind::Unspecified,
+            IpAddr::V6(ip) if ip.is_loopback() => AddressKind::Loopback,
+            IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80 => AddressKind::LinkLocal,
+            IpAddr::V6(ip) if ip.segments()[0] & 0xfe00 == 0xfc00 => AddressKind::Private,
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => AddressKind::of(IpAddr::V4(ip)),
+                None => AddressKind::Public,
+            },
+        }
+    }
+
+    /// Whether hosts on the internet can reach the address.
+    pub fn is_public(self) -> bool {
+        self == AddressKind::Public
+    }
+}
+
+/// Where a gateway sits between this host and the internet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatTopology {
+    /// The external address reported by the gateway
+    pub external_ip: IpAddr,
+    /// The kind of the external address
+    pub kind: AddressKind,
+    /// The address of the gateway found on the gateway's external network, which is then a
+    /// router cascaded behind it
+    pub upstream: Option<SocketAddr>,
+}
+
+impl NatTopology {
+    /// Classify the external address of a gateway, with the address of the gateway found in
+    /// front of it, if any.
+    pub fn new(external_ip: IpAddr, upstream: Option<SocketAddr>) -> NatTopology {
+        NatTopology {
+            external_ip,
+            kind: AddressKind::of(external_ip),
+            upstream,
+        }
+    }
+
+    /// Whether port mappings on the gateway are reachable from the internet.
+    pub fn is_reachable(&self) -> bool {
+        self.kind.is_public()
+    }
+
+    /// Whether another gateway was found in front of this one.
+    pub fn is_cascaded(&self) -> bool {
+        self.upstream.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_classified() {
+        let kind = |ip: &str| AddressKind::of(ip.parse().unwrap());
+        assert_eq!(kind("10.1.2.3"), AddressKind::Private);
+        assert_eq!(kind("172.16.0.1"), AddressKind::Private);
+        assert_eq!(kind("192.168.0.2"), AddressKind::Private);
+        assert_eq!(kind("100.64.0.1"), AddressKind::CarrierGradeNat);
+        assert_eq!(kind("100.127.255.254"), AddressKind::CarrierGradeNat);
+        assert_eq!(kind("100.128.0.1"), AddressKind::Public);
+        assert_eq!(kind("169.254.3.4"), AddressKind::LinkLocal);
+        assert_eq!(kind("127.0.0.1"), AddressKind::Loopback);
+        assert_eq!(kind("0.0.0.0"), AddressKind::Unspecified);
+        assert_eq!(kind("203.0.113.7"), AddressKind::Public);
+        assert_eq!(kind("fd00::1"), AddressKind::Private);
+        assert_eq!(kind("fe80::1"), AddressKind::LinkLocal);
+        assert_eq!(kind("::ffff:192.168.1.1"), AddressKind::Private);
+        assert_eq!(kind("2001:db8::1"), AddressKind::Public);
+    }
+
+    #[test]
+    fn cascaded_gateways_are_not_reachable() {
+        let upstream = "192.168.0.1:49152".parse().unwrap();
+        let topology = NatTopology::new("192.168.0.2".parse().unwrap(), Some(upstream));
+        assert_eq!(topology.kind, AddressKind::Private);
+        assert!(topology.is_cascaded() && !topology.is_reachable());
+
+        let topology = NatTopology::new("203.0.113.7".parse().unwrap(), None);
+        assert!(topology.is_reachable() && !topology.is_cascaded());
+    }
+}
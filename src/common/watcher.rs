@@ -0,0 +1,163 @@
+//! State shared by the sync and async external IP address watchers.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use log::debug;
+
+use super::gena::{EventNotification, PropertyChange};
+use super::parsing::{self, RequestResult};
+use crate::errors::GetExternalIpError;
+
+/// A change of the external IP address, as `(old, new)`. `None` means the WAN connection was
+/// down, i.e. the gateway reported an empty or unspecified (`0.0.0.0`) address.
+pub type ExternalIpChange = (Option<IpAddr>, Option<IpAddr>);
+
+/// The address, or `None` if it is the unspecified address a disconnected gateway reports.
+pub fn connected_address(ip: Option<IpAddr>) -> Option<IpAddr> {
+    ip.filter(|ip| !ip.is_unspecified())
+}
+
+/// Parse a `GetExternalIPAddress` response, treating an empty or unspecified address as
+/// disconnected rather than as an invalid response.
+pub fn parse_external_ip_state(result: RequestResult) -> Result<Option<IpAddr>, GetExternalIpError> {
+    if let Ok(resp) = &result {
+        if resp.argument("NewExternalIPAddress").is_ok_and(|ip| ip.is_empty()) {
+            return Ok(None);
+        }
+    }
+    parsing::parse_get_external_ip_response(result).map(|ip| connected_address(Some(ip)))
+}
+
+/// The external address reported by an event of the subscription `sid`, if it reports one.
+///
+/// Any host can send a notification to the callback address, so events of another
+/// subscription are ignored.
+pub fn event_address(notification: &EventNotification, sid: &str) -> Option<Option<IpAddr>> {
+    if notification.sid != sid {
+        debug!("ignoring an event of unknown subscription {}", notification.sid);
+        return None;
+    }
+    notification.changes.iter().rev().find_map(|change| match change {
+        PropertyChange::ExternalIpAddress(ip) => Some(connected_address(*ip)),
+        _ => None,
+    })
+}
+
+/// Tracks the last known address and turns observations into changes.
+pub struct AddressTracker {
+    current: Option<IpAddr>,
+}
+
+impl AddressTracker {
+    pub fn new(current: Option<IpAddr>) -> AddressTracker {
+        AddressTracker { current }
+    }
+
+    /// Record an observed address, returning the change if it differs from the last one.
+    pub fn update(&mut self, observed: Option<IpAddr>) -> Option<ExternalIpChange> {
+        if observed == self.current {
+            return None;
+        }
+        let old = std::mem::replace(&mut self.current, observed);
+        Some((old, observed))
+    }
+}
+
+/// Polling interval that doubles while the address stays the same, from `min` up to `max`,
+/// and goes back to `min` when it changes.
+pub struct PollInterval {
+    current: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl PollInterval {
+    pub fn new(min: Duration, max: Duration) -> PollInterval {
+        let max = max.max(min);
+        PollInterval { current: min, min, max }
+    }
+
+    /// How long to wait before the next poll.
+    pub fn get(&self) -> Duration {
+        self.current
+    }
+
+    /// Adapt the interval after a poll, depending on whether the address changed.
+    pub fn observed(&mut self, changed: bool) {
+        self.current = if changed {
+            self.min
+        } else {
+            self.current.saturating_mul(2).min(self.max)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::parsing::parse_response;
+
+    fn response(ip: &str) -> RequestResult {
+        let text = format!(
+            r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+<NewExternalIPAddress>{ip}</NewExternalIPAddress>
+</u:GetExternalIPAddressResponse>
+</s:Body>
+</s:Envelope>"#
+        );
+        parse_response(text, "GetExternalIPAddressResponse")
+    }
+
+    #[test]
+    fn empty_and_unspecified_addresses_are_disconnected() {
+        assert_eq!(
+            parse_external_ip_state(response("203.0.113.7")).unwrap(),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(parse_external_ip_state(response("")).unwrap(), None);
+        assert_eq!(parse_external_ip_state(response("0.0.0.0")).unwrap(), None);
+        assert!(parse_external_ip_state(response("garbage")).is_err());
+    }
+
+    #[test]
+    fn events_of_other_subscriptions_are_ignored() {
+        let notification = EventNotification {
+            sid: "uuid:subscription".to_string(),
+            seq: 1,
+            changes: vec![PropertyChange::ExternalIpAddress(Some("203.0.113.7".parse().unwrap()))],
+        };
+        assert_eq!(
+            event_address(&notification, "uuid:subscription"),
+            Some(Some("203.0.113.7".parse().unwrap()))
+        );
+        assert_eq!(event_address(&notification, "uuid:other"), None);
+    }
+
+    #[test]
+    fn tracker_reports_changes_only() {
+        let a: IpAddr = "203.0.113.7".parse().unwrap();
+        let b: IpAddr = "198.51.100.1".parse().unwrap();
+        let mut tracker = AddressTracker::new(Some(a));
+        assert_eq!(tracker.update(Some(a)), None);
+        assert_eq!(tracker.update(None), Some((Some(a), None)));
+        assert_eq!(tracker.update(None), None);
+        assert_eq!(tracker.update(Some(b)), Some((None, Some(b))));
+    }
+
+    #[test]
+    fn poll_interval_backs_off_until_a_change() {
+        let mut interval = PollInterval::new(Duration::from_secs(10), Duration::from_secs(35));
+        assert_eq!(interval.get(), Duration::from_secs(10));
+        interval.observed(false);
+        assert_eq!(interval.get(), Duration::from_secs(20));
+        interval.observed(false);
+        interval.observed(false);
+        assert_eq!(interval.get(), Duration::from_secs(35));
+        interval.observed(true);
+        assert_eq!(interval.get(), Duration::from_secs(10));
+    }
+}
@@ -1,11 +1,16 @@
+use std::fmt;
 use std::io;
 use std::str;
 #[cfg(feature = "aio_tokio")]
 use std::string::FromUtf8Error;
+use std::time::Duration;
 
 #[cfg(feature = "aio_tokio")]
 use tokio::time::error::Elapsed;
 
+use crate::common::parsing::PortMappingEntry;
+use crate::common::port_mapper::MappingMechanism;
+
 /// Errors that can occur when sending the request to the gateway.
 #[derive(thiserror::Error, Debug)]
 pub enum RequestError {
@@ -21,7 +26,7 @@ pub enum RequestError {
     InvalidResponse(String),
     /// The gateway returned an unhandled error code and description.
     #[error("Gateway response error {}: {}", _0, _1)]
-    ErrorCode(u16, String),
+    ErrorCode(UpnpErrorCode, String),
     #[error("Action is not supported by the gateway: {}", _0)]
     /// Action is not supported by the gateway
     UnsupportedAction(String),
@@ -45,6 +50,169 @@ pub enum RequestError {
     Utf8Error(#[from] FromUtf8Error),
 }
 
+impl RequestError {
+    /// The UPnP error code returned by the gateway, if any.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            RequestError::ErrorCode(code, _) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// Error codes of the UPnP device architecture and of the WANIPConnection v1 and v2 services.
+///
+/// The `WANIPv6FirewallControl` service reuses codes 701 to 709 with other meanings, so they
+/// are `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum UpnpErrorCode {
+    /// 401: the action is not defined by the service
+    InvalidAction,
+    /// 402: missing, extra or invalid arguments
+    InvalidArgs,
+    /// 501: the action failed on the device
+    ActionFailed,
+    /// 600: an argument value is invalid
+    ArgumentValueInvalid,
+    /// 601: an argument value is out of range
+    ArgumentValueOutOfRange,
+    /// 602: the optional action is not implemented
+    OptionalActionNotImplemented,
+    /// 603: the device ran out of memory
+    OutOfMemory,
+    /// 604: the device requires human intervention
+    HumanInterventionRequired,
+    /// 605: a string argument is too long
+    StringArgumentTooLong,
+    /// 606: the client is not authorized to perform the action
+    ActionNotAuthorized,
+    /// 713: the array index is out of bounds
+    SpecifiedArrayIndexInvalid,
+    /// 714: no such entry in the array
+    NoSuchEntryInArray,
+    /// 715: the remote host can not be a wildcard
+    WildCardNotPermittedInSrcIp,
+    /// 716: the external port can not be a wildcard
+    WildCardNotPermittedInExtPort,
+    /// 718: the mapping conflicts with a mapping assigned to another client
+    ConflictInMappingEntry,
+    /// 719: the action is not allowed while the connection is configured automatically
+    ActionDisallowedWhenAutoConfigEnabled,
+    /// 720: the device UUID is invalid
+    InvalidDeviceUuid,
+    /// 721: the service ID is invalid
+    InvalidServiceId,
+    /// 723: the connection service can not be the default one
+    InvalidConnServiceSelection,
+    /// 724: the internal and external ports must be the same
+    SamePortValuesRequired,
+    /// 725: only permanent leases are supported
+    OnlyPermanentLeasesSupported,
+    /// 726: the remote host must be a wildcard
+    RemoteHostOnlySupportsWildcard,
+    /// 727: the external port must be a wildcard
+    ExternalPortOnlySupportsWildcard,
+    /// 728: no external port is available
+    NoPortMapsAvailable,
+    /// 729: the mapping conflicts with one made by another mechanism, such as NAT-PMP
+    ConflictWithOtherMechanisms,
+    /// 730: no port mapping was found in the given range
+    PortMappingNotFound,
+    /// 731: the port mapping is read-only
+    ReadOnly,
+    /// 732: the internal port can not be a wildcard
+    WildCardNotPermittedInIntPort,
+    /// 733: the arguments are inconsistent with each other
+    InconsistentParameters,
+    /// Any other code, such as a vendor specific one
+    Other(u16),
+}
+
+impl UpnpErrorCode {
+    /// The numeric error code.
+    pub fn code(self) -> u16 {
+        match self {
+            UpnpErrorCode::InvalidAction => 401,
+            UpnpErrorCode::InvalidArgs => 402,
+            UpnpErrorCode::ActionFailed => 501,
+            UpnpErrorCode::ArgumentValueInvalid => 600,
+            UpnpErrorCode::ArgumentValueOutOfRange => 601,
+            UpnpErrorCode::OptionalActionNotImplemented => 602,
+            UpnpErrorCode::OutOfMemory => 603,
+            UpnpErrorCode::HumanInterventionRequired => 604,
+            UpnpErrorCode::StringArgumentTooLong => 605,
+            UpnpErrorCode::ActionNotAuthorized => 606,
+            UpnpErrorCode::SpecifiedArrayIndexInvalid => 713,
+            UpnpErrorCode::NoSuchEntryInArray => 714,
+            UpnpErrorCode::WildCardNotPermittedInSrcIp => 715,
+            UpnpErrorCode::WildCardNotPermittedInExtPort => 716,
+            UpnpErrorCode::ConflictInMappingEntry => 718,
+            UpnpErrorCode::ActionDisallowedWhenAutoConfigEnabled => 719,
+            UpnpErrorCode::InvalidDeviceUuid => 720,
+            UpnpErrorCode::InvalidServiceId => 721,
+            UpnpErrorCode::InvalidConnServiceSelection => 723,
+            UpnpErrorCode::SamePortValuesRequired => 724,
+            UpnpErrorCode::OnlyPermanentLeasesSupported => 725,
+            UpnpErrorCode::RemoteHostOnlySupportsWildcard => 726,
+            UpnpErrorCode::ExternalPortOnlySupportsWildcard => 727,
+            UpnpErrorCode::NoPortMapsAvailable => 728,
+            UpnpErrorCode::ConflictWithOtherMechanisms => 729,
+            UpnpErrorCode::PortMappingNotFound => 730,
+            UpnpErrorCode::ReadOnly => 731,
+            UpnpErrorCode::WildCardNotPermittedInIntPort => 732,
+            UpnpErrorCode::InconsistentParameters => 733,
+            UpnpErrorCode::Other(code) => code,
+        }
+    }
+}
+
+impl From<u16> for UpnpErrorCode {
+    fn from(code: u16) -> UpnpErrorCode {
+        match code {
+            401 => UpnpErrorCode::InvalidAction,
+            402 => UpnpErrorCode::InvalidArgs,
+            501 => UpnpErrorCode::ActionFailed,
+            600 => UpnpErrorCode::ArgumentValueInvalid,
+            601 => UpnpErrorCode::ArgumentValueOutOfRange,
+            602 => UpnpErrorCode::OptionalActionNotImplemented,
+            603 => UpnpErrorCode::OutOfMemory,
+            604 => UpnpErrorCode::HumanInterventionRequired,
+            605 => UpnpErrorCode::StringArgumentTooLong,
+            606 => UpnpErrorCode::ActionNotAuthorized,
+            713 => UpnpErrorCode::SpecifiedArrayIndexInvalid,
+            714 => UpnpErrorCode::NoSuchEntryInArray,
+            715 => UpnpErrorCode::WildCardNotPermittedInSrcIp,
+            716 => UpnpErrorCode::WildCardNotPermittedInExtPort,
+            718 => UpnpErrorCode::ConflictInMappingEntry,
+            719 => UpnpErrorCode::ActionDisallowedWhenAutoConfigEnabled,
+            720 => UpnpErrorCode::InvalidDeviceUuid,
+            721 => UpnpErrorCode::InvalidServiceId,
+            723 => UpnpErrorCode::InvalidConnServiceSelection,
+            724 => UpnpErrorCode::SamePortValuesRequired,
+            725 => UpnpErrorCode::OnlyPermanentLeasesSupported,
+            726 => UpnpErrorCode::RemoteHostOnlySupportsWildcard,
+            727 => UpnpErrorCode::ExternalPortOnlySupportsWildcard,
+            728 => UpnpErrorCode::NoPortMapsAvailable,
+            729 => UpnpErrorCode::ConflictWithOtherMechanisms,
+            730 => UpnpErrorCode::PortMappingNotFound,
+            731 => UpnpErrorCode::ReadOnly,
+            732 => UpnpErrorCode::WildCardNotPermittedInIntPort,
+            733 => UpnpErrorCode::InconsistentParameters,
+            code => UpnpErrorCode::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for UpnpErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpnpErrorCode::Other(code) => write!(f, "{code}"),
+            known => write!(f, "{} ({known:?})", known.code()),
+        }
+    }
+}
+
 #[cfg(feature = "aio_tokio")]
 impl From<Elapsed> for RequestError {
     fn from(_err: Elapsed) -> RequestError {
@@ -63,6 +231,16 @@ pub enum GetExternalIpError {
     RequestError(#[source] RequestError),
 }
 
+impl GetExternalIpError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            GetExternalIpError::ActionNotAuthorized => Some(UpnpErrorCode::ActionNotAuthorized),
+            GetExternalIpError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
 impl From<io::Error> for GetExternalIpError {
     fn from(err: io::Error) -> GetExternalIpError {
         GetExternalIpError::RequestError(RequestError::from(err))
@@ -83,6 +261,17 @@ pub enum RemovePortError {
     RequestError(#[source] RequestError),
 }
 
+impl RemovePortError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            RemovePortError::ActionNotAuthorized => Some(UpnpErrorCode::ActionNotAuthorized),
+            RemovePortError::NoSuchPortMapping => Some(UpnpErrorCode::NoSuchEntryInArray),
+            RemovePortError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
 /// Errors returned by `Gateway::add_any_port` and `Gateway::get_any_address`
 #[derive(thiserror::Error, Debug)]
 pub enum AddAnyPortError {
@@ -101,17 +290,40 @@ pub enum AddAnyPortError {
         "The gateway can only map internal ports to same-numbered external ports and this external port is in use."
     )]
     ExternalPortInUse,
+    /// The mapping conflicts with one made by another mechanism, such as NAT-PMP or PCP.
+    #[error("The mapping conflicts with one made by another mechanism, such as NAT-PMP or PCP.")]
+    ConflictWithOtherMechanisms,
     /// The gateway only supports permanent leases (ie. a `lease_duration` of 0).
     #[error("The gateway only supports permanent leases (ie. a `lease_duration` of 0).")]
     OnlyPermanentLeasesSupported,
     /// The description was too long for the gateway to handle.
     #[error("The description was too long for the gateway to handle.")]
     DescriptionTooLong,
+    /// The lease duration can not be requested from the gateway.
+    #[error("The lease duration {0:?} can not be requested from the gateway.")]
+    InvalidLeaseDuration(Duration),
     /// Some other error occured performing the request.
     #[error("Request error. {0}")]
     RequestError(#[from] RequestError),
 }
 
+impl AddAnyPortError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            AddAnyPortError::ActionNotAuthorized => Some(UpnpErrorCode::ActionNotAuthorized),
+            AddAnyPortError::InternalPortZeroInvalid => None,
+            AddAnyPortError::NoPortsAvailable => Some(UpnpErrorCode::NoPortMapsAvailable),
+            AddAnyPortError::ExternalPortInUse => Some(UpnpErrorCode::ConflictInMappingEntry),
+            AddAnyPortError::ConflictWithOtherMechanisms => Some(UpnpErrorCode::ConflictWithOtherMechanisms),
+            AddAnyPortError::OnlyPermanentLeasesSupported => Some(UpnpErrorCode::OnlyPermanentLeasesSupported),
+            AddAnyPortError::DescriptionTooLong => Some(UpnpErrorCode::StringArgumentTooLong),
+            AddAnyPortError::InvalidLeaseDuration(_) => None,
+            AddAnyPortError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
 impl From<GetExternalIpError> for AddAnyPortError {
     fn from(err: GetExternalIpError) -> AddAnyPortError {
         match err {
@@ -136,6 +348,9 @@ pub enum AddPortError {
     /// The requested mapping conflicts with a mapping assigned to another client.
     #[error("The requested mapping conflicts with a mapping assigned to another client.")]
     PortInUse,
+    /// The mapping conflicts with one made by another mechanism, such as NAT-PMP or PCP.
+    #[error("The mapping conflicts with one made by another mechanism, such as NAT-PMP or PCP.")]
+    ConflictWithOtherMechanisms,
     /// The gateway requires that the requested internal and external ports are the same.
     #[error("The gateway requires that the requested internal and external ports are the same.")]
     SamePortValuesRequired,
@@ -145,11 +360,103 @@ pub enum AddPortError {
     /// The description was too long for the gateway to handle.
     #[error("The description was too long for the gateway to handle.")]
     DescriptionTooLong,
+    /// The lease duration can not be requested from the gateway.
+    #[error("The lease duration {0:?} can not be requested from the gateway.")]
+    InvalidLeaseDuration(Duration),
+    /// The external port is mapped to another client, as described by the entry.
+    #[error(
+        "External port {} is mapped to {}:{} ({}).",
+        .0.external_port,
+        .0.internal_client,
+        .0.internal_port,
+        .0.port_mapping_description
+    )]
+    PortInUseBy(PortMappingEntry),
     /// Some other error occured performing the request.
     #[error("Request error. {0}")]
     RequestError(#[source] RequestError),
 }
 
+impl AddPortError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            AddPortError::ActionNotAuthorized => Some(UpnpErrorCode::ActionNotAuthorized),
+            AddPortError::InternalPortZeroInvalid
+            | AddPortError::ExternalPortZeroInvalid
+            | AddPortError::InvalidLeaseDuration(_) => None,
+            AddPortError::PortInUse | AddPortError::PortInUseBy(_) => Some(UpnpErrorCode::ConflictInMappingEntry),
+            AddPortError::ConflictWithOtherMechanisms => Some(UpnpErrorCode::ConflictWithOtherMechanisms),
+            AddPortError::SamePortValuesRequired => Some(UpnpErrorCode::SamePortValuesRequired),
+            AddPortError::OnlyPermanentLeasesSupported => Some(UpnpErrorCode::OnlyPermanentLeasesSupported),
+            AddPortError::DescriptionTooLong => Some(UpnpErrorCode::StringArgumentTooLong),
+            AddPortError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
+/// Errors returned by `Gateway::add_port_range`
+#[derive(thiserror::Error, Debug)]
+pub enum AddPortRangeError {
+    /// The range is empty or goes past port 65535.
+    #[error("The port range is empty or goes past port 65535.")]
+    InvalidRange,
+    /// An external port of the range conflicts with a mapping assigned to another client.
+    #[error("External port {0} conflicts with a mapping assigned to another client.")]
+    PortInUse(u16),
+    /// Mapping an external port of the range failed.
+    #[error("Could not map external port {port}: {error}")]
+    AddPortError {
+        /// The external port that could not be mapped
+        port: u16,
+        /// The error returned for it
+        #[source]
+        error: AddPortError,
+    },
+}
+
+impl AddPortRangeError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            AddPortRangeError::InvalidRange => None,
+            AddPortRangeError::PortInUse(_) => Some(UpnpErrorCode::ConflictInMappingEntry),
+            AddPortRangeError::AddPortError { error, .. } => error.upnp_error_code(),
+        }
+    }
+}
+
+/// Errors returned by `Gateway::discover_upstream` and `Gateway::add_cascaded_port`
+#[derive(thiserror::Error, Debug)]
+pub enum CascadeError {
+    /// The external address of the gateway is public, no router is in front of it.
+    #[error("The gateway is not behind another router")]
+    NotCascaded,
+    /// No gateway answered on the external network of the gateway.
+    #[error("No gateway found in front of the gateway")]
+    NoUpstreamGateway,
+    /// Getting the external address of a gateway failed.
+    #[error("{0}")]
+    GetExternalIpError(#[from] GetExternalIpError),
+    /// Mapping the port on the inner gateway failed.
+    #[error("Could not map the port on the inner gateway: {0}")]
+    InnerMapping(#[source] AddPortError),
+    /// Mapping the port on the outer gateway failed. The inner mapping was removed.
+    #[error("Could not map the port on the outer gateway: {0}")]
+    OuterMapping(#[source] AddPortError),
+}
+
+impl CascadeError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            CascadeError::NotCascaded | CascadeError::NoUpstreamGateway => None,
+            CascadeError::GetExternalIpError(e) => e.upnp_error_code(),
+            CascadeError::InnerMapping(e) | CascadeError::OuterMapping(e) => e.upnp_error_code(),
+        }
+    }
+}
+
 /// Errors than can occur while trying to find the gateway.
 #[derive(thiserror::Error, Debug)]
 pub enum SearchError {
@@ -207,16 +514,353 @@ pub enum GetGenericPortMappingEntryError {
     RequestError(#[source] RequestError),
 }
 
+impl GetGenericPortMappingEntryError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            GetGenericPortMappingEntryError::ActionNotAuthorized => Some(UpnpErrorCode::ActionNotAuthorized),
+            GetGenericPortMappingEntryError::SpecifiedArrayIndexInvalid => {
+                Some(UpnpErrorCode::SpecifiedArrayIndexInvalid)
+            }
+            GetGenericPortMappingEntryError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
 impl From<RequestError> for GetGenericPortMappingEntryError {
     fn from(err: RequestError) -> GetGenericPortMappingEntryError {
         match err {
-            RequestError::ErrorCode(606, _) => GetGenericPortMappingEntryError::ActionNotAuthorized,
-            RequestError::ErrorCode(713, _) => GetGenericPortMappingEntryError::SpecifiedArrayIndexInvalid,
+            RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => {
+                GetGenericPortMappingEntryError::ActionNotAuthorized
+            }
+            RequestError::ErrorCode(UpnpErrorCode::SpecifiedArrayIndexInvalid, _) => {
+                GetGenericPortMappingEntryError::SpecifiedArrayIndexInvalid
+            }
             other => GetGenericPortMappingEntryError::RequestError(other),
         }
     }
 }
 
+/// Errors than can occur while getting the port mapping of an external port
+#[derive(thiserror::Error, Debug)]
+pub enum GetSpecificPortMappingEntryError {
+    /// The client is not authorized to perform the operation.
+    #[error("The client is not authorized to look up port mappings.")]
+    ActionNotAuthorized,
+    /// The external port is not mapped.
+    #[error("The port was not mapped")]
+    NoSuchEntryInArray,
+    /// Some other error occured performing the request.
+    #[error("{0}")]
+    RequestError(#[source] RequestError),
+}
+
+impl GetSpecificPortMappingEntryError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            GetSpecificPortMappingEntryError::ActionNotAuthorized => Some(UpnpErrorCode::ActionNotAuthorized),
+            GetSpecificPortMappingEntryError::NoSuchEntryInArray => Some(UpnpErrorCode::NoSuchEntryInArray),
+            GetSpecificPortMappingEntryError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
+impl From<RequestError> for GetSpecificPortMappingEntryError {
+    fn from(err: RequestError) -> GetSpecificPortMappingEntryError {
+        match err {
+            RequestError::ErrorCode(UpnpErrorCode::ActionNotAuthorized, _) => {
+                GetSpecificPortMappingEntryError::ActionNotAuthorized
+            }
+            RequestError::ErrorCode(UpnpErrorCode::NoSuchEntryInArray, _) => {
+                GetSpecificPortMappingEntryError::NoSuchEntryInArray
+            }
+            other => GetSpecificPortMappingEntryError::RequestError(other),
+        }
+    }
+}
+
+/// Errors returned by `Gateway::get_default_connection_service` and
+/// `Gateway::set_default_connection_service`
+#[derive(thiserror::Error, Debug)]
+pub enum DefaultConnectionServiceError {
+    /// The client is not authorized to perform the operation.
+    #[error("The client is not authorized to access the default connection service.")]
+    ActionNotAuthorized,
+    /// The device UUID of the connection service is invalid.
+    #[error("The device UUID of the connection service is invalid.")]
+    InvalidDeviceUuid,
+    /// The service id of the connection service is invalid.
+    #[error("The service id of the connection service is invalid.")]
+    InvalidServiceId,
+    /// The selected connection service is not a valid default connection service.
+    #[error("The selected connection service is not a valid default connection service.")]
+    InvalidConnServiceSelection,
+    /// Some other error occured performing the request.
+    #[error("Request error. {0}")]
+    RequestError(#[from] RequestError),
+}
+
+impl DefaultConnectionServiceError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            DefaultConnectionServiceError::ActionNotAuthorized => Some(UpnpErrorCode::ActionNotAuthorized),
+            DefaultConnectionServiceError::InvalidDeviceUuid => Some(UpnpErrorCode::InvalidDeviceUuid),
+            DefaultConnectionServiceError::InvalidServiceId => Some(UpnpErrorCode::InvalidServiceId),
+            DefaultConnectionServiceError::InvalidConnServiceSelection => {
+                Some(UpnpErrorCode::InvalidConnServiceSelection)
+            }
+            DefaultConnectionServiceError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
+/// Errors returned by the `WANIPv6FirewallControl` pinhole actions of `Gateway`
+#[derive(thiserror::Error, Debug)]
+pub enum PinholeError {
+    /// The client is not authorized to perform the operation.
+    #[error("The client is not authorized to control the IPv6 firewall.")]
+    ActionNotAuthorized,
+    /// The gateway cannot create any more pinholes.
+    #[error("The gateway cannot create any more pinholes.")]
+    PinholeSpaceExhausted,
+    /// The IPv6 firewall is disabled.
+    #[error("The IPv6 firewall is disabled.")]
+    FirewallDisabled,
+    /// The gateway does not allow creating inbound pinholes.
+    #[error("The gateway does not allow creating inbound pinholes.")]
+    InboundPinholeNotAllowed,
+    /// No pinhole exists with the given id.
+    #[error("No pinhole exists with the given id.")]
+    NoSuchEntry,
+    /// The protocol is not supported by the gateway.
+    #[error("The protocol is not supported by the gateway.")]
+    ProtocolNotSupported,
+    /// The internal port can not be a wildcard.
+    #[error("The internal port can not be a wildcard.")]
+    InternalPortWildcardingNotAllowed,
+    /// The protocol can not be a wildcard.
+    #[error("The protocol can not be a wildcard.")]
+    ProtocolWildcardingNotAllowed,
+    /// The remote host can not be a wildcard.
+    #[error("The remote host can not be a wildcard.")]
+    WildCardNotPermittedInSrcIp,
+    /// No traffic was received through the pinhole.
+    #[error("No traffic was received through the pinhole.")]
+    NoTrafficReceived,
+    /// Some other error occured performing the request.
+    #[error("Request error. {0}")]
+    RequestError(#[from] RequestError),
+}
+
+impl PinholeError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            PinholeError::ActionNotAuthorized => Some(UpnpErrorCode::ActionNotAuthorized),
+            PinholeError::PinholeSpaceExhausted => Some(UpnpErrorCode::Other(701)),
+            PinholeError::FirewallDisabled => Some(UpnpErrorCode::Other(702)),
+            PinholeError::InboundPinholeNotAllowed => Some(UpnpErrorCode::Other(703)),
+            PinholeError::NoSuchEntry => Some(UpnpErrorCode::Other(704)),
+            PinholeError::ProtocolNotSupported => Some(UpnpErrorCode::Other(705)),
+            PinholeError::InternalPortWildcardingNotAllowed => Some(UpnpErrorCode::Other(706)),
+            PinholeError::ProtocolWildcardingNotAllowed => Some(UpnpErrorCode::Other(707)),
+            PinholeError::WildCardNotPermittedInSrcIp => Some(UpnpErrorCode::Other(708)),
+            PinholeError::NoTrafficReceived => Some(UpnpErrorCode::Other(709)),
+            PinholeError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
+/// Errors returned when subscribing to the events of a service
+#[derive(thiserror::Error, Debug)]
+pub enum SubscriptionError {
+    /// The gateway rejected the request, or does not know the subscription being renewed.
+    #[error("The gateway rejected the subscription request.")]
+    PreconditionFailed,
+    /// The service does not publish events.
+    #[error("The service does not publish events.")]
+    EventsNotSupported,
+    /// The gateway replied with an unexpected HTTP status.
+    #[error("Unexpected HTTP status {0}")]
+    UnexpectedStatus(u16),
+    /// Some other error occured performing the request.
+    #[error("Request error. {0}")]
+    RequestError(#[from] RequestError),
+}
+
+impl SubscriptionError {
+    /// The UPnP error code returned by the gateway, if the error comes from one.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            SubscriptionError::PreconditionFailed
+            | SubscriptionError::EventsNotSupported
+            | SubscriptionError::UnexpectedStatus(_) => None,
+            SubscriptionError::RequestError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
+impl From<io::Error> for SubscriptionError {
+    fn from(err: io::Error) -> SubscriptionError {
+        SubscriptionError::RequestError(RequestError::from(err))
+    }
+}
+
+/// Errors returned by the NAT-PMP client
+#[derive(thiserror::Error, Debug)]
+pub enum NatPmpError {
+    /// The gateway does not support version 0 of the protocol (result code 1).
+    #[error("The gateway does not support this NAT-PMP version")]
+    UnsupportedVersion,
+    /// The gateway supports mapping but the feature is disabled (result code 2).
+    #[error("The client is not authorized to map ports")]
+    NotAuthorized,
+    /// The gateway has no external address, e.g. its WAN connection is down (result code 3).
+    #[error("The gateway has a network failure")]
+    NetworkFailure,
+    /// The gateway cannot create more mappings (result code 4).
+    #[error("The gateway is out of resources")]
+    OutOfResources,
+    /// The gateway does not support the request (result code 5).
+    #[error("The gateway does not support the request")]
+    UnsupportedOpcode,
+    /// The gateway returned a result code not defined by RFC 6886.
+    #[error("Unknown NAT-PMP result code {0}")]
+    UnknownResultCode(u16),
+    /// The gateway did not respond after all retransmissions.
+    #[error("No response from the gateway")]
+    NoResponse,
+    /// The response from the gateway could not be parsed.
+    #[error("Invalid response from gateway: {0}")]
+    InvalidResponse(String),
+    /// IO Error
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// Errors returned by the STUN client
+#[derive(thiserror::Error, Debug)]
+pub enum StunError {
+    /// The server answered with an error response.
+    #[error("STUN error {code}: {reason}")]
+    ErrorResponse {
+        /// The error code, such as 400 for a bad request
+        code: u16,
+        /// The reason phrase of the server
+        reason: String,
+    },
+    /// The server did not respond after all retransmissions.
+    #[error("No response from the STUN server")]
+    NoResponse,
+    /// The response from the server could not be parsed.
+    #[error("Invalid response from STUN server: {0}")]
+    InvalidResponse(String),
+    /// IO Error
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// Errors returned by the PCP client
+#[derive(thiserror::Error, Debug)]
+pub enum PcpError {
+    /// The server does not support version 2 of the protocol (result code 1).
+    #[error("The server does not support this PCP version")]
+    UnsupportedVersion,
+    /// The client is not authorized to perform the request (result code 2).
+    #[error("The client is not authorized to perform the request")]
+    NotAuthorized,
+    /// The server could not parse the request (result code 3).
+    #[error("The server could not parse the request")]
+    MalformedRequest,
+    /// The server does not support the opcode (result code 4).
+    #[error("The server does not support the opcode")]
+    UnsupportedOpcode,
+    /// The server does not support a mandatory option (result code 5).
+    #[error("The server does not support a mandatory option")]
+    UnsupportedOption,
+    /// The server could not parse an option (result code 6).
+    #[error("The server could not parse an option")]
+    MalformedOption,
+    /// The server has a network failure (result code 7).
+    #[error("The server has a network failure")]
+    NetworkFailure,
+    /// The server is out of resources (result code 8).
+    #[error("The server is out of resources")]
+    NoResources,
+    /// The server does not support the protocol (result code 9).
+    #[error("The server does not support the protocol")]
+    UnsupportedProtocol,
+    /// The client exceeded its port quota (result code 10).
+    #[error("The client exceeded its port quota")]
+    UserExceededQuota,
+    /// The suggested external address or port cannot be provided, and `PREFER_FAILURE` was
+    /// requested (result code 11).
+    #[error("The server cannot provide the suggested external address or port")]
+    CannotProvideExternal,
+    /// The client address in the request does not match the source of the packet, e.g.
+    /// because of another NAT in between (result code 12).
+    #[error("The client address does not match the source address of the request")]
+    AddressMismatch,
+    /// The server cannot create filters for that many remote peers (result code 13).
+    #[error("Too many remote peers")]
+    ExcessiveRemotePeers,
+    /// The server returned a result code not defined by RFC 6887.
+    #[error("Unknown PCP result code {0}")]
+    UnknownResultCode(u8),
+    /// The server did not respond after all retransmissions.
+    #[error("No response from the server")]
+    NoResponse,
+    /// The response from the server could not be parsed.
+    #[error("Invalid response from server: {0}")]
+    InvalidResponse(String),
+    /// IO Error
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// Errors returned by the port mapper
+#[derive(thiserror::Error, Debug)]
+pub enum PortMapperError {
+    /// None of the mechanisms responded.
+    #[error("No port mapping mechanism is available")]
+    NoMechanismAvailable,
+    /// The mapping was made with another mechanism than the port mapper uses.
+    #[error("The mapping was made with {0}")]
+    WrongMechanism(MappingMechanism),
+    /// Error mapping a port with UPnP.
+    #[error("UPnP error: {0}")]
+    AddAnyPortError(#[from] AddAnyPortError),
+    /// Error removing a port mapping with UPnP.
+    #[error("UPnP error: {0}")]
+    RemovePortError(#[from] RemovePortError),
+    /// Error getting the external address with UPnP.
+    #[error("UPnP error: {0}")]
+    GetExternalIpError(#[from] GetExternalIpError),
+    /// PCP error
+    #[error("PCP error: {0}")]
+    PcpError(#[from] PcpError),
+    /// NAT-PMP error
+    #[error("NAT-PMP error: {0}")]
+    NatPmpError(#[from] NatPmpError),
+}
+
+impl PortMapperError {
+    /// The UPnP error code returned by the gateway, if the error comes from a UPnP request.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            PortMapperError::NoMechanismAvailable
+            | PortMapperError::WrongMechanism(_)
+            | PortMapperError::PcpError(_)
+            | PortMapperError::NatPmpError(_) => None,
+            PortMapperError::AddAnyPortError(e) => e.upnp_error_code(),
+            PortMapperError::RemovePortError(e) => e.upnp_error_code(),
+            PortMapperError::GetExternalIpError(e) => e.upnp_error_code(),
+        }
+    }
+}
+
 /// An error type that emcompasses all possible errors.
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -226,12 +870,42 @@ pub enum Error {
     /// `AddPortError`
     #[error("{0}")]
     AddPortError(#[from] AddPortError),
+    /// `AddPortRangeError`
+    #[error("{0}")]
+    AddPortRangeError(#[from] AddPortRangeError),
+    /// `CascadeError`
+    #[error("{0}")]
+    CascadeError(#[from] CascadeError),
     /// `GetExternalIpError`
     #[error("{0}")]
     GetExternalIpError(#[from] GetExternalIpError),
     /// `GetGenericPortMappingEntryError`
     #[error("{0}")]
     GetGenericPortMappingEntryError(#[from] GetGenericPortMappingEntryError),
+    /// `GetSpecificPortMappingEntryError`
+    #[error("{0}")]
+    GetSpecificPortMappingEntryError(#[from] GetSpecificPortMappingEntryError),
+    /// `DefaultConnectionServiceError`
+    #[error("{0}")]
+    DefaultConnectionServiceError(#[from] DefaultConnectionServiceError),
+    /// `PinholeError`
+    #[error("{0}")]
+    PinholeError(#[from] PinholeError),
+    /// `SubscriptionError`
+    #[error("{0}")]
+    SubscriptionError(#[from] SubscriptionError),
+    /// `NatPmpError`
+    #[error("{0}")]
+    NatPmpError(#[from] NatPmpError),
+    /// `PcpError`
+    #[error("{0}")]
+    PcpError(#[from] PcpError),
+    /// `StunError`
+    #[error("{0}")]
+    StunError(#[from] StunError),
+    /// `PortMapperError`
+    #[error("{0}")]
+    PortMapperError(#[from] PortMapperError),
     /// `RemovePortError`
     #[error("{0}")]
     RemovePortError(#[from] RemovePortError),
@@ -243,5 +917,27 @@ pub enum Error {
     SearchError(#[from] SearchError),
 }
 
+impl Error {
+    /// The UPnP error code returned by the gateway, if the error comes from a UPnP request.
+    pub fn upnp_error_code(&self) -> Option<UpnpErrorCode> {
+        match self {
+            Error::AddAnyPortError(e) => e.upnp_error_code(),
+            Error::AddPortError(e) => e.upnp_error_code(),
+            Error::AddPortRangeError(e) => e.upnp_error_code(),
+            Error::CascadeError(e) => e.upnp_error_code(),
+            Error::GetExternalIpError(e) => e.upnp_error_code(),
+            Error::GetGenericPortMappingEntryError(e) => e.upnp_error_code(),
+            Error::GetSpecificPortMappingEntryError(e) => e.upnp_error_code(),
+            Error::DefaultConnectionServiceError(e) => e.upnp_error_code(),
+            Error::PinholeError(e) => e.upnp_error_code(),
+            Error::SubscriptionError(e) => e.upnp_error_code(),
+            Error::PortMapperError(e) => e.upnp_error_code(),
+            Error::RemovePortError(e) => e.upnp_error_code(),
+            Error::RequestError(e) => e.upnp_error_code(),
+            Error::NatPmpError(_) | Error::PcpError(_) | Error::StunError(_) | Error::SearchError(_) => None,
+        }
+    }
+}
+
 /// A result type where the error is `igd::Error`.
 pub type Result<T = ()> = std::result::Result<T, Error>;
@@ -0,0 +1,104 @@
+use std::net::{IpAddr, SocketAddr};
+
+use crate::common::firewall::{self, FirewallStatus, PinholeId, PinholeProtocol, WAN_IPV6_FIREWALL_CONTROL_SERVICE};
+use crate::common::parsing::RequestResult;
+use crate::errors::PinholeError;
+use crate::Gateway;
+
+impl Gateway {
+    fn perform_firewall_request(&self, action: &str, body: &str) -> RequestResult {
+        let service = self.service(WAN_IPV6_FIREWALL_CONTROL_SERVICE, action)?;
+        self.perform_service_request(
+            &service.control_url,
+            &service.service_type,
+            action,
+            body,
+            &format!("{action}Response"),
+        )
+    }
+
+    /// Get the state of the gateway's IPv6 firewall.
+    pub fn get_firewall_status(&self) -> Result<FirewallStatus, PinholeError> {
+        firewall::parse_get_firewall_status_response(self.perform_firewall_request(
+            firewall::GET_FIREWALL_STATUS_ACTION,
+            &firewall::format_get_firewall_status_message(),
+        ))
+    }
+
+    /// Get the timeout in seconds of outbound pinholes matching the given traffic.
+    ///
+    /// A `remote_host` of `None` and a `remote_port` of 0 are wildcards.
+    pub fn get_outbound_pinhole_timeout(
+        &self,
+        remote_host: Option<IpAddr>,
+        remote_port: u16,
+        internal_addr: SocketAddr,
+        protocol: PinholeProtocol,
+    ) -> Result<u32, PinholeError> {
+        firewall::parse_get_outbound_pinhole_timeout_response(self.perform_firewall_request(
+            firewall::GET_OUTBOUND_PINHOLE_TIMEOUT_ACTION,
+            &firewall::format_get_outbound_pinhole_timeout_message(remote_host, remote_port, internal_addr, protocol),
+        ))
+    }
+
+    /// Add an inbound pinhole to the IPv6 firewall.
+    ///
+    /// The internal_addr is the IPv6 address and port where the traffic is sent to.
+    /// A `remote_host` of `None` and a `remote_port` of 0 are wildcards.
+    /// The lease_time parameter is in seconds, between 1 and 86400.
+    ///
+    /// # Returns
+    ///
+    /// The id of the pinhole on success. Otherwise an error.
+    pub fn add_pinhole(
+        &self,
+        remote_host: Option<IpAddr>,
+        remote_port: u16,
+        internal_addr: SocketAddr,
+        protocol: PinholeProtocol,
+        lease_time: u32,
+    ) -> Result<PinholeId, PinholeError> {
+        firewall::parse_add_pinhole_response(self.perform_firewall_request(
+            firewall::ADD_PINHOLE_ACTION,
+            &firewall::format_add_pinhole_message(remote_host, remote_port, internal_addr, protocol, lease_time),
+        ))
+    }
+
+    /// Extend the lease of a pinhole.
+    ///
+    /// The lease_time parameter is in seconds, between 1 and 86400.
+    pub fn update_pinhole(&self, id: PinholeId, lease_time: u32) -> Result<(), PinholeError> {
+        self.perform_firewall_request(
+            firewall::UPDATE_PINHOLE_ACTION,
+            &firewall::format_update_pinhole_message(id, lease_time),
+        )
+        .map_err(firewall::convert_pinhole_error)?;
+        Ok(())
+    }
+
+    /// Remove a pinhole.
+    pub fn delete_pinhole(&self, id: PinholeId) -> Result<(), PinholeError> {
+        self.perform_firewall_request(
+            firewall::DELETE_PINHOLE_ACTION,
+            &firewall::format_pinhole_id_message(firewall::DELETE_PINHOLE_ACTION, id),
+        )
+        .map_err(firewall::convert_pinhole_error)?;
+        Ok(())
+    }
+
+    /// Get the number of packets that went through a pinhole.
+    pub fn get_pinhole_packets(&self, id: PinholeId) -> Result<u32, PinholeError> {
+        firewall::parse_get_pinhole_packets_response(self.perform_firewall_request(
+            firewall::GET_PINHOLE_PACKETS_ACTION,
+            &firewall::format_pinhole_id_message(firewall::GET_PINHOLE_PACKETS_ACTION, id),
+        ))
+    }
+
+    /// Check whether traffic has been received through a pinhole.
+    pub fn check_pinhole_working(&self, id: PinholeId) -> Result<bool, PinholeError> {
+        firewall::parse_check_pinhole_working_response(self.perform_firewall_request(
+            firewall::CHECK_PINHOLE_WORKING_ACTION,
+            &firewall::format_pinhole_id_message(firewall::CHECK_PINHOLE_WORKING_ACTION, id),
+        ))
+    }
+}
@@ -1,12 +1,23 @@
 use attohttpc::{Method, RequestBuilder};
+use socket2::{Domain, Protocol, Socket, Type};
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::net::{IpAddr, SocketAddr};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+use log::debug;
 
 use crate::common::options::{DEFAULT_REQUEST_TIMEOUT, MAX_RESPONSE_BYTES};
-use crate::common::{self, messages, parsing, parsing::RequestResult};
-use crate::errors::{self, AddAnyPortError, AddPortError, GetExternalIpError, RemovePortError, RequestError};
+use crate::common::{
+    self, http, lease, messages, parsing, parsing::OutArguments, parsing::RequestResult, parsing::ServiceDescription,
+    range,
+};
+use crate::errors::{
+    self, AddAnyPortError, AddPortError, AddPortRangeError, DefaultConnectionServiceError, GetExternalIpError,
+    RemovePortError, RequestError,
+};
 use crate::PortMappingProtocol;
 use crate::RequestError::AttoHttpError;
 
@@ -26,16 +37,54 @@ pub struct Gateway {
     /// Service type of the gateway's WAN connection service (e.g.
     /// `urn:schemas-upnp-org:service:WANIPConnection:1`)
     pub service_type: String,
+    /// All services advertised in the device description
+    pub services: Vec<ServiceDescription>,
+    /// Local address control connections are bound to, as some gateways only accept
+    /// mappings requested from the internal client. Set from the search's bind address, or
+    /// else the address routing to the gateway; `None` lets the OS pick the address.
+    ///
+    /// attohttpc can not bind its connections, so while this is set, control and event
+    /// subscription requests are sent with the minimal HTTP/1.1 client of this crate instead.
+    /// Set it to `None` to send them with attohttpc.
+    pub bind_addr: Option<IpAddr>,
 }
 
 impl Gateway {
     fn perform_request(&self, action: &str, body: &str, ok: &str) -> RequestResult {
-        let url = format!("http://{}{}", self.addr, self.control_url);
-        let header = messages::soap_action(&self.service_type, action);
+        self.perform_service_request(&self.control_url, &self.service_type, action, body, ok)
+    }
+
+    pub(crate) fn perform_service_request(
+        &self,
+        control_url: &str,
+        service_type: &str,
+        action: &str,
+        body: &str,
+        ok: &str,
+    ) -> RequestResult {
+        self.perform_service_request_within(control_url, service_type, action, body, ok, DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    fn perform_service_request_within(
+        &self,
+        control_url: &str,
+        service_type: &str,
+        action: &str,
+        body: &str,
+        ok: &str,
+        timeout: Duration,
+    ) -> RequestResult {
+        let header = messages::soap_action(service_type, action);
+        if let Some(bind_addr) = self.bind_addr {
+            let request = http::format_soap_request(self.addr, control_url, &header, body);
+            let response = send_from(self.addr, bind_addr, &request, timeout)?;
+            return parsing::parse_response(String::from_utf8_lossy(&response.body).into_owned(), ok);
+        }
 
+        let url = format!("http://{}{}", self.addr, control_url);
         let response = match RequestBuilder::try_new(Method::POST, url) {
             Ok(request_builder) => request_builder
-                .timeout(DEFAULT_REQUEST_TIMEOUT)
+                .timeout(timeout)
                 .header("SOAPAction", header.as_str())
                 .header("Content-Type", "text/xml")
                 .text(body)
@@ -48,6 +97,14 @@ impl Gateway {
         parsing::parse_response(text, ok)
     }
 
+    /// Find the advertised service of the given type, or fail with `UnsupportedAction(action)`.
+    pub(crate) fn service(&self, service_type: &str, action: &str) -> Result<&ServiceDescription, RequestError> {
+        self.services
+            .iter()
+            .find(|service| service.service_type == service_type)
+            .ok_or_else(|| RequestError::UnsupportedAction(action.to_string()))
+    }
+
     /// Get the external IP address of the gateway.
     pub fn get_external_ip(&self) -> Result<IpAddr, GetExternalIpError> {
         parsing::parse_get_external_ip_response(self.perform_request(
@@ -61,7 +118,9 @@ impl Gateway {
     /// function that calls `get_external_ip` followed by `add_any_port`
     ///
     /// The local_addr is the address where the traffic is sent to.
-    /// The lease_duration parameter is in seconds. A value of 0 is infinite.
+    /// The lease_duration parameter is in seconds. A value of 0 is infinite, except on IGD v2
+    /// (`WANIPConnection:2`) gateways, which allow no infinite lease: there 0 is sent as one
+    /// week (604800 seconds), the longest lease they accept, and longer leases are capped to it.
     ///
     /// # Returns
     ///
@@ -81,7 +140,9 @@ impl Gateway {
     /// Add a port mapping.with any external port.
     ///
     /// The local_addr is the address where the traffic is sent to.
-    /// The lease_duration parameter is in seconds. A value of 0 is infinite.
+    /// The lease_duration parameter is in seconds. A value of 0 is infinite, except on IGD v2
+    /// (`WANIPConnection:2`) gateways, which allow no infinite lease: there 0 is sent as one
+    /// week (604800 seconds), the longest lease they accept, and longer leases are capped to it.
     ///
     /// # Returns
     ///
@@ -96,8 +157,8 @@ impl Gateway {
         // This function first attempts to call AddAnyPortMapping on the IGD with a random port
         // number. If that fails due to the method being unknown it attempts to call AddPortMapping
         // instead with a random port number. If that fails due to ConflictInMappingEntry it retrys
-        // with another port up to a maximum of 20 times. If it fails due to SamePortValuesRequired
-        // it retrys once with the same port values.
+        // with another port up to a maximum of 20 times, then fails with NoPortsAvailable. If it
+        // fails due to SamePortValuesRequired it retrys once with the same port values.
 
         if local_addr.port() == 0 {
             return Err(AddAnyPortError::InternalPortZeroInvalid);
@@ -137,7 +198,7 @@ impl Gateway {
         for _ in 0..ATTEMPTS {
             match self.add_random_port_mapping(protocol, local_addr, lease_duration, description) {
                 Ok(port) => return Ok(port),
-                Err(AddAnyPortError::NoPortsAvailable) => continue,
+                Err(e) if parsing::is_random_port_conflict(&e) => continue,
                 Err(e) => return Err(e),
             }
         }
@@ -207,7 +268,9 @@ impl Gateway {
     /// Add a port mapping.
     ///
     /// The local_addr is the address where the traffic is sent to.
-    /// The lease_duration parameter is in seconds. A value of 0 is infinite.
+    /// The lease_duration parameter is in seconds. A value of 0 is infinite, except on IGD v2
+    /// (`WANIPConnection:2`) gateways, which allow no infinite lease: there 0 is sent as one
+    /// week (604800 seconds), the longest lease they accept, and longer leases are capped to it.
     pub fn add_port(
         &self,
         protocol: PortMappingProtocol,
@@ -227,6 +290,169 @@ impl Gateway {
             .map_err(parsing::convert_add_port_error)
     }
 
+    /// Add a port mapping with a lease of the given duration, `None` being infinite.
+    ///
+    /// The lease is adjusted to the version of the WAN connection service: IGD v2 gateways
+    /// cap leases at one week and do not grant infinite leases.
+    ///
+    /// # Returns
+    ///
+    /// The lease granted by the gateway on success, `None` being infinite. Otherwise an error.
+    pub fn add_port_with_lease(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<Option<Duration>, AddPortError> {
+        let lease_duration =
+            lease::lease_duration(&self.service_type, lease).map_err(AddPortError::InvalidLeaseDuration)?;
+        self.add_port(protocol, external_port, local_addr, lease_duration, description)?;
+        Ok(lease::granted_lease(&self.service_type, lease_duration))
+    }
+
+    /// Add a port mapping with any external port and a lease of the given duration, `None`
+    /// being infinite. The lease is adjusted as for [`add_port_with_lease`](Self::add_port_with_lease).
+    ///
+    /// # Returns
+    ///
+    /// The external port that was mapped and the lease granted by the gateway on success.
+    /// Otherwise an error.
+    pub fn add_any_port_with_lease(
+        &self,
+        protocol: PortMappingProtocol,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<(u16, Option<Duration>), AddAnyPortError> {
+        let lease_duration =
+            lease::lease_duration(&self.service_type, lease).map_err(AddAnyPortError::InvalidLeaseDuration)?;
+        let external_port = self.add_any_port(protocol, local_addr, lease_duration, description)?;
+        Ok((external_port, lease::granted_lease(&self.service_type, lease_duration)))
+    }
+
+    /// Add a port mapping, or refresh it if the external port is already mapped to `local_addr`.
+    ///
+    /// Gateways refuse to add a mapping of an external port that is in use, even by the same
+    /// client, for example after the process restarted. The existing mapping is then looked
+    /// up: if it points at `local_addr`, it is removed and added again with the new lease
+    /// and description. Should adding it again fail, the old mapping is restored with its
+    /// remaining lease, so that the port is not left to another host. Otherwise the error
+    /// is `AddPortError::PortInUseBy`, describing it.
+    pub fn add_or_refresh_port(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease_duration: u32,
+        description: &str,
+    ) -> Result<(), AddPortError> {
+        match self.add_port(protocol, external_port, local_addr, lease_duration, description) {
+            Err(AddPortError::PortInUse) => {}
+            result => return result,
+        }
+        let entry = match self.get_specific_port_mapping_entry(protocol, external_port) {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("could not look up the {protocol} mapping of external port {external_port}: {e}");
+                return Err(AddPortError::PortInUse);
+            }
+        };
+        if entry.internal_addr() != Some(local_addr) {
+            return Err(AddPortError::PortInUseBy(entry));
+        }
+        self.remove_port(protocol, external_port)
+            .map_err(parsing::convert_refresh_remove_error)?;
+        let result = self.add_port(protocol, external_port, local_addr, lease_duration, description);
+        if result.is_err() {
+            let restored = self.add_port(
+                protocol,
+                external_port,
+                local_addr,
+                entry.lease_duration,
+                &entry.port_mapping_description,
+            );
+            if let Err(e) = restored {
+                debug!("could not restore the {protocol} mapping of external port {external_port}: {e}");
+            }
+        }
+        result
+    }
+
+    /// The address of this host on the interface that routes to the gateway, with the given
+    /// port. This is the address the gateway expects as internal client.
+    pub fn local_addr_for_port(&self, local_port: u16) -> Result<SocketAddr, RequestError> {
+        Ok(SocketAddr::new(common::local_ip_for(self.addr)?, local_port))
+    }
+
+    /// Add a port mapping to a local port of this host, on the interface that routes to the
+    /// gateway. See [`add_port`](Self::add_port).
+    ///
+    /// # Returns
+    ///
+    /// The local address that was mapped on success. Otherwise an error.
+    pub fn add_local_port(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_port: u16,
+        lease_duration: u32,
+        description: &str,
+    ) -> Result<SocketAddr, AddPortError> {
+        let local_addr = self
+            .local_addr_for_port(local_port)
+            .map_err(AddPortError::RequestError)?;
+        self.add_port(protocol, external_port, local_addr, lease_duration, description)?;
+        Ok(local_addr)
+    }
+
+    /// Add a port mapping with any external port to a local port of this host, on the
+    /// interface that routes to the gateway. See [`add_any_port`](Self::add_any_port).
+    ///
+    /// # Returns
+    ///
+    /// The external port that was mapped on success. Otherwise an error.
+    pub fn add_any_local_port(
+        &self,
+        protocol: PortMappingProtocol,
+        local_port: u16,
+        lease_duration: u32,
+        description: &str,
+    ) -> Result<u16, AddAnyPortError> {
+        let local_addr = self.local_addr_for_port(local_port)?;
+        self.add_any_port(protocol, local_addr, lease_duration, description)
+    }
+
+    /// Add port mappings for a range of `count` consecutive ports, starting at external port
+    /// `external_start` and local address `local_addr_start`.
+    ///
+    /// Either every port of the range is mapped, or none is: when a port can not be mapped,
+    /// the ports mapped before it are removed again.
+    /// The lease is adjusted as for [`add_port_with_lease`](Self::add_port_with_lease).
+    pub fn add_port_range(
+        &self,
+        protocol: PortMappingProtocol,
+        external_start: u16,
+        local_addr_start: SocketAddr,
+        count: u16,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<(), AddPortRangeError> {
+        let ports = range::port_range(external_start, local_addr_start, count)?;
+        for (index, &(external_port, local_addr)) in ports.iter().enumerate() {
+            if let Err(error) = self.add_port_with_lease(protocol, external_port, local_addr, lease, description) {
+                for &(added_port, _) in &ports[..index] {
+                    if let Err(e) = self.remove_port(protocol, added_port) {
+                        debug!("could not roll back the {protocol} mapping of external port {added_port}: {e}");
+                    }
+                }
+                return Err(range::range_error(external_port, error));
+            }
+        }
+        Ok(())
+    }
+
     /// Remove a port mapping.
     pub fn remove_port(&self, protocol: PortMappingProtocol, external_port: u16) -> Result<(), RemovePortError> {
         parsing::parse_delete_port_mapping_response(self.perform_request(
@@ -258,6 +484,150 @@ impl Gateway {
             "GetGenericPortMappingEntryResponse",
         ))
     }
+
+    /// Get the port mapping of an external port.
+    pub fn get_specific_port_mapping_entry(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+    ) -> Result<parsing::PortMappingEntry, errors::GetSpecificPortMappingEntryError> {
+        parsing::parse_get_specific_port_mapping_entry(
+            self.perform_request(
+                messages::GET_SPECIFIC_PORT_MAPPING_ENTRY_ACTION,
+                &messages::format_get_specific_port_mapping_entry_message(&self.service_type, protocol, external_port),
+                "GetSpecificPortMappingEntryResponse",
+            ),
+            protocol,
+            external_port,
+        )
+    }
+
+    /// Invoke any action of an advertised service.
+    ///
+    /// The service can be given by its service type or its service id. The in-arguments are
+    /// sent in the order of the control schema when invoking an action of the WAN connection
+    /// service, and in the given order otherwise.
+    ///
+    /// # Returns
+    ///
+    /// The out-arguments of the action on success. Otherwise an error.
+    pub fn invoke(&self, service: &str, action: &str, args: &[(&str, &str)]) -> Result<OutArguments, RequestError> {
+        let args = args
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect::<Vec<_>>();
+        let (control_url, service_type) = if service == self.service_type {
+            (self.control_url.as_str(), self.service_type.as_str())
+        } else {
+            let description = self
+                .services
+                .iter()
+                .find(|description| description.service_type == service || description.service_id == service)
+                .ok_or_else(|| RequestError::UnsupportedAction(format!("{service}#{action}")))?;
+            (description.control_url.as_str(), description.service_type.as_str())
+        };
+        let schema = if control_url == self.control_url {
+            self.control_schema.get(action).map(Vec::as_slice)
+        } else {
+            None
+        };
+        let body = messages::format_action_message(service_type, action, &messages::order_arguments(schema, &args));
+        parsing::parse_out_arguments(self.perform_service_request(
+            control_url,
+            service_type,
+            action,
+            &body,
+            &format!("{action}Response"),
+        ))
+    }
+
+    /// Get the default connection service of the root device's Layer3Forwarding service.
+    ///
+    /// The value has the form `uuid:<device-uuid>:WANConnectionDevice:1,<service-id>`.
+    pub fn get_default_connection_service(&self) -> Result<String, DefaultConnectionServiceError> {
+        self.get_default_connection_service_within(DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    /// Get the default connection service, waiting at most `timeout` for the gateway.
+    pub(crate) fn get_default_connection_service_within(
+        &self,
+        timeout: Duration,
+    ) -> Result<String, DefaultConnectionServiceError> {
+        let service = self.service(
+            parsing::LAYER3_FORWARDING_SERVICE,
+            messages::GET_DEFAULT_CONNECTION_SERVICE_ACTION,
+        )?;
+        parsing::parse_get_default_connection_service_response(self.perform_service_request_within(
+            &service.control_url,
+            &service.service_type,
+            messages::GET_DEFAULT_CONNECTION_SERVICE_ACTION,
+            &messages::format_get_default_connection_service_message(&service.service_type),
+            "GetDefaultConnectionServiceResponse",
+            timeout,
+        ))
+    }
+
+    /// Set the default connection service of the root device's Layer3Forwarding service.
+    ///
+    /// The value has the form `uuid:<device-uuid>:WANConnectionDevice:1,<service-id>`.
+    pub fn set_default_connection_service(
+        &self,
+        default_connection_service: &str,
+    ) -> Result<(), DefaultConnectionServiceError> {
+        let service = self.service(
+            parsing::LAYER3_FORWARDING_SERVICE,
+            messages::SET_DEFAULT_CONNECTION_SERVICE_ACTION,
+        )?;
+        self.perform_service_request(
+            &service.control_url,
+            &service.service_type,
+            messages::SET_DEFAULT_CONNECTION_SERVICE_ACTION,
+            &messages::format_set_default_connection_service_message(&service.service_type, default_connection_service),
+            "SetDefaultConnectionServiceResponse",
+        )
+        .map_err(parsing::convert_default_connection_service_error)?;
+        Ok(())
+    }
+
+    /// Find the WAN connection service the gateway advertises as its default connection service.
+    ///
+    /// Returns `None` if the referenced service is not in `services`.
+    pub fn find_default_connection_service(
+        &self,
+    ) -> Result<Option<&ServiceDescription>, DefaultConnectionServiceError> {
+        let default_connection_service = self.get_default_connection_service()?;
+        Ok(parsing::find_default_connection_service(
+            &self.services,
+            &default_connection_service,
+        ))
+    }
+}
+
+// attohttpc can not bind its connections, so requests from a given address are sent over a
+// connection of our own.
+pub(crate) fn send_from(
+    addr: SocketAddr,
+    bind_addr: IpAddr,
+    request: &str,
+    timeout: Duration,
+) -> io::Result<http::Response> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.bind(&SocketAddr::new(bind_addr, 0).into())?;
+    socket.connect_timeout(&addr.into(), timeout)?;
+    let mut stream = TcpStream::from(socket);
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = Vec::new();
+    stream.take(MAX_RESPONSE_BYTES as u64 + 1).read_to_end(&mut response)?;
+    if response.len() > MAX_RESPONSE_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "gateway response exceeded the maximum allowed size",
+        ));
+    }
+    http::parse_response(&response)
 }
 
 impl fmt::Display for Gateway {
@@ -280,3 +650,169 @@ impl Hash for Gateway {
         self.control_url.hash(state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    // Answer one GetExternalIPAddress request, checking it comes from `bind_addr`.
+    fn stand_in(bind_addr: IpAddr) -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, peer) = listener.accept().unwrap();
+            assert_eq!(peer.ip(), bind_addr);
+            let mut request = Vec::new();
+            while common::gena::head_end(&request).is_none() {
+                let mut buf = [0; 1024];
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            let head = String::from_utf8_lossy(&request);
+            assert!(head.starts_with("POST /ctl HTTP/1.1\r\n"));
+            assert!(
+                head.contains("SOAPAction: \"urn:schemas-upnp-org:service:WANIPConnection:1#GetExternalIPAddress\"")
+            );
+            let body = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>
+<u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+<NewExternalIPAddress>203.0.113.7</NewExternalIPAddress>
+</u:GetExternalIPAddressResponse>
+</s:Body>
+</s:Envelope>"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{body}\r\n0\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        addr
+    }
+
+    #[test]
+    fn control_requests_are_sent_from_bind_addr() {
+        let bind_addr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let gateway = Gateway {
+            addr: stand_in(bind_addr),
+            root_url: "/root.xml".to_string(),
+            control_url: "/ctl".to_string(),
+            control_schema_url: "/scpd.xml".to_string(),
+            control_schema: HashMap::new(),
+            service_type: "urn:schemas-upnp-org:service:WANIPConnection:1".to_string(),
+            services: Vec::new(),
+            bind_addr: Some(bind_addr),
+        };
+        assert_eq!(gateway.get_external_ip().unwrap(), IpAddr::from([203, 0, 113, 7]));
+    }
+
+    fn envelope(body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+<s:Body>{body}</s:Body>
+</s:Envelope>"#
+        )
+    }
+
+    fn fault(code: u16) -> String {
+        envelope(&format!(
+            r#"<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail>
+<UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{code}</errorCode><errorDescription>Error {code}</errorDescription></UPnPError>
+</detail></s:Fault>"#
+        ))
+    }
+
+    // Answer one control request per connection with the scripted responses in turn,
+    // checking their actions. Returns the request bodies once the script is done.
+    fn scripted(script: Vec<(&'static str, String)>) -> (SocketAddr, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for (action, response) in script {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                let end = loop {
+                    if let Some(end) = common::gena::head_end(&request) {
+                        break end;
+                    }
+                    let read = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..read]);
+                };
+                let head = common::gena::parse_request_head(&request[..end]).unwrap();
+                while request.len() < end + head.content_length().unwrap() {
+                    let read = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..read]);
+                }
+                assert!(head.header("SOAPAction").unwrap().ends_with(&format!("#{action}\"")));
+                bodies.push(String::from_utf8_lossy(&request[end..]).into_owned());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            bodies
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn failed_refresh_restores_the_old_mapping() {
+        let service_type = "urn:schemas-upnp-org:service:WANIPConnection:1";
+        let ok = |action: &str| envelope(&format!(r#"<u:{action}Response xmlns:u="{service_type}"/>"#));
+        let entry = envelope(&format!(
+            r#"<u:GetSpecificPortMappingEntryResponse xmlns:u="{service_type}">
+<NewInternalPort>8080</NewInternalPort><NewInternalClient>127.0.0.1</NewInternalClient><NewEnabled>1</NewEnabled>
+<NewPortMappingDescription>old</NewPortMappingDescription><NewLeaseDuration>1800</NewLeaseDuration>
+</u:GetSpecificPortMappingEntryResponse>"#
+        ));
+        let (addr, bodies) = scripted(vec![
+            ("AddPortMapping", fault(718)),
+            ("GetSpecificPortMappingEntry", entry),
+            ("DeletePortMapping", ok("DeletePortMapping")),
+            ("AddPortMapping", fault(725)),
+            ("AddPortMapping", ok("AddPortMapping")),
+        ]);
+        let schema = |arguments: &[&str]| arguments.iter().map(|argument| argument.to_string()).collect();
+        let gateway = Gateway {
+            addr,
+            root_url: "/root.xml".to_string(),
+            control_url: "/ctl".to_string(),
+            control_schema_url: "/scpd.xml".to_string(),
+            control_schema: HashMap::from([
+                (
+                    "AddPortMapping".to_string(),
+                    schema(&[
+                        "NewRemoteHost",
+                        "NewExternalPort",
+                        "NewProtocol",
+                        "NewInternalPort",
+                        "NewInternalClient",
+                        "NewEnabled",
+                        "NewPortMappingDescription",
+                        "NewLeaseDuration",
+                    ]),
+                ),
+                (
+                    "DeletePortMapping".to_string(),
+                    schema(&["NewRemoteHost", "NewExternalPort", "NewProtocol"]),
+                ),
+            ]),
+            service_type: service_type.to_string(),
+            services: Vec::new(),
+            bind_addr: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        };
+
+        let local_addr = "127.0.0.1:8080".parse().unwrap();
+        let result = gateway.add_or_refresh_port(PortMappingProtocol::TCP, 80, local_addr, 3600, "new");
+        assert!(matches!(result, Err(AddPortError::OnlyPermanentLeasesSupported)));
+        let restore = &bodies.join().unwrap()[4];
+        assert!(restore.contains("<NewLeaseDuration>1800</NewLeaseDuration>"));
+        assert!(restore.contains("<NewPortMappingDescription>old</NewPortMappingDescription>"));
+    }
+}
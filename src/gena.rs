@@ -0,0 +1,423 @@
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use attohttpc::{Method, RequestBuilder};
+use log::{debug, warn};
+use url::Url;
+
+use crate::common;
+use crate::common::gena::{self, EventNotification, RequestHead};
+use crate::common::http;
+use crate::common::options::{DEFAULT_REQUEST_TIMEOUT, MAX_RESPONSE_BYTES};
+use crate::common::parsing::ServiceDescription;
+use crate::errors::{RequestError, SubscriptionError};
+use crate::gateway::{self, Gateway};
+
+/// How long to wait for a gateway to send a `NOTIFY` request once it connected. Each
+/// connection is read on its own thread, so a stalled one does not hold up other events.
+const NOTIFY_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before retrying a renewal that failed.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Receives the events gateways send to subscriptions, on a background thread.
+///
+/// The listener must be reachable by the gateway, so it should be bound to the unspecified
+/// address or to the address of the interface the gateway is reachable on.
+pub struct EventListener {
+    local_addr: SocketAddr,
+    events: Receiver<EventNotification>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl EventListener {
+    /// Listen for events on the given address. Use port 0 to let the OS pick a port.
+    pub fn bind(addr: SocketAddr) -> io::Result<EventListener> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let (sender, events) = mpsc::channel();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let stop = shutdown.clone();
+        thread::Builder::new()
+            .name("igd-event-listener".to_string())
+            .spawn(move || accept_notifications(listener, sender, stop))?;
+        Ok(EventListener {
+            local_addr,
+            events,
+            shutdown,
+        })
+    }
+
+    /// The address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Block until the next event is received.
+    pub fn recv(&self) -> Result<EventNotification, RecvError> {
+        self.events.recv()
+    }
+
+    /// Block until the next event is received, or the timeout elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<EventNotification, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// Get the next event if one was already received.
+    pub fn try_recv(&self) -> Result<EventNotification, TryRecvError> {
+        self.events.try_recv()
+    }
+
+    /// The address the gateway at `gateway_addr` should send events to.
+    fn callback_addr(&self, gateway_addr: SocketAddr) -> io::Result<SocketAddr> {
+        if self.local_addr.ip().is_unspecified() {
+            Ok(SocketAddr::new(
+                common::local_ip_for(gateway_addr)?,
+                self.local_addr.port(),
+            ))
+        } else {
+            Ok(self.local_addr)
+        }
+    }
+}
+
+impl Drop for EventListener {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the thread blocked accepting connections so it sees the shutdown flag.
+        let ip = match self.local_addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        let _ = TcpStream::connect_timeout(&SocketAddr::new(ip, self.local_addr.port()), NOTIFY_READ_TIMEOUT);
+    }
+}
+
+fn accept_notifications(listener: TcpListener, sender: Sender<EventNotification>, stop: Arc<AtomicBool>) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug!("error while accepting an event connection: {e}");
+                continue;
+            }
+        };
+        let sender = sender.clone();
+        let spawned = thread::Builder::new()
+            .name("igd-event-connection".to_string())
+            .spawn(move || {
+                if let Some(notification) = handle_notification(stream) {
+                    let _ = sender.send(notification);
+                }
+            });
+        if let Err(e) = spawned {
+            debug!("could not handle an event connection: {e}");
+        }
+    }
+}
+
+fn handle_notification(mut stream: TcpStream) -> Option<EventNotification> {
+    if let Err(e) = stream.set_read_timeout(Some(NOTIFY_READ_TIMEOUT)) {
+        debug!("could not set the event connection timeout: {e}");
+        return None;
+    }
+    let result = read_request(&mut stream).and_then(|(head, body)| gena::parse_notification(&head, &body));
+    let reply = match &result {
+        Ok(_) => gena::NOTIFY_OK.to_string(),
+        Err(status) => {
+            debug!("rejecting event request with status {status}");
+            gena::notify_error(*status)
+        }
+    };
+    if let Err(e) = stream.write_all(reply.as_bytes()) {
+        debug!("could not reply to an event request: {e}");
+    }
+    result.ok()
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<(RequestHead, Vec<u8>), u16> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let end = loop {
+        if let Some(end) = gena::head_end(&buf) {
+            break end;
+        }
+        if buf.len() > gena::MAX_HEAD_BYTES {
+            return Err(413);
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => return Err(400),
+            Ok(read) => buf.extend_from_slice(&chunk[..read]),
+        }
+    };
+    let head = gena::parse_request_head(&buf[..end]).ok_or(400u16)?;
+    let length = head.content_length().ok_or(400u16)?;
+    if length > MAX_RESPONSE_BYTES {
+        return Err(413);
+    }
+    let mut body = buf.split_off(end);
+    if body.len() < length {
+        let mut rest = vec![0u8; length - body.len()];
+        stream.read_exact(&mut rest).map_err(|_| 400u16)?;
+        body.extend_from_slice(&rest);
+    }
+    body.truncate(length);
+    Ok((head, body))
+}
+
+/// A subscription to the events of a service, which is renewed on a background thread until
+/// it is dropped or unsubscribed.
+pub struct Subscription {
+    url: String,
+    bind_addr: Option<IpAddr>,
+    sid: Arc<Mutex<String>>,
+    control: Option<Sender<bool>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Subscription {
+    /// The subscription id, which matches `EventNotification::sid` of the events it receives.
+    ///
+    /// It changes if the gateway forgot the subscription and it had to be made again.
+    pub fn sid(&self) -> String {
+        self.sid.lock().unwrap().clone()
+    }
+
+    /// Cancel the subscription.
+    ///
+    /// Dropping the subscription also cancels it, but ignores any error.
+    pub fn unsubscribe(mut self) -> Result<(), SubscriptionError> {
+        if let Some(control) = self.control.take() {
+            let _ = control.send(false);
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        send_unsubscribe(&self.url, self.bind_addr, &self.sid())
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(control) = self.control.take() {
+            let _ = control.send(true);
+        }
+    }
+}
+
+impl Gateway {
+    /// Subscribe to the events of the WAN connection service, such as changes of
+    /// `ExternalIPAddress`, `ConnectionStatus` and `PortMappingNumberOfEntries`.
+    ///
+    /// The events are delivered to the listener. The gateway may grant a shorter duration than
+    /// the requested timeout; the subscription is renewed before it expires either way.
+    /// Requests are sent from [`Gateway::bind_addr`], like control requests.
+    pub fn subscribe(&self, listener: &EventListener, timeout: Duration) -> Result<Subscription, SubscriptionError> {
+        let service = self
+            .services
+            .iter()
+            .find(|service| service.control_url == self.control_url)
+            .ok_or(SubscriptionError::EventsNotSupported)?;
+        self.subscribe_service(service, listener, timeout)
+    }
+
+    /// Subscribe to the events of any advertised service.
+    pub fn subscribe_service(
+        &self,
+        service: &ServiceDescription,
+        listener: &EventListener,
+        timeout: Duration,
+    ) -> Result<Subscription, SubscriptionError> {
+        if service.event_sub_url.is_empty() {
+            return Err(SubscriptionError::EventsNotSupported);
+        }
+        let url = gena::event_url(&self.addr, &service.event_sub_url);
+        let callback = gena::callback(&listener.callback_addr(self.addr)?);
+        let bind_addr = self.bind_addr;
+        let (sid, granted) = send_subscribe(&url, bind_addr, &callback, timeout)?;
+        debug!("subscribed to {url} as {sid} for {granted:?}");
+
+        let sid = Arc::new(Mutex::new(sid));
+        let (control, control_receiver) = mpsc::channel();
+        let thread = {
+            let url = url.clone();
+            let sid = sid.clone();
+            thread::Builder::new()
+                .name("igd-subscription".to_string())
+                .spawn(move || renew_subscription(url, bind_addr, callback, sid, timeout, granted, control_receiver))?
+        };
+        Ok(Subscription {
+            url,
+            bind_addr,
+            sid,
+            control: Some(control),
+            thread: Some(thread),
+        })
+    }
+}
+
+fn renew_subscription(
+    url: String,
+    bind_addr: Option<IpAddr>,
+    callback: String,
+    sid: Arc<Mutex<String>>,
+    timeout: Duration,
+    granted: Duration,
+    control: Receiver<bool>,
+) {
+    let mut interval = gena::renew_interval(granted);
+    loop {
+        match control.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(unsubscribe) => {
+                if unsubscribe {
+                    let _ = send_unsubscribe(&url, bind_addr, &sid.lock().unwrap());
+                }
+                return;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let _ = send_unsubscribe(&url, bind_addr, &sid.lock().unwrap());
+                return;
+            }
+        }
+
+        let current = sid.lock().unwrap().clone();
+        let renewed = match send_renew(&url, bind_addr, &current, timeout) {
+            Err(SubscriptionError::PreconditionFailed) => {
+                debug!("subscription {current} expired, subscribing again to {url}");
+                send_subscribe(&url, bind_addr, &callback, timeout)
+            }
+            result => result,
+        };
+        interval = match renewed {
+            Ok((new_sid, granted)) => {
+                *sid.lock().unwrap() = new_sid;
+                gena::renew_interval(granted)
+            }
+            Err(e) => {
+                warn!("could not renew subscription to {url}: {e}");
+                RETRY_INTERVAL
+            }
+        };
+    }
+}
+
+// A reply to a SUBSCRIBE or UNSUBSCRIBE request.
+struct Reply {
+    status: u16,
+    sid: Option<String>,
+    timeout: Option<String>,
+}
+
+fn send(
+    method: &str,
+    url: &str,
+    bind_addr: Option<IpAddr>,
+    headers: &[(&'static str, &str)],
+) -> Result<Reply, SubscriptionError> {
+    if let Some(bind_addr) = bind_addr {
+        return send_from(method, url, bind_addr, headers);
+    }
+
+    let mut request_builder =
+        RequestBuilder::try_new(Method::from_bytes(method.as_bytes()).expect("valid HTTP method"), url)
+            .map_err(RequestError::from)?;
+    for (name, value) in headers {
+        request_builder = request_builder.header(*name, *value);
+    }
+    let response = request_builder
+        .timeout(DEFAULT_REQUEST_TIMEOUT)
+        .send()
+        .map_err(RequestError::from)?;
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    Ok(Reply {
+        status: response.status().as_u16(),
+        sid: header("SID"),
+        timeout: header("TIMEOUT"),
+    })
+}
+
+// Send the request over a connection bound to `bind_addr`, which attohttpc does not support.
+fn send_from(
+    method: &str,
+    url: &str,
+    bind_addr: IpAddr,
+    headers: &[(&'static str, &str)],
+) -> Result<Reply, SubscriptionError> {
+    let invalid = |e: &dyn std::fmt::Display| RequestError::InvalidResponse(format!("invalid event url {url}: {e}"));
+    let parsed = Url::parse(url).map_err(|e| invalid(&e))?;
+    let addr = *parsed
+        .socket_addrs(|| Some(80))?
+        .first()
+        .ok_or_else(|| invalid(&"no address"))?;
+    let path = match parsed.query() {
+        Some(query) => format!("{}?{query}", parsed.path()),
+        None => parsed.path().to_string(),
+    };
+    let request = http::format_request(method, addr, &path, headers, "");
+    let response = gateway::send_from(addr, bind_addr, &request, DEFAULT_REQUEST_TIMEOUT)?;
+    Ok(Reply {
+        status: response.status,
+        sid: response.header("SID").map(str::to_string),
+        timeout: response.header("TIMEOUT").map(str::to_string),
+    })
+}
+
+fn send_subscribe(
+    url: &str,
+    bind_addr: Option<IpAddr>,
+    callback: &str,
+    timeout: Duration,
+) -> Result<(String, Duration), SubscriptionError> {
+    let reply = send(
+        "SUBSCRIBE",
+        url,
+        bind_addr,
+        &[
+            ("CALLBACK", callback),
+            ("NT", "upnp:event"),
+            ("TIMEOUT", &gena::format_timeout(timeout)),
+        ],
+    )?;
+    gena::parse_subscribe_response(reply.status, reply.sid.as_deref(), reply.timeout.as_deref(), timeout)
+}
+
+fn send_renew(
+    url: &str,
+    bind_addr: Option<IpAddr>,
+    sid: &str,
+    timeout: Duration,
+) -> Result<(String, Duration), SubscriptionError> {
+    let reply = send(
+        "SUBSCRIBE",
+        url,
+        bind_addr,
+        &[("SID", sid), ("TIMEOUT", &gena::format_timeout(timeout))],
+    )?;
+    gena::parse_subscribe_response(
+        reply.status,
+        reply.sid.as_deref().or(Some(sid)),
+        reply.timeout.as_deref(),
+        timeout,
+    )
+}
+
+fn send_unsubscribe(url: &str, bind_addr: Option<IpAddr>, sid: &str) -> Result<(), SubscriptionError> {
+    let reply = send("UNSUBSCRIBE", url, bind_addr, &[("SID", sid)])?;
+    gena::parse_unsubscribe_response(reply.status)
+}
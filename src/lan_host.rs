@@ -0,0 +1,67 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::common::lan_host::{self, AddressRange, LAN_HOST_CONFIG_MANAGEMENT_SERVICE};
+use crate::common::parsing::RequestResult;
+use crate::errors::RequestError;
+use crate::Gateway;
+
+impl Gateway {
+    fn perform_lan_host_config_request(&self, action: &str) -> RequestResult {
+        let service = self.service(LAN_HOST_CONFIG_MANAGEMENT_SERVICE, action)?;
+        self.perform_service_request(
+            &service.control_url,
+            &service.service_type,
+            action,
+            &lan_host::format_lan_host_config_message(&service.service_type, action),
+            &format!("{action}Response"),
+        )
+    }
+
+    /// Get whether the DHCP server of the LAN can be configured.
+    pub fn get_dhcp_server_configurable(&self) -> Result<bool, RequestError> {
+        self.perform_lan_host_config_request(lan_host::GET_DHCP_SERVER_CONFIGURABLE_ACTION)?
+            .bool_argument("NewDHCPServerConfigurable")
+    }
+
+    /// Get the subnet mask of the LAN.
+    pub fn get_subnet_mask(&self) -> Result<Ipv4Addr, RequestError> {
+        lan_host::parse_get_subnet_mask_response(self.perform_lan_host_config_request(lan_host::GET_SUBNET_MASK_ACTION))
+    }
+
+    /// Get the routers the DHCP server hands out to LAN hosts.
+    pub fn get_ip_routers_list(&self) -> Result<Vec<IpAddr>, RequestError> {
+        lan_host::parse_address_list_response(
+            self.perform_lan_host_config_request(lan_host::GET_IP_ROUTERS_LIST_ACTION),
+            "NewIPRouters",
+        )
+    }
+
+    /// Get the domain name the DHCP server hands out to LAN hosts.
+    pub fn get_domain_name(&self) -> Result<String, RequestError> {
+        self.perform_lan_host_config_request(lan_host::GET_DOMAIN_NAME_ACTION)?
+            .argument("NewDomainName")
+    }
+
+    /// Get the range of addresses the DHCP server hands out to LAN hosts.
+    pub fn get_address_range(&self) -> Result<AddressRange, RequestError> {
+        lan_host::parse_get_address_range_response(
+            self.perform_lan_host_config_request(lan_host::GET_ADDRESS_RANGE_ACTION),
+        )
+    }
+
+    /// Get the DNS servers the DHCP server hands out to LAN hosts.
+    pub fn get_dns_servers(&self) -> Result<Vec<IpAddr>, RequestError> {
+        lan_host::parse_address_list_response(
+            self.perform_lan_host_config_request(lan_host::GET_DNS_SERVERS_ACTION),
+            "NewDNSServers",
+        )
+    }
+
+    /// Get the addresses within the address range the DHCP server does not hand out.
+    pub fn get_reserved_addresses(&self) -> Result<Vec<IpAddr>, RequestError> {
+        lan_host::parse_address_list_response(
+            self.perform_lan_host_config_request(lan_host::GET_RESERVED_ADDRESSES_ACTION),
+            "NewReservedAddresses",
+        )
+    }
+}
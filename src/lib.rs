@@ -6,18 +6,67 @@
 
 // data structures
 #[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
-pub use self::common::parsing::PortMappingEntry;
+pub use self::common::batch::{BatchReport, MappingOperation, OperationOutcome, DEFAULT_BATCH_CONCURRENCY};
 #[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
-pub use self::common::SearchOptions;
+pub use self::common::firewall::{FirewallStatus, PinholeId, PinholeProtocol};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::gena::{ConnectionStatus, EventNotification, PropertyChange};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::lan_host::AddressRange;
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::lease::MAX_LEASE_DURATION_V2;
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::link_config::{CableLinkConfigInfo, DslLinkInfo, LinkStatus};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::manager::{MappingFailure, MappingSpec};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::natpmp::{NatPmpExternalAddress, NatPmpMapping, NATPMP_PORT};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::options::DEFAULT_SUBSCRIPTION_TIMEOUT;
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::parsing::{OutArguments, PortMappingEntry, ServiceDescription};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::pcp::{PcpAnnounce, PcpMapOptions, PcpMapping, PcpMappingKind, PCP_PORT};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::port_mapper::{MappedPort, MappingMechanism, PortMapperOptions};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::ppp::LinkLayerMaxBitRates;
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::reconcile::ReconcilePlan;
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::stun::{ExternalIpCheck, STUN_PORT};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::topology::{AddressKind, NatTopology};
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::watcher::ExternalIpChange;
+#[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
+pub use self::common::{MapOptions, SearchOptions, WatchOptions};
 #[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
 pub use self::errors::{
-    AddAnyPortError, AddPortError, GetExternalIpError, GetGenericPortMappingEntryError, RemovePortError, RequestError,
-    SearchError,
+    AddAnyPortError, AddPortError, AddPortRangeError, CascadeError, DefaultConnectionServiceError, GetExternalIpError,
+    GetGenericPortMappingEntryError, NatPmpError, PcpError, PinholeError, PortMapperError, RemovePortError,
+    RequestError, SearchError, StunError, SubscriptionError, UpnpErrorCode,
 };
 #[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
 pub use self::errors::{Error, Result};
 #[cfg(feature = "io_sync")]
 pub use self::gateway::Gateway;
+#[cfg(feature = "io_sync")]
+pub use self::gena::{EventListener, Subscription};
+#[cfg(feature = "io_sync")]
+pub use self::manager::MappingManager;
+#[cfg(feature = "io_sync")]
+pub use self::mapping::PortMapping;
+#[cfg(feature = "io_sync")]
+pub use self::natpmp::NatPmpClient;
+#[cfg(feature = "io_sync")]
+pub use self::pcp::PcpClient;
+#[cfg(feature = "io_sync")]
+pub use self::port_mapper::PortMapper;
+#[cfg(feature = "io_sync")]
+pub use self::stun::StunClient;
+#[cfg(feature = "io_sync")]
+pub use self::watcher::ExternalIpWatcher;
 
 // search of gateway
 #[cfg(feature = "io_sync")]
@@ -25,14 +74,46 @@ pub use self::search::search_gateway;
 
 #[cfg(feature = "aio_tokio")]
 pub mod aio;
+#[cfg(feature = "io_sync")]
+mod batch;
+#[cfg(feature = "io_sync")]
+mod cascade;
 #[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
 mod common;
 #[cfg(any(feature = "io_sync", feature = "aio_tokio"))]
 mod errors;
 #[cfg(feature = "io_sync")]
+mod firewall;
+#[cfg(feature = "io_sync")]
 mod gateway;
 #[cfg(feature = "io_sync")]
+mod gena;
+#[cfg(feature = "io_sync")]
+mod lan_host;
+#[cfg(feature = "io_sync")]
+mod link_config;
+#[cfg(feature = "io_sync")]
+mod manager;
+#[cfg(feature = "io_sync")]
+mod mapping;
+#[cfg(feature = "io_sync")]
+mod natpmp;
+#[cfg(feature = "io_sync")]
+mod pcp;
+#[cfg(feature = "io_sync")]
+mod port_mapper;
+#[cfg(feature = "io_sync")]
+mod ppp;
+#[cfg(feature = "io_sync")]
+mod reconcile;
+#[cfg(feature = "io_sync")]
 mod search;
+#[cfg(feature = "io_sync")]
+mod stun;
+#[cfg(feature = "io_sync")]
+mod topology;
+#[cfg(feature = "io_sync")]
+mod watcher;
 
 use std::fmt;
 
@@ -0,0 +1,140 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use log::debug;
+
+use crate::common::manager::{self, MappingFailure, MappingSpec, Schedule};
+use crate::errors::{AddPortError, RemovePortError};
+use crate::{Gateway, PortMappingProtocol};
+
+/// Keeps a set of port mappings alive on a background thread, adding each one again before
+/// its lease expires, which also re-creates mappings the gateway lost.
+///
+/// Dropping the manager stops the renewals; the mappings then expire with their lease.
+pub struct MappingManager {
+    gateway: Gateway,
+    schedule: Arc<Mutex<Schedule>>,
+    wake: Sender<()>,
+}
+
+impl MappingManager {
+    /// Manage mappings on the gateway, calling `on_failure` whenever a renewal fails.
+    pub fn new<F>(gateway: Gateway, on_failure: F) -> io::Result<MappingManager>
+    where
+        F: Fn(MappingFailure) + Send + 'static,
+    {
+        let schedule = Arc::new(Mutex::new(Schedule::default()));
+        let (wake, woken) = mpsc::channel();
+        {
+            let gateway = gateway.clone();
+            let schedule = schedule.clone();
+            thread::Builder::new()
+                .name("igd-mapping-manager".to_string())
+                .spawn(move || renew_mappings(gateway, schedule, woken, on_failure))?;
+        }
+        Ok(MappingManager {
+            gateway,
+            schedule,
+            wake,
+        })
+    }
+
+    /// Manage mappings on the gateway, sending the failed renewals to the returned channel.
+    pub fn with_channel(gateway: Gateway) -> io::Result<(MappingManager, Receiver<MappingFailure>)> {
+        let (sender, failures) = mpsc::channel();
+        let manager = MappingManager::new(gateway, move |failure| {
+            let _ = sender.send(failure);
+        })?;
+        Ok((manager, failures))
+    }
+
+    /// Add the mapping to the gateway and keep it alive.
+    pub fn add(&self, mapping: MappingSpec) -> Result<(), AddPortError> {
+        let granted = self.gateway.add_port_with_lease(
+            mapping.protocol,
+            mapping.external_port,
+            mapping.local_addr,
+            mapping.lease,
+            &mapping.description,
+        )?;
+        self.schedule.lock().unwrap().insert(mapping, granted, Instant::now());
+        let _ = self.wake.send(());
+        Ok(())
+    }
+
+    /// Stop managing a mapping and remove it from the gateway.
+    pub fn remove(&self, protocol: PortMappingProtocol, external_port: u16) -> Result<(), RemovePortError> {
+        self.schedule.lock().unwrap().remove(protocol, external_port);
+        self.gateway.remove_port(protocol, external_port)
+    }
+
+    /// The managed mappings.
+    pub fn mappings(&self) -> Vec<MappingSpec> {
+        self.schedule.lock().unwrap().mappings()
+    }
+}
+
+fn renew_mappings<F>(gateway: Gateway, schedule: Arc<Mutex<Schedule>>, woken: Receiver<()>, on_failure: F)
+where
+    F: Fn(MappingFailure),
+{
+    loop {
+        let next_due = schedule.lock().unwrap().next_due();
+        let timeout = match next_due {
+            Some(due) => due.saturating_duration_since(Instant::now()),
+            None => manager::IDLE_INTERVAL,
+        };
+        match woken.recv_timeout(timeout) {
+            Ok(()) => continue,
+            Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        // The schedule is only locked between requests, so adding, removing and listing
+        // mappings do not wait for the renewals.
+        let due = schedule.lock().unwrap().due(Instant::now());
+        for (mapping, generation) in due {
+            let result = gateway.add_port_with_lease(
+                mapping.protocol,
+                mapping.external_port,
+                mapping.local_addr,
+                mapping.lease,
+                &mapping.description,
+            );
+            let current =
+                schedule
+                    .lock()
+                    .unwrap()
+                    .renewed(&mapping, generation, result.as_ref().copied(), Instant::now());
+            match result {
+                Ok(_) if !current => undo_stale_renewal(&gateway, &schedule, &mapping),
+                Ok(_) => {}
+                Err(error) if current => on_failure(MappingFailure { mapping, error }),
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+// The mapping was removed or added again while it was being renewed, so the renewal may
+// have overwritten that change on the gateway. Remove the mapping again if it is no longer
+// managed, and renew the current version right away otherwise.
+fn undo_stale_renewal(gateway: &Gateway, schedule: &Mutex<Schedule>, mapping: &MappingSpec) {
+    if !schedule
+        .lock()
+        .unwrap()
+        .contains(mapping.protocol, mapping.external_port)
+    {
+        if let Err(error) = gateway.remove_port(mapping.protocol, mapping.external_port) {
+            debug!("could not remove the renewal of a removed mapping: {}", error);
+        }
+    }
+    // Also covers a mapping added again during the removal above.
+    schedule
+        .lock()
+        .unwrap()
+        .renew_now(mapping.protocol, mapping.external_port, Instant::now());
+}
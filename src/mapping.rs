@@ -0,0 +1,234 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::common::MapOptions;
+use crate::errors::{AddAnyPortError, AddPortError, RemovePortError};
+use crate::{Gateway, PortMappingProtocol};
+
+/// A port mapping that is removed from the gateway when dropped.
+///
+/// Use [`release`](Self::release) to remove it and get the error, if any, or
+/// [`leak`](Self::leak) to keep it on the gateway.
+#[derive(Debug)]
+pub struct PortMapping {
+    gateway: Gateway,
+    protocol: PortMappingProtocol,
+    external_port: u16,
+    lease: Option<Duration>,
+    expiry: Option<Expiry>,
+    active: bool,
+}
+
+// Removal of a permanent mapping once the requested lease has passed. Dropping `detach`
+// without sending cancels it.
+#[derive(Debug)]
+struct Expiry {
+    detach: Sender<()>,
+    expired: Arc<AtomicBool>,
+}
+
+impl PortMapping {
+    fn new(
+        gateway: &Gateway,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        granted: Option<Duration>,
+        requested: Option<Duration>,
+    ) -> PortMapping {
+        let expiry = requested.filter(|_| granted.is_none()).map(|lease| {
+            let (detach, detached) = mpsc::channel();
+            let expired = Arc::new(AtomicBool::new(false));
+            let thread = {
+                let gateway = gateway.clone();
+                let expired = expired.clone();
+                thread::Builder::new()
+                    .name("igd-lease-expiry".to_string())
+                    .spawn(move || remove_after(gateway, protocol, external_port, lease, detached, expired))
+            };
+            if let Err(e) = thread {
+                debug!(
+                    "could not schedule the removal of the {protocol} mapping of external port {external_port}: {e}"
+                );
+            }
+            Expiry { detach, expired }
+        });
+        PortMapping {
+            gateway: gateway.clone(),
+            protocol,
+            external_port,
+            lease: granted,
+            expiry,
+            active: true,
+        }
+    }
+
+    /// The gateway the port is mapped on.
+    pub fn gateway(&self) -> &Gateway {
+        &self.gateway
+    }
+
+    /// The protocol of the mapping.
+    pub fn protocol(&self) -> PortMappingProtocol {
+        self.protocol
+    }
+
+    /// The external port of the mapping.
+    pub fn external_port(&self) -> u16 {
+        self.external_port
+    }
+
+    /// The lease of the mapping on the gateway, `None` being infinite.
+    pub fn lease(&self) -> Option<Duration> {
+        self.lease
+    }
+
+    /// Whether the mapping has an infinite lease on the gateway.
+    ///
+    /// When the gateway only supports permanent leases and the lease was emulated, the
+    /// mapping is still removed once the requested lease has passed.
+    pub fn is_permanent(&self) -> bool {
+        self.lease.is_none()
+    }
+
+    /// Remove the mapping from the gateway now.
+    pub fn release(mut self) -> Result<(), RemovePortError> {
+        self.active = false;
+        self.gateway.remove_port(self.protocol, self.external_port)
+    }
+
+    /// Keep the mapping on the gateway. It then lives until its lease expires or it is
+    /// removed with `remove_port`.
+    pub fn leak(mut self) {
+        self.active = false;
+        if let Some(expiry) = &self.expiry {
+            let _ = expiry.detach.send(());
+        }
+    }
+}
+
+impl Drop for PortMapping {
+    fn drop(&mut self) {
+        let expired = self
+            .expiry
+            .as_ref()
+            .is_some_and(|expiry| expiry.expired.load(Ordering::SeqCst));
+        if !self.active || expired {
+            return;
+        }
+        if let Err(e) = self.gateway.remove_port(self.protocol, self.external_port) {
+            debug!(
+                "could not remove the {} mapping of external port {}: {e}",
+                self.protocol, self.external_port
+            );
+        }
+    }
+}
+
+fn remove_after(
+    gateway: Gateway,
+    protocol: PortMappingProtocol,
+    external_port: u16,
+    lease: Duration,
+    detached: Receiver<()>,
+    expired: Arc<AtomicBool>,
+) {
+    let deadline = Instant::now() + lease;
+    match detached.recv_timeout(lease) {
+        Ok(()) => thread::sleep(deadline.saturating_duration_since(Instant::now())),
+        Err(RecvTimeoutError::Disconnected) => return,
+        Err(RecvTimeoutError::Timeout) => {}
+    }
+    expired.store(true, Ordering::SeqCst);
+    match gateway.remove_port(protocol, external_port) {
+        Ok(()) => debug!("removed the {protocol} mapping of external port {external_port} as its lease expired"),
+        Err(e) => debug!("could not remove the {protocol} mapping of external port {external_port}: {e}"),
+    }
+}
+
+impl Gateway {
+    /// Add a port mapping that is removed when the returned guard is dropped.
+    ///
+    /// The arguments are the same as for [`add_port_with_lease`](Self::add_port_with_lease).
+    pub fn map_port(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<PortMapping, AddPortError> {
+        self.map_port_with_options(
+            protocol,
+            external_port,
+            local_addr,
+            lease,
+            description,
+            MapOptions::default(),
+        )
+    }
+
+    /// Add a port mapping that is removed when the returned guard is dropped, with the given
+    /// options.
+    pub fn map_port_with_options(
+        &self,
+        protocol: PortMappingProtocol,
+        external_port: u16,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+        options: MapOptions,
+    ) -> Result<PortMapping, AddPortError> {
+        let granted = match self.add_port_with_lease(protocol, external_port, local_addr, lease, description) {
+            Ok(granted) => granted,
+            Err(AddPortError::OnlyPermanentLeasesSupported) if options.permanent_lease_fallback => {
+                debug!("gateway only supports permanent leases, mapping external port {external_port} permanently");
+                self.add_port(protocol, external_port, local_addr, 0, description)?;
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(PortMapping::new(self, protocol, external_port, granted, lease))
+    }
+
+    /// Add a port mapping with any external port that is removed when the returned guard is
+    /// dropped.
+    ///
+    /// The arguments are the same as for
+    /// [`add_any_port_with_lease`](Self::add_any_port_with_lease).
+    pub fn map_any_port(
+        &self,
+        protocol: PortMappingProtocol,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+    ) -> Result<PortMapping, AddAnyPortError> {
+        self.map_any_port_with_options(protocol, local_addr, lease, description, MapOptions::default())
+    }
+
+    /// Add a port mapping with any external port that is removed when the returned guard is
+    /// dropped, with the given options.
+    pub fn map_any_port_with_options(
+        &self,
+        protocol: PortMappingProtocol,
+        local_addr: SocketAddr,
+        lease: Option<Duration>,
+        description: &str,
+        options: MapOptions,
+    ) -> Result<PortMapping, AddAnyPortError> {
+        let (external_port, granted) = match self.add_any_port_with_lease(protocol, local_addr, lease, description) {
+            Ok(mapped) => mapped,
+            Err(AddAnyPortError::OnlyPermanentLeasesSupported) if options.permanent_lease_fallback => {
+                debug!("gateway only supports permanent leases, mapping a port permanently");
+                (self.add_any_port(protocol, local_addr, 0, description)?, None)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(PortMapping::new(self, protocol, external_port, granted, lease))
+    }
+}
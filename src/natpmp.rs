@@ -0,0 +1,203 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::common::natpmp::{self, EpochTracker, NatPmpExternalAddress, NatPmpMapping};
+use crate::errors::NatPmpError;
+use crate::PortMappingProtocol;
+
+/// A client of a NAT-PMP (RFC 6886) gateway.
+pub struct NatPmpClient {
+    socket: UdpSocket,
+    gateway: SocketAddr,
+    max_attempts: u32,
+    // Held for the whole exchange, so concurrent requests do not read each other's responses.
+    epoch: Mutex<EpochTracker>,
+}
+
+impl NatPmpClient {
+    /// Create a client of the gateway at `gateway`, which usually is the default router on
+    /// port [`NATPMP_PORT`](crate::NATPMP_PORT).
+    pub fn new(gateway: SocketAddr) -> io::Result<NatPmpClient> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.connect(gateway)?;
+        Ok(NatPmpClient {
+            socket,
+            gateway,
+            max_attempts: natpmp::MAX_ATTEMPTS,
+            epoch: Mutex::new(EpochTracker::default()),
+        })
+    }
+
+    /// The address of the gateway.
+    pub fn gateway(&self) -> SocketAddr {
+        self.gateway
+    }
+
+    /// Set how many times a request is sent before failing with `NoResponse`. The delay
+    /// between attempts starts at 250ms and doubles each time, so the default of 9 attempts
+    /// waits about 2 minutes.
+    pub fn set_max_attempts(&mut self, attempts: u32) {
+        self.max_attempts = attempts.max(1);
+    }
+
+    /// Get the external address of the gateway.
+    pub fn external_address(&self) -> Result<NatPmpExternalAddress, NatPmpError> {
+        let request = natpmp::format_external_address_request();
+        let mut epoch = self.epoch.lock().unwrap();
+        let (address, response_epoch) = self.send(&request, natpmp::parse_external_address_response)?;
+        Ok(NatPmpExternalAddress {
+            address,
+            epoch: response_epoch,
+            gateway_restarted: epoch.observe(response_epoch, Instant::now()),
+        })
+    }
+
+    /// Map `internal_port` for the given lifetime, or renew the existing mapping of that port.
+    ///
+    /// The gateway may grant a different external port than the suggested one (0 lets the
+    /// gateway choose) and a shorter lifetime than requested.
+    pub fn add_mapping(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        suggested_external_port: u16,
+        lifetime: Duration,
+    ) -> Result<NatPmpMapping, NatPmpError> {
+        let lifetime = u32::try_from(lifetime.as_secs()).unwrap_or(u32::MAX).max(1);
+        let request = natpmp::format_mapping_request(protocol, internal_port, suggested_external_port, lifetime);
+        self.request_mapping(&request, protocol, internal_port)
+    }
+
+    /// Remove the mapping of `internal_port`.
+    pub fn remove_mapping(&self, protocol: PortMappingProtocol, internal_port: u16) -> Result<(), NatPmpError> {
+        let request = natpmp::format_mapping_request(protocol, internal_port, 0, 0);
+        self.request_mapping(&request, protocol, internal_port).map(|_| ())
+    }
+
+    /// Remove all mappings of this host for the given protocol.
+    pub fn remove_all_mappings(&self, protocol: PortMappingProtocol) -> Result<(), NatPmpError> {
+        self.remove_mapping(protocol, 0)
+    }
+
+    fn request_mapping(
+        &self,
+        request: &[u8],
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+    ) -> Result<NatPmpMapping, NatPmpError> {
+        let mut epoch = self.epoch.lock().unwrap();
+        let mut mapping = self.send(request, |packet| {
+            natpmp::parse_mapping_response(packet, protocol, internal_port)
+        })?;
+        mapping.gateway_restarted = epoch.observe(mapping.epoch, Instant::now());
+        Ok(mapping)
+    }
+
+    // Send the request following the retransmission schedule until a response is parsed.
+    fn send<T>(
+        &self,
+        request: &[u8],
+        parse: impl Fn(&[u8]) -> Result<Option<T>, NatPmpError>,
+    ) -> Result<T, NatPmpError> {
+        let mut timeout = natpmp::INITIAL_RETRANSMISSION_TIMEOUT;
+        let mut buf = [0u8; natpmp::MAX_RESPONSE_SIZE];
+        for _ in 0..self.max_attempts {
+            self.socket.send(request)?;
+            let deadline = Instant::now() + timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                self.socket.set_read_timeout(Some(remaining))?;
+                let read = match self.socket.recv(&mut buf) {
+                    Ok(read) => read,
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+                    Err(e) => return Err(e.into()),
+                };
+                match parse(&buf[..read])? {
+                    Some(response) => return Ok(response),
+                    None => debug!("ignoring unexpected NAT-PMP packet from {}", self.gateway),
+                }
+            }
+            timeout *= 2;
+        }
+        Err(NatPmpError::NoResponse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Answer each request like a NAT-PMP gateway whose epoch starts at `epochs[i]` for the i-th
+    // request, granting the suggested port (or 40000) and the requested lifetime.
+    fn stand_in(epochs: Vec<u32>) -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 12];
+            for epoch in epochs {
+                let (read, from) = socket.recv_from(&mut buf).unwrap();
+                let mut response = vec![0, 128 + buf[1], 0, 0];
+                response.extend_from_slice(&epoch.to_be_bytes());
+                if buf[1] == 0 {
+                    response.extend_from_slice(&[203, 0, 113, 7]);
+                } else {
+                    assert_eq!(read, 12);
+                    let external = match u16::from_be_bytes([buf[6], buf[7]]) {
+                        0 if buf[8..12] != [0; 4] => 40000,
+                        port => port,
+                    };
+                    response.extend_from_slice(&buf[4..6]);
+                    response.extend_from_slice(&external.to_be_bytes());
+                    response.extend_from_slice(&buf[8..12]);
+                }
+                socket.send_to(&response, from).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn requests_against_stand_in() {
+        let client = NatPmpClient::new(stand_in(vec![100, 101, 102, 3])).unwrap();
+
+        let external = client.external_address().unwrap();
+        assert_eq!(external.address, Ipv4Addr::new(203, 0, 113, 7));
+        assert!(!external.gateway_restarted);
+
+        let mapping = client
+            .add_mapping(PortMappingProtocol::UDP, 5000, 0, Duration::from_secs(7200))
+            .unwrap();
+        assert_eq!(mapping.internal_port, 5000);
+        assert_eq!(mapping.external_port, 40000);
+        assert_eq!(mapping.lifetime, Duration::from_secs(7200));
+
+        client.remove_mapping(PortMappingProtocol::UDP, 5000).unwrap();
+
+        let mapping = client
+            .add_mapping(PortMappingProtocol::TCP, 8080, 8080, Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(mapping.external_port, 8080);
+        assert!(mapping.gateway_restarted);
+    }
+
+    #[test]
+    fn no_response_after_retransmissions() {
+        let silent = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut client = NatPmpClient::new(silent.local_addr().unwrap()).unwrap();
+        client.set_max_attempts(2);
+        assert!(matches!(client.external_address(), Err(NatPmpError::NoResponse)));
+        let mut buf = [0u8; 12];
+        silent.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        for _ in 0..2 {
+            assert_eq!(silent.recv(&mut buf).unwrap(), 2);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::common::pcp::{self, EpochTracker, Nonce, PcpAnnounce, PcpMapOptions, PcpMapping, PcpMappingKind};
+use crate::errors::PcpError;
+use crate::PortMappingProtocol;
+
+/// A client of a PCP (RFC 6887) server.
+pub struct PcpClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    client_ip: IpAddr,
+    max_attempts: u32,
+    // Held for the whole exchange, so concurrent requests do not read each other's responses.
+    epoch: Mutex<EpochTracker>,
+}
+
+impl PcpClient {
+    /// Create a client of the server at `server`, which usually is the default router on
+    /// port [`PCP_PORT`](crate::PCP_PORT).
+    pub fn new(server: SocketAddr) -> io::Result<PcpClient> {
+        let unspecified = match server {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let socket = UdpSocket::bind((unspecified, 0))?;
+        socket.connect(server)?;
+        let client_ip = socket.local_addr()?.ip();
+        Ok(PcpClient {
+            socket,
+            server,
+            client_ip,
+            max_attempts: pcp::MAX_ATTEMPTS,
+            epoch: Mutex::new(EpochTracker::default()),
+        })
+    }
+
+    /// The address of the server.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Set how many times a request is sent before failing with `NoResponse`. The delay
+    /// between attempts starts at about 3 seconds and doubles each time.
+    pub fn set_max_attempts(&mut self, attempts: u32) {
+        self.max_attempts = attempts.max(1);
+    }
+
+    /// Check that the server is reachable and get its epoch.
+    pub fn announce(&self) -> Result<PcpAnnounce, PcpError> {
+        let request = pcp::format_announce_request(self.client_ip);
+        let mut epoch = self.epoch.lock().unwrap();
+        let response_epoch = self.send(&request, pcp::parse_announce_response)?;
+        Ok(PcpAnnounce {
+            epoch: response_epoch,
+            gateway_restarted: epoch.observe(response_epoch, Instant::now()),
+        })
+    }
+
+    /// Map `internal_port` on the server for inbound traffic, with the MAP opcode.
+    ///
+    /// The server may grant another external address or port than the suggested one, unless
+    /// `prefer_failure` is set, and a shorter lifetime than requested.
+    pub fn map(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        lifetime: Duration,
+        options: PcpMapOptions,
+    ) -> Result<PcpMapping, PcpError> {
+        let nonce = pcp::new_nonce();
+        let request = pcp::format_map_request(
+            self.client_ip,
+            &nonce,
+            protocol,
+            internal_port,
+            lifetime_secs(lifetime),
+            &options,
+        );
+        self.request_mapping(&request, PcpMappingKind::Map, &nonce, protocol, internal_port)
+    }
+
+    /// Create or learn the mapping used by the connection from `internal_port` to
+    /// `remote_peer`, with the PEER opcode.
+    pub fn peer(
+        &self,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+        remote_peer: SocketAddr,
+        lifetime: Duration,
+        suggested_external_addr: Option<SocketAddr>,
+    ) -> Result<PcpMapping, PcpError> {
+        let nonce = pcp::new_nonce();
+        let request = pcp::format_peer_request(
+            self.client_ip,
+            &nonce,
+            protocol,
+            internal_port,
+            remote_peer,
+            lifetime_secs(lifetime),
+            suggested_external_addr,
+        );
+        let kind = PcpMappingKind::Peer { remote_peer };
+        self.request_mapping(&request, kind, &nonce, protocol, internal_port)
+    }
+
+    /// Extend the lifetime of a mapping, which should be done after
+    /// [`PcpMapping::renew_after`]. It also re-creates the mapping if the server lost it.
+    pub fn renew(&self, mapping: &PcpMapping, lifetime: Duration) -> Result<PcpMapping, PcpError> {
+        self.refresh(mapping, lifetime_secs(lifetime))
+    }
+
+    /// Delete a mapping.
+    pub fn delete(&self, mapping: &PcpMapping) -> Result<(), PcpError> {
+        self.refresh(mapping, 0).map(|_| ())
+    }
+
+    fn refresh(&self, mapping: &PcpMapping, lifetime: u32) -> Result<PcpMapping, PcpError> {
+        // The current external address is suggested, so a server that lost the mapping
+        // re-creates the same one if it can.
+        let request = match mapping.kind {
+            PcpMappingKind::Map => pcp::format_map_request(
+                self.client_ip,
+                &mapping.nonce,
+                mapping.protocol,
+                mapping.internal_port,
+                lifetime,
+                &PcpMapOptions {
+                    suggested_external_addr: Some(mapping.external_addr),
+                    prefer_failure: false,
+                },
+            ),
+            PcpMappingKind::Peer { remote_peer } => pcp::format_peer_request(
+                self.client_ip,
+                &mapping.nonce,
+                mapping.protocol,
+                mapping.internal_port,
+                remote_peer,
+                lifetime,
+                Some(mapping.external_addr),
+            ),
+        };
+        self.request_mapping(
+            &request,
+            mapping.kind,
+            &mapping.nonce,
+            mapping.protocol,
+            mapping.internal_port,
+        )
+    }
+
+    fn request_mapping(
+        &self,
+        request: &[u8],
+        kind: PcpMappingKind,
+        nonce: &Nonce,
+        protocol: PortMappingProtocol,
+        internal_port: u16,
+    ) -> Result<PcpMapping, PcpError> {
+        let mut epoch = self.epoch.lock().unwrap();
+        let mut mapping = self.send(request, |packet| {
+            pcp::parse_mapping_response(packet, kind, nonce, protocol, internal_port)
+        })?;
+        mapping.gateway_restarted = epoch.observe(mapping.epoch, Instant::now());
+        Ok(mapping)
+    }
+
+    // Send the request following the retransmission schedule until a response is parsed.
+    fn send<T>(&self, request: &[u8], parse: impl Fn(&[u8]) -> Result<Option<T>, PcpError>) -> Result<T, PcpError> {
+        let mut timeout = None;
+        let mut buf = [0u8; pcp::MAX_MESSAGE_SIZE];
+        for _ in 0..self.max_attempts {
+            self.socket.send(request)?;
+            let attempt_timeout = pcp::retransmission_timeout(timeout);
+            timeout = Some(attempt_timeout);
+            let deadline = Instant::now() + attempt_timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                self.socket.set_read_timeout(Some(remaining))?;
+                let read = match self.socket.recv(&mut buf) {
+                    Ok(read) => read,
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+                    Err(e) => return Err(e.into()),
+                };
+                match parse(&buf[..read])? {
+                    Some(response) => return Ok(response),
+                    None => debug!("ignoring unexpected PCP packet from {}", self.server),
+                }
+            }
+        }
+        Err(PcpError::NoResponse)
+    }
+}
+
+fn lifetime_secs(lifetime: Duration) -> u32 {
+    u32::try_from(lifetime.as_secs()).unwrap_or(u32::MAX).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Answer MAP requests like a PCP server with external address 203.0.113.7, granting the
+    // suggested port unless it is 0. A response with another nonce is sent first, which the
+    // client must ignore. Requests with PREFER_FAILURE are refused with CANNOT_PROVIDE_EXTERNAL.
+    fn stand_in(requests: usize) -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 1100];
+            for _ in 0..requests {
+                let (read, from) = socket.recv_from(&mut buf).unwrap();
+                let request = &buf[..read];
+                assert_eq!(&request[..2], &[2, 1]);
+                assert_eq!(&request[20..24], &[127, 0, 0, 1]);
+                let prefer_failure = read > 60 && request[60] == 2;
+                let mut response = vec![2, 0x81, 0, if prefer_failure { 11 } else { 0 }];
+                response.extend_from_slice(&request[4..8]);
+                response.extend_from_slice(&77u32.to_be_bytes());
+                response.extend_from_slice(&[0; 12]);
+                response.extend_from_slice(&request[24..42]);
+                let port = match u16::from_be_bytes([request[42], request[43]]) {
+                    0 => 40000,
+                    port => port,
+                };
+                response.extend_from_slice(&port.to_be_bytes());
+                response.extend_from_slice(&Ipv4Addr::new(203, 0, 113, 7).to_ipv6_mapped().octets());
+
+                let mut other = response.clone();
+                other[24] ^= 0xff;
+                socket.send_to(&other, from).unwrap();
+                socket.send_to(&response, from).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn map_against_stand_in() {
+        let client = PcpClient::new(stand_in(4)).unwrap();
+
+        let mapping = client
+            .map(
+                PortMappingProtocol::UDP,
+                5000,
+                Duration::from_secs(7200),
+                PcpMapOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(mapping.external_addr, "203.0.113.7:40000".parse().unwrap());
+        assert_eq!(mapping.lifetime, Duration::from_secs(7200));
+        assert_eq!(mapping.epoch, 77);
+
+        let renewed = client.renew(&mapping, Duration::from_secs(3600)).unwrap();
+        assert_eq!(renewed.nonce, mapping.nonce);
+        assert_eq!(renewed.external_addr, mapping.external_addr);
+        assert_eq!(renewed.lifetime, Duration::from_secs(3600));
+
+        client.delete(&mapping).unwrap();
+
+        let options = PcpMapOptions {
+            suggested_external_addr: Some("203.0.113.7:80".parse().unwrap()),
+            prefer_failure: true,
+        };
+        assert!(matches!(
+            client.map(PortMappingProtocol::TCP, 80, Duration::from_secs(60), options),
+            Err(PcpError::CannotProvideExternal)
+        ));
+    }
+}
@@ -0,0 +1,273 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::common::port_mapper::{self, MappedPort, MappingMechanism, PortMapperOptions};
+use crate::common::{natpmp, pcp, SearchOptions};
+use crate::errors::PortMapperError;
+use crate::natpmp::NatPmpClient;
+use crate::pcp::PcpClient;
+use crate::{search_gateway, Gateway, PcpMapOptions, PortMappingProtocol};
+
+enum Backend {
+    Igd(Gateway),
+    Pcp(PcpClient),
+    NatPmp(NatPmpClient),
+}
+
+/// Maps ports with whichever of UPnP IGD, PCP and NAT-PMP the router supports.
+pub struct PortMapper {
+    backend: Backend,
+    description: String,
+}
+
+impl PortMapper {
+    /// Probe the mechanisms in the order of `options.mechanisms`, and use the first one
+    /// that responds.
+    pub fn new(options: PortMapperOptions) -> Result<PortMapper, PortMapperError> {
+        let mut prober = Prober {
+            bind_addr: options.search.bind_addr,
+            search: Some(options.search),
+            igd: None,
+            gateway: options.gateway,
+        };
+        for mechanism in &options.mechanisms {
+            if let Some(backend) = prober.probe(*mechanism) {
+                debug!("mapping ports with {mechanism}");
+                return Ok(PortMapper {
+                    backend,
+                    description: options.description,
+                });
+            }
+        }
+        Err(PortMapperError::NoMechanismAvailable)
+    }
+
+    /// The mechanism ports are mapped with.
+    pub fn mechanism(&self) -> MappingMechanism {
+        match self.backend {
+            Backend::Igd(_) => MappingMechanism::Igd,
+            Backend::Pcp(_) => MappingMechanism::Pcp,
+            Backend::NatPmp(_) => MappingMechanism::NatPmp,
+        }
+    }
+
+    /// Map an external port to `local_addr` for the given lease.
+    ///
+    /// The router picks the external port, trying to use the local port with PCP and NAT-PMP.
+    /// Those two only map ports to this host, so the IP of `local_addr` is only used by UPnP.
+    pub fn map(
+        &self,
+        protocol: PortMappingProtocol,
+        local_addr: SocketAddr,
+        lease: Duration,
+    ) -> Result<MappedPort, PortMapperError> {
+        let (external_addr, lease, pcp) = match &self.backend {
+            Backend::Igd(gateway) => {
+                let ip = gateway.get_external_ip()?;
+                let (port, granted) = gateway.add_any_port_with_lease(
+                    protocol,
+                    local_addr,
+                    port_mapper::igd_lease(lease),
+                    &self.description,
+                )?;
+                (SocketAddr::new(ip, port), granted.unwrap_or_default(), None)
+            }
+            Backend::Pcp(client) => {
+                let options = PcpMapOptions {
+                    suggested_external_addr: Some(SocketAddr::new(
+                        unspecified_like(client.server()),
+                        local_addr.port(),
+                    )),
+                    prefer_failure: false,
+                };
+                let mapping = client.map(protocol, local_addr.port(), lease, options)?;
+                (mapping.external_addr, mapping.lifetime, Some(mapping))
+            }
+            Backend::NatPmp(client) => {
+                let address = client.external_address()?.address;
+                let mapping = client.add_mapping(protocol, local_addr.port(), local_addr.port(), lease)?;
+                (
+                    SocketAddr::new(IpAddr::V4(address), mapping.external_port),
+                    mapping.lifetime,
+                    None,
+                )
+            }
+        };
+        Ok(MappedPort {
+            mechanism: self.mechanism(),
+            protocol,
+            local_addr,
+            external_addr,
+            lease,
+            pcp,
+        })
+    }
+
+    /// Remove a mapping made by [`map`](Self::map).
+    pub fn unmap(&self, mapping: &MappedPort) -> Result<(), PortMapperError> {
+        if mapping.mechanism != self.mechanism() {
+            return Err(PortMapperError::WrongMechanism(mapping.mechanism));
+        }
+        match &self.backend {
+            Backend::Igd(gateway) => gateway.remove_port(mapping.protocol, mapping.external_addr.port())?,
+            Backend::Pcp(client) => match &mapping.pcp {
+                Some(pcp) => client.delete(pcp)?,
+                None => return Err(PortMapperError::WrongMechanism(mapping.mechanism)),
+            },
+            Backend::NatPmp(client) => client.remove_mapping(mapping.protocol, mapping.local_addr.port())?,
+        }
+        Ok(())
+    }
+
+    /// Get the external address of the router.
+    ///
+    /// PCP has no request for it, so a short-lived mapping of a random UDP port is made and
+    /// removed right away.
+    pub fn external_address(&self) -> Result<IpAddr, PortMapperError> {
+        match &self.backend {
+            Backend::Igd(gateway) => Ok(gateway.get_external_ip()?),
+            Backend::Pcp(client) => {
+                let mapping = client.map(
+                    PortMappingProtocol::UDP,
+                    crate::common::random_port(),
+                    Duration::from_secs(1),
+                    PcpMapOptions::default(),
+                )?;
+                if let Err(e) = client.delete(&mapping) {
+                    debug!("could not delete the PCP mapping made to get the external address: {e}");
+                }
+                Ok(mapping.external_addr.ip())
+            }
+            Backend::NatPmp(client) => Ok(IpAddr::V4(client.external_address()?.address)),
+        }
+    }
+}
+
+fn unspecified_like(addr: SocketAddr) -> IpAddr {
+    match addr {
+        SocketAddr::V4(_) => IpAddr::from([0, 0, 0, 0]),
+        SocketAddr::V6(_) => IpAddr::from([0u16; 8]),
+    }
+}
+
+struct Prober {
+    bind_addr: SocketAddr,
+    search: Option<SearchOptions>,
+    igd: Option<Gateway>,
+    gateway: Option<SocketAddr>,
+}
+
+impl Prober {
+    // Search for the UPnP gateway, once.
+    fn search(&mut self) -> Option<&Gateway> {
+        if let Some(options) = self.search.take() {
+            match search_gateway(options) {
+                Ok(gateway) => self.igd = Some(gateway),
+                Err(e) => debug!("no UPnP gateway found: {e}"),
+            }
+        }
+        self.igd.as_ref()
+    }
+
+    fn router(&mut self) -> Option<SocketAddr> {
+        if self.gateway.is_none() {
+            let router = match self.search() {
+                Some(gateway) => Some(gateway.addr.ip()),
+                None => port_mapper::default_router(self.bind_addr),
+            };
+            self.gateway = router.map(|ip| SocketAddr::new(ip, port_mapper::SERVER_PORT));
+            debug!("sending PCP and NAT-PMP requests to {:?}", self.gateway);
+        }
+        self.gateway
+    }
+
+    fn probe(&mut self, mechanism: MappingMechanism) -> Option<Backend> {
+        match mechanism {
+            MappingMechanism::Igd => self.search().cloned().map(Backend::Igd),
+            MappingMechanism::Pcp => {
+                let mut client = PcpClient::new(self.router()?).ok()?;
+                client.set_max_attempts(port_mapper::PCP_PROBE_ATTEMPTS);
+                if let Err(e) = client.announce() {
+                    debug!("no PCP server found: {e}");
+                    return None;
+                }
+                client.set_max_attempts(pcp::MAX_ATTEMPTS);
+                Some(Backend::Pcp(client))
+            }
+            MappingMechanism::NatPmp => {
+                let router = match self.router()? {
+                    router @ SocketAddr::V4(_) => router,
+                    SocketAddr::V6(_) => return None,
+                };
+                let mut client = NatPmpClient::new(router).ok()?;
+                client.set_max_attempts(port_mapper::NATPMP_PROBE_ATTEMPTS);
+                if let Err(e) = client.external_address() {
+                    debug!("no NAT-PMP gateway found: {e}");
+                    return None;
+                }
+                client.set_max_attempts(natpmp::MAX_ATTEMPTS);
+                Some(Backend::NatPmp(client))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, UdpSocket};
+    use std::thread;
+
+    // Answer external address and mapping requests like a NAT-PMP gateway, until `requests`
+    // were answered.
+    fn natpmp_stand_in(socket: UdpSocket, requests: usize) {
+        thread::spawn(move || {
+            let mut buf = [0u8; 12];
+            for _ in 0..requests {
+                let (_, from) = socket.recv_from(&mut buf).unwrap();
+                let mut response = vec![0, 128 + buf[1], 0, 0, 0, 0, 0, 1];
+                if buf[1] == 0 {
+                    response.extend_from_slice(&[203, 0, 113, 7]);
+                } else {
+                    response.extend_from_slice(&buf[4..6]);
+                    response.extend_from_slice(&buf[4..6]);
+                    response.extend_from_slice(&buf[8..12]);
+                }
+                socket.send_to(&response, from).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn natpmp_is_used_without_upnp() {
+        let gateway = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let gateway_addr = gateway.local_addr().unwrap();
+        natpmp_stand_in(gateway, 3);
+        let silent = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let mapper = PortMapper::new(PortMapperOptions {
+            search: SearchOptions {
+                bind_addr: (Ipv4Addr::LOCALHOST, 0).into(),
+                broadcast_address: silent.local_addr().unwrap(),
+                timeout: Some(Duration::from_millis(500)),
+                single_search_timeout: Some(Duration::from_millis(500)),
+            },
+            gateway: Some(gateway_addr),
+            mechanisms: vec![MappingMechanism::Igd, MappingMechanism::NatPmp],
+            description: "test".to_string(),
+        })
+        .unwrap();
+        assert_eq!(mapper.mechanism(), MappingMechanism::NatPmp);
+
+        let mapped = mapper
+            .map(
+                PortMappingProtocol::UDP,
+                "127.0.0.1:5000".parse().unwrap(),
+                Duration::from_secs(60),
+            )
+            .unwrap();
+        assert_eq!(mapped.external_addr, "203.0.113.7:5000".parse().unwrap());
+    }
+}
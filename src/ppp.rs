@@ -0,0 +1,98 @@
+use crate::common::messages;
+use crate::common::parsing::RequestResult;
+use crate::common::ppp::{self, LinkLayerMaxBitRates};
+use crate::errors::RequestError;
+use crate::Gateway;
+
+impl Gateway {
+    fn perform_ppp_request(&self, action: &str, body: &str) -> RequestResult {
+        ppp::check_ppp_service(&self.service_type, action)?;
+        self.perform_service_request(
+            &self.control_url,
+            &self.service_type,
+            action,
+            body,
+            &format!("{action}Response"),
+        )
+    }
+
+    /// Get the maximum upstream and downstream bit rates of the PPP link.
+    ///
+    /// Only supported when `service_type` is a WANPPPConnection service.
+    pub fn get_link_layer_max_bit_rates(&self) -> Result<LinkLayerMaxBitRates, RequestError> {
+        ppp::parse_get_link_layer_max_bit_rates_response(self.perform_ppp_request(
+            ppp::GET_LINK_LAYER_MAX_BIT_RATES_ACTION,
+            &messages::format_action_message(&self.service_type, ppp::GET_LINK_LAYER_MAX_BIT_RATES_ACTION, &[]),
+        ))
+    }
+
+    /// Get the user name used to authenticate the PPP connection.
+    ///
+    /// Only supported when `service_type` is a WANPPPConnection service.
+    pub fn get_user_name(&self) -> Result<String, RequestError> {
+        ppp::parse_string_response(
+            self.perform_ppp_request(
+                ppp::GET_USER_NAME_ACTION,
+                &messages::format_action_message(&self.service_type, ppp::GET_USER_NAME_ACTION, &[]),
+            ),
+            "NewUserName",
+        )
+    }
+
+    /// Get the encryption protocol of the PPP connection.
+    ///
+    /// Only supported when `service_type` is a WANPPPConnection service.
+    pub fn get_ppp_encryption_protocol(&self) -> Result<String, RequestError> {
+        ppp::parse_string_response(
+            self.perform_ppp_request(
+                ppp::GET_PPP_ENCRYPTION_PROTOCOL_ACTION,
+                &messages::format_action_message(&self.service_type, ppp::GET_PPP_ENCRYPTION_PROTOCOL_ACTION, &[]),
+            ),
+            "NewPPPEncryptionProtocol",
+        )
+    }
+
+    /// Get the compression protocol of the PPP connection.
+    ///
+    /// Only supported when `service_type` is a WANPPPConnection service.
+    pub fn get_ppp_compression_protocol(&self) -> Result<String, RequestError> {
+        ppp::parse_string_response(
+            self.perform_ppp_request(
+                ppp::GET_PPP_COMPRESSION_PROTOCOL_ACTION,
+                &messages::format_action_message(&self.service_type, ppp::GET_PPP_COMPRESSION_PROTOCOL_ACTION, &[]),
+            ),
+            "NewPPPCompressionProtocol",
+        )
+    }
+
+    /// Get the authentication protocol of the PPP connection (e.g. `PAP` or `CHAP`).
+    ///
+    /// Only supported when `service_type` is a WANPPPConnection service.
+    pub fn get_ppp_authentication_protocol(&self) -> Result<String, RequestError> {
+        ppp::parse_string_response(
+            self.perform_ppp_request(
+                ppp::GET_PPP_AUTHENTICATION_PROTOCOL_ACTION,
+                &messages::format_action_message(&self.service_type, ppp::GET_PPP_AUTHENTICATION_PROTOCOL_ACTION, &[]),
+            ),
+            "NewPPPAuthenticationProtocol",
+        )
+    }
+
+    /// Set the user name and password used to authenticate the PPP connection.
+    ///
+    /// Only supported when `service_type` is a WANPPPConnection service.
+    pub fn configure_connection(&self, user_name: &str, password: &str) -> Result<(), RequestError> {
+        self.perform_ppp_request(
+            ppp::CONFIGURE_CONNECTION_ACTION,
+            &ppp::format_configure_connection_message(
+                &self.service_type,
+                self.control_schema
+                    .get(ppp::CONFIGURE_CONNECTION_ACTION)
+                    .map(Vec::as_slice),
+                user_name,
+                password,
+            ),
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+
+use crate::common;
+use crate::common::batch::BatchReport;
+use crate::common::manager::MappingSpec;
+use crate::common::parsing::PortMappingEntry;
+use crate::common::reconcile::{self, ReconcilePlan};
+use crate::errors::GetGenericPortMappingEntryError;
+use crate::Gateway;
+
+impl Gateway {
+    /// Get all port mapping entries visible to this client, by index.
+    pub fn get_port_mapping_entries(&self) -> Result<Vec<PortMappingEntry>, GetGenericPortMappingEntryError> {
+        let mut entries = Vec::new();
+        for index in 0..reconcile::MAX_PORT_MAPPING_ENTRIES {
+            match self.get_generic_port_mapping_entry(index) {
+                Ok(entry) => entries.push(entry),
+                Err(e) if reconcile::is_end_of_entries(&e) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Compare the gateway's port mappings with the desired ones, and plan the changes that
+    /// make them match.
+    ///
+    /// Only the mappings that `owns` accepts, and that go to this host's address on the
+    /// gateway's network or to an address of a desired mapping, are ours. The plan changes or
+    /// removes only those, so that the mappings of other applications on this host survive;
+    /// `owns` usually checks the description, e.g.
+    /// `|entry| entry.port_mapping_description.starts_with("my-app")`. The plan is applied
+    /// with [`apply_reconcile_plan`](Self::apply_reconcile_plan), and can be inspected first.
+    pub fn reconcile(
+        &self,
+        desired: &[MappingSpec],
+        owns: impl Fn(&PortMappingEntry) -> bool,
+    ) -> Result<ReconcilePlan, GetGenericPortMappingEntryError> {
+        let entries = self.get_port_mapping_entries()?;
+        let mut own_addresses: HashSet<_> = desired.iter().map(|mapping| mapping.local_addr.ip()).collect();
+        own_addresses.extend(common::local_ip_for(self.addr).ok());
+        Ok(reconcile::plan(desired, &entries, &own_addresses, owns))
+    }
+
+    /// Apply a plan made by [`reconcile`](Self::reconcile), as a batch of its removes
+    /// followed by its adds.
+    pub fn apply_reconcile_plan(&self, plan: &ReconcilePlan) -> BatchReport {
+        self.run_batch(&plan.operations())
+    }
+}
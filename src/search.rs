@@ -7,7 +7,7 @@ use attohttpc::{Method, RequestBuilder};
 use log::debug;
 
 use crate::common::options::{DEFAULT_TIMEOUT, MAX_RESPONSE_BYTES, RESPONSE_TIMEOUT};
-use crate::common::{self, messages, parsing, SearchOptions};
+use crate::common::{self, messages, parsing, parsing::ServiceDescription, SearchOptions};
 use crate::errors::SearchError;
 use crate::gateway::Gateway;
 
@@ -71,52 +71,98 @@ pub fn search_gateway(options: SearchOptions) -> Result<Gateway, SearchError> {
             }
         };
 
-        let (service_type, control_schema_url, control_url) =
-            match get_control_urls(&addr, &root_url, max_time.saturating_sub(start.elapsed())) {
-                Ok(o) => o,
-                Err(e) => {
-                    debug!(
-                        "Error has occurred while getting control urls. error: {}, addr: {}, root_url: {}",
-                        e, addr, root_url
-                    );
-                    continue;
-                }
-            };
-
-        let control_schema = match get_schemas(&addr, &control_schema_url, max_time.saturating_sub(start.elapsed())) {
+        let services = match get_services(&addr, &root_url, max_time.saturating_sub(start.elapsed())) {
             Ok(o) => o,
             Err(e) => {
                 debug!(
-                    "Error has occurred while getting schemas. error: {}, addr: {}, control_schema_url: {}",
-                    e, addr, control_schema_url
+                    "Error has occurred while getting control urls. error: {}, addr: {}, root_url: {}",
+                    e, addr, root_url
+                );
+                continue;
+            }
+        };
+
+        let service = match services.iter().find(|service| service.is_wan_connection()) {
+            Some(service) => service.clone(),
+            None => {
+                debug!(
+                    "No WAN connection service found. addr: {}, root_url: {}",
+                    addr, root_url
                 );
                 continue;
             }
         };
 
-        return Ok(Gateway {
+        let mut gateway = Gateway {
             addr,
             root_url,
-            control_url,
-            control_schema_url,
-            control_schema,
-            service_type,
-        });
+            control_url: service.control_url,
+            control_schema_url: service.scpd_url,
+            control_schema: HashMap::new(),
+            service_type: service.service_type,
+            services,
+            bind_addr: common::control_bind_addr(options.bind_addr, addr),
+        };
+        use_default_connection_service(&mut gateway, max_time.saturating_sub(start.elapsed()));
+
+        gateway.control_schema = match get_schemas(
+            &gateway.addr,
+            &gateway.control_schema_url,
+            max_time.saturating_sub(start.elapsed()),
+        ) {
+            Ok(o) => o,
+            Err(e) => {
+                debug!(
+                    "Error has occurred while getting schemas. error: {}, addr: {}, control_schema_url: {}",
+                    e, gateway.addr, gateway.control_schema_url
+                );
+                continue;
+            }
+        };
+
+        return Ok(gateway);
     }
 
     Err(SearchError::NoResponseWithinTimeout)
 }
 
-fn get_control_urls(
-    addr: &SocketAddr,
-    root_url: &str,
-    timeout: Duration,
-) -> Result<(String, String, String), SearchError> {
+/// Switch the gateway to the WAN connection service advertised by the root device's
+/// Layer3Forwarding service, keeping the first one in document order if there is none or if
+/// the gateway does not answer within `timeout`, the time left to the search.
+fn use_default_connection_service(gateway: &mut Gateway, timeout: Duration) {
+    if timeout.is_zero()
+        || !gateway
+            .services
+            .iter()
+            .any(|service| service.service_type == parsing::LAYER3_FORWARDING_SERVICE)
+    {
+        return;
+    }
+    let default_connection_service = gateway.get_default_connection_service_within(timeout);
+    let service = match default_connection_service
+        .map(|default| parsing::find_default_connection_service(&gateway.services, &default).cloned())
+    {
+        Ok(Some(service)) => service,
+        Ok(None) => {
+            debug!("default connection service is not one of the advertised services");
+            return;
+        }
+        Err(e) => {
+            debug!("could not get the default connection service: {e}");
+            return;
+        }
+    };
+    gateway.control_url = service.control_url;
+    gateway.control_schema_url = service.scpd_url;
+    gateway.service_type = service.service_type;
+}
+
+fn get_services(addr: &SocketAddr, root_url: &str, timeout: Duration) -> Result<Vec<ServiceDescription>, SearchError> {
     let url = format!("http://{}:{}{}", addr.ip(), addr.port(), root_url);
     match RequestBuilder::try_new(Method::GET, url) {
         Ok(request_builder) => {
             let response = request_builder.timeout(timeout).send()?;
-            parsing::parse_control_urls(&common::read_response_body(response, MAX_RESPONSE_BYTES)?[..])
+            parsing::parse_services(&common::read_response_body(response, MAX_RESPONSE_BYTES)?[..])
         }
         Err(error) => Err(SearchError::HttpError(error)),
     }
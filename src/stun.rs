@@ -0,0 +1,130 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use log::debug;
+
+use crate::common::stun::{self, ExternalIpCheck};
+use crate::errors::StunError;
+
+/// A client of a STUN (RFC 5389) server, which tells the address requests come from as seen
+/// from the internet.
+pub struct StunClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    max_attempts: u32,
+    // Held for the whole exchange, so concurrent requests do not read each other's responses.
+    exchange: Mutex<()>,
+}
+
+impl StunClient {
+    /// Create a client of the STUN server at `server`, which usually listens on port
+    /// [`STUN_PORT`](crate::STUN_PORT).
+    pub fn new(server: SocketAddr) -> io::Result<StunClient> {
+        let unspecified = match server {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let socket = UdpSocket::bind((unspecified, 0))?;
+        socket.connect(server)?;
+        Ok(StunClient {
+            socket,
+            server,
+            max_attempts: stun::MAX_ATTEMPTS,
+            exchange: Mutex::new(()),
+        })
+    }
+
+    /// The address of the server.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Set how many times a request is sent before failing with `NoResponse`. The delay
+    /// between attempts starts at 500ms and doubles each time, and the last request is waited
+    /// for 8 seconds, so the default of 7 attempts waits 39.5 seconds.
+    pub fn set_max_attempts(&mut self, attempts: u32) {
+        self.max_attempts = attempts.max(1);
+    }
+
+    /// Get the address and port the server received a binding request from.
+    pub fn reflexive_address(&self) -> Result<SocketAddr, StunError> {
+        let transaction_id = stun::transaction_id();
+        let request = stun::format_binding_request(transaction_id);
+        let _exchange = self.exchange.lock().unwrap();
+        let mut buf = [0u8; stun::MAX_RESPONSE_SIZE];
+        for attempt in 0..self.max_attempts {
+            self.socket.send(&request)?;
+            let deadline = Instant::now() + stun::response_wait(attempt, self.max_attempts);
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                self.socket.set_read_timeout(Some(remaining))?;
+                let read = match self.socket.recv(&mut buf) {
+                    Ok(read) => read,
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+                    Err(e) => return Err(e.into()),
+                };
+                match stun::parse_binding_response(&buf[..read], transaction_id)? {
+                    Some(address) => return Ok(address),
+                    None => debug!("ignoring unexpected STUN packet from {}", self.server),
+                }
+            }
+        }
+        Err(StunError::NoResponse)
+    }
+
+    /// Compare the external address a gateway reports, from
+    /// [`Gateway::get_external_ip`](crate::Gateway::get_external_ip), with the reflexive
+    /// address. A mismatch hints at a double NAT.
+    pub fn check_external_ip(&self, gateway_ip: IpAddr) -> Result<ExternalIpCheck, StunError> {
+        Ok(ExternalIpCheck {
+            gateway_ip,
+            reflexive_addr: self.reflexive_address()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Answer one binding request like a STUN server, with the XOR-MAPPED-ADDRESS of the client.
+    fn stand_in() -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 20];
+            let (read, from) = socket.recv_from(&mut buf).unwrap();
+            assert_eq!(read, 20);
+            assert_eq!(buf[..2], [0, 1]);
+            let SocketAddr::V4(from_v4) = from else {
+                panic!("IPv4 client expected");
+            };
+            let mut response = vec![0x01, 0x01, 0, 12];
+            response.extend_from_slice(&buf[4..20]);
+            response.extend_from_slice(&[0, 0x20, 0, 8, 0, 1]);
+            response.extend_from_slice(&(from.port() ^ 0x2112).to_be_bytes());
+            let octets = from_v4.ip().octets();
+            response.extend((0..4).map(|i| octets[i] ^ buf[4 + i]));
+            socket.send_to(&response, from).unwrap();
+        });
+        addr
+    }
+
+    #[test]
+    fn reflexive_address_from_stand_in() {
+        let client = StunClient::new(stand_in()).unwrap();
+        let local = client.socket.local_addr().unwrap();
+        let check = client.check_external_ip(IpAddr::from([203, 0, 113, 7])).unwrap();
+        assert_eq!(
+            check.reflexive_addr,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), local.port())
+        );
+        assert!(check.is_mismatch());
+    }
+}
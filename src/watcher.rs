@@ -0,0 +1,224 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::common::messages;
+use crate::common::watcher::{self, AddressTracker, ExternalIpChange, PollInterval};
+use crate::common::WatchOptions;
+use crate::errors::GetExternalIpError;
+use crate::gena::{EventListener, Subscription};
+use crate::Gateway;
+
+/// How often the watcher checks whether it was dropped while it waits for events.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches the external IP address of a gateway on a background thread, and delivers its
+/// changes as `(old, new)`.
+///
+/// Dropping the watcher stops the thread and cancels its event subscription.
+pub struct ExternalIpWatcher {
+    initial_address: Option<IpAddr>,
+    uses_events: bool,
+    changes: Receiver<ExternalIpChange>,
+    _stop: Sender<()>,
+}
+
+impl ExternalIpWatcher {
+    /// The external address when the watcher started, `None` if the gateway was disconnected.
+    pub fn initial_address(&self) -> Option<IpAddr> {
+        self.initial_address
+    }
+
+    /// Whether changes are received as GENA events rather than only by polling.
+    pub fn uses_events(&self) -> bool {
+        self.uses_events
+    }
+
+    /// Block until the next change.
+    pub fn recv(&self) -> Result<ExternalIpChange, RecvError> {
+        self.changes.recv()
+    }
+
+    /// Block until the next change, or the timeout elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ExternalIpChange, RecvTimeoutError> {
+        self.changes.recv_timeout(timeout)
+    }
+
+    /// Get the next change if one was already observed.
+    pub fn try_recv(&self) -> Result<ExternalIpChange, TryRecvError> {
+        self.changes.try_recv()
+    }
+}
+
+impl Gateway {
+    /// Get the external IP address, or `None` if the gateway reports an empty or unspecified
+    /// address because its WAN connection is down.
+    pub fn get_external_ip_state(&self) -> Result<Option<IpAddr>, GetExternalIpError> {
+        watcher::parse_external_ip_state(self.perform_service_request(
+            &self.control_url,
+            &self.service_type,
+            messages::GET_EXTERNAL_IP_ACTION,
+            &messages::format_get_external_ip_message(&self.service_type),
+            "GetExternalIPAddressResponse",
+        ))
+    }
+
+    /// Watch the external IP address for changes.
+    ///
+    /// The watcher subscribes to `ExternalIPAddress` events if the gateway supports them, and
+    /// otherwise polls the address, less often while it stays the same.
+    pub fn watch_external_ip(&self, options: WatchOptions) -> Result<ExternalIpWatcher, GetExternalIpError> {
+        let initial_address = self.get_external_ip_state()?;
+        let events = if options.use_events {
+            subscribe(self, options.listen_addr, options.subscription_timeout)
+        } else {
+            None
+        };
+        let uses_events = events.is_some();
+
+        let (sender, changes) = mpsc::channel();
+        let (stop, stopped) = mpsc::channel();
+        let gateway = self.clone();
+        thread::Builder::new()
+            .name("igd-external-ip-watcher".to_string())
+            .spawn(move || watch(gateway, options, initial_address, events, sender, stopped))?;
+        Ok(ExternalIpWatcher {
+            initial_address,
+            uses_events,
+            changes,
+            _stop: stop,
+        })
+    }
+}
+
+fn subscribe(gateway: &Gateway, listen_addr: SocketAddr, timeout: Duration) -> Option<(EventListener, Subscription)> {
+    let listener = match EventListener::bind(listen_addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            debug!("could not listen for events, polling the external IP address: {e}");
+            return None;
+        }
+    };
+    match gateway.subscribe(&listener, timeout) {
+        Ok(subscription) => Some((listener, subscription)),
+        Err(e) => {
+            debug!("could not subscribe to events, polling the external IP address: {e}");
+            None
+        }
+    }
+}
+
+/// What woke up the watcher thread.
+enum Wake {
+    /// An event reported the external address.
+    Event(Option<IpAddr>),
+    /// Nothing happened before the wait timed out.
+    Timeout,
+    /// The watcher was dropped.
+    Stop,
+}
+
+fn wait(events: &Option<(EventListener, Subscription)>, stopped: &Receiver<()>, timeout: Duration) -> Wake {
+    let (listener, subscription) = match events {
+        Some((listener, subscription)) => (listener, subscription),
+        None => {
+            return match stopped.recv_timeout(timeout) {
+                Err(RecvTimeoutError::Timeout) => Wake::Timeout,
+                _ => Wake::Stop,
+            }
+        }
+    };
+    if let Err(TryRecvError::Disconnected) = stopped.try_recv() {
+        return Wake::Stop;
+    }
+    recv_event(listener, &subscription.sid(), timeout.min(STOP_CHECK_INTERVAL))
+}
+
+fn recv_event(listener: &EventListener, sid: &str, timeout: Duration) -> Wake {
+    match listener.recv_timeout(timeout) {
+        Ok(notification) => match watcher::event_address(&notification, sid) {
+            Some(address) => Wake::Event(address),
+            None => Wake::Timeout,
+        },
+        Err(RecvTimeoutError::Timeout) => Wake::Timeout,
+        Err(RecvTimeoutError::Disconnected) => Wake::Stop,
+    }
+}
+
+fn watch(
+    gateway: Gateway,
+    options: WatchOptions,
+    initial_address: Option<IpAddr>,
+    events: Option<(EventListener, Subscription)>,
+    sender: Sender<ExternalIpChange>,
+    stopped: Receiver<()>,
+) {
+    let mut tracker = AddressTracker::new(initial_address);
+    // Events make polling a fallback for missed events, so it stays at the longest interval.
+    let min_poll_interval = match events {
+        Some(_) => options.max_poll_interval,
+        None => options.min_poll_interval,
+    };
+    let mut interval = PollInterval::new(min_poll_interval, options.max_poll_interval);
+    let mut next_poll = Instant::now() + interval.get();
+    loop {
+        let observed = match wait(&events, &stopped, next_poll.saturating_duration_since(Instant::now())) {
+            Wake::Stop => return,
+            Wake::Event(address) => address,
+            Wake::Timeout if Instant::now() < next_poll => continue,
+            Wake::Timeout => match gateway.get_external_ip_state() {
+                Ok(address) => address,
+                Err(e) => {
+                    debug!("could not poll the external IP address: {e}");
+                    next_poll = Instant::now() + interval.get();
+                    continue;
+                }
+            },
+        };
+        let change = tracker.update(observed);
+        interval.observed(change.is_some());
+        next_poll = Instant::now() + interval.get();
+        if let Some(change) = change {
+            if sender.send(change).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::{Ipv4Addr, TcpStream};
+
+    fn notify(listener: &EventListener, sid: &str) {
+        let body = "<e:propertyset xmlns:e=\"urn:schemas-upnp-org:event-1-0\"><e:property>\
+                    <ExternalIPAddress>198.51.100.66</ExternalIPAddress></e:property></e:propertyset>";
+        let request = format!(
+            "NOTIFY / HTTP/1.1\r\nNT: upnp:event\r\nNTS: upnp:propchange\r\nSID: {sid}\r\nSEQ: 0\r\n\
+             Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let mut stream = TcpStream::connect(listener.local_addr()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn events_with_another_sid_are_dropped() {
+        let listener = EventListener::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let timeout = Duration::from_secs(5);
+
+        notify(&listener, "uuid:forged");
+        assert!(matches!(recv_event(&listener, "uuid:ours", timeout), Wake::Timeout));
+
+        notify(&listener, "uuid:ours");
+        assert!(matches!(
+            recv_event(&listener, "uuid:ours", timeout),
+            Wake::Event(Some(ip)) if ip == IpAddr::from([198, 51, 100, 66])
+        ));
+    }
+}